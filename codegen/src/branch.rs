@@ -12,13 +12,16 @@ use std::collections::HashMap;
 pub struct BranchBlock {
     points: Vec<(usize, Token, Option<EntityKey>)>,
     label: Option<EntityKey>,
+    // for and range are loops, switch and select are not
+    is_loop: bool,
 }
 
 impl BranchBlock {
-    pub fn new(label: Option<EntityKey>) -> BranchBlock {
+    pub fn new(label: Option<EntityKey>, is_loop: bool) -> BranchBlock {
         BranchBlock {
             points: vec![],
             label: label,
+            is_loop: is_loop,
         }
     }
 }
@@ -69,26 +72,42 @@ impl BranchHelper {
         func.emit_code_with_imm(Opcode::JUMP, offset, Some(pos));
     }
 
-    pub fn enter_block(&mut self) {
+    pub fn enter_block(&mut self, is_loop: bool) {
         self.block_stack
-            .push(BranchBlock::new(self.next_block_label.take()))
+            .push(BranchBlock::new(self.next_block_label.take(), is_loop))
     }
 
     pub fn leave_block(&mut self, func: &mut FunctionVal, begin: Option<usize>) {
         let end = func.next_code_index();
+        self.leave_block_with_end(func, begin, end)
+    }
+
+    /// like leave_block, but break jumps to `end` instead of past the block
+    pub fn leave_block_with_end(
+        &mut self,
+        func: &mut FunctionVal,
+        begin: Option<usize>,
+        end: usize,
+    ) {
         let block = self.block_stack.pop().unwrap();
         for (index, token, label) in block.points.into_iter() {
-            let current_pc = index as OpIndex + 1;
-            let target = if token == Token::BREAK {
-                end
-            } else {
-                begin.unwrap()
+            // without a label, break ends the innermost for, switch or select,
+            // while continue goes on with the innermost loop
+            let is_target = match label {
+                Some(_) => label == block.label,
+                None => token == Token::BREAK || block.is_loop,
             };
-            if label == block.label {
+            if is_target {
+                let current_pc = index as OpIndex + 1;
+                let target = if token == Token::BREAK {
+                    end
+                } else {
+                    begin.unwrap()
+                };
                 func.instruction_mut(index)
                     .set_imm(target as OpIndex - current_pc);
             } else {
-                // this break/continue tries to jump out of an outer loop
+                // this break/continue tries to jump out of an outer block
                 // so we add it to outer block's jump out points
                 self.block_stack
                     .last_mut()
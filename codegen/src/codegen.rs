@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::iter::FromIterator;
 use std::rc::Rc;
@@ -8,6 +8,7 @@ use super::call::CallHelper;
 use super::emit::*;
 use super::interface::IfaceMapping;
 use super::package::PkgHelper;
+use super::param::SharedParams;
 use super::types::{TypeCache, TypeLookup};
 
 use goscript_vm::gc::GcoVec;
@@ -66,6 +67,8 @@ pub struct CodeGen<'a> {
     pkg_key: PackageKey,
     func_stack: Vec<FunctionKey>,
     func_t_stack: Vec<TCTypeKey>, // for casting return values to interfaces
+    init_funcs: Vec<FunctionKey>, // called by the package constructor
+    func_lit_counts: HashMap<FunctionKey, usize>, // for naming the function literals
     blank_ident: IdentKey,
 }
 
@@ -98,6 +101,8 @@ impl<'a> CodeGen<'a> {
             pkg_key: pkg,
             func_stack: Vec::new(),
             func_t_stack: Vec::new(),
+            init_funcs: Vec::new(),
+            func_lit_counts: HashMap::new(),
             blank_ident: bk,
         }
     }
@@ -216,12 +221,13 @@ impl<'a> CodeGen<'a> {
         self.gen_assign_def_var(&lhs, &vs.typ, &rhs);
     }
 
-    fn gen_def_const(&mut self, names: &Vec<IdentKey>, values: &Vec<Expr>) {
-        assert!(names.len() == values.len());
-        for i in 0..names.len() {
-            let ident = self.ast_objs.idents[names[i]].clone();
-            let val = self.tlookup.get_const_value(values[i].id());
-            self.current_func_add_const_def(&ident, val);
+    fn gen_def_const(&mut self, names: &Vec<IdentKey>) {
+        for name in names.iter() {
+            // blank identifiers are not defined
+            if let Some(val) = self.tlookup.get_def_const_value(*name) {
+                let ident = self.ast_objs.idents[*name].clone();
+                self.current_func_add_const_def(&ident, val);
+            }
         }
     }
 
@@ -263,17 +269,14 @@ impl<'a> CodeGen<'a> {
                         let pos = ind_expr.as_ref().l_brack;
 
                         let mut index_const = None;
-                        let mut index_typ = None;
-                        if let Some(const_val) = self.tlookup.get_tc_const_value(ind.id()) {
-                            let (ival, _) = const_val.to_int().int_as_i64();
-                            if let Ok(i) = OpIndex::try_from(ival) {
-                                index_const = Some(i);
+                        let mut index_typ = self.try_gen_iface_map_key(obj, ind);
+                        if index_typ.is_none() {
+                            index_const = self.try_index_imm(ind);
+                            if index_const.is_none() {
+                                self.visit_expr(ind);
+                                index_typ = Some(self.tlookup.get_expr_value_type(ind));
                             }
                         }
-                        if index_const.is_none() {
-                            self.visit_expr(ind);
-                            index_typ = Some(self.tlookup.get_expr_value_type(ind));
-                        }
                         (
                             LeftHandSide::IndexSelExpr(IndexSelInfo::new(
                                 0,
@@ -544,7 +547,16 @@ impl<'a> CodeGen<'a> {
             let pos = Some(*p);
             match l {
                 LeftHandSide::Primitive(_) => {
-                    current_func_emitter!(self).emit_store(l, rhs_index, None, None, typ, pos);
+                    let mut emitter = current_func_emitter!(self);
+                    let fkey = self.func_stack.last().unwrap();
+                    emitter.emit_store(
+                        l,
+                        rhs_index,
+                        None,
+                        Some((self.pkg_helper.pairs_mut(), *fkey)),
+                        typ,
+                        pos,
+                    );
                 }
                 LeftHandSide::IndexSelExpr(info) => {
                     current_func_emitter!(self).emit_store(
@@ -555,8 +567,9 @@ impl<'a> CodeGen<'a> {
                         typ,
                         pos,
                     );
-                    // the lhs of IndexSelExpr takes two spots
-                    current_indexing_deref_index += 2;
+                    // the lhs of IndexSelExpr takes one spot if the index is a constant,
+                    // or two if it's on the stack
+                    current_indexing_deref_index += info.stack_space();
                 }
                 LeftHandSide::Deref(_) => {
                     current_func_emitter!(self).emit_store(
@@ -650,7 +663,13 @@ impl<'a> CodeGen<'a> {
         }
     }
 
-    fn gen_switch_body(&mut self, body: &BlockStmt, tag_type: ValueType) {
+    /// binding is the local of the variable declared by a type switch, if any
+    fn gen_switch_body(
+        &mut self,
+        body: &BlockStmt,
+        tag_type: ValueType,
+        binding: Option<EntIndex>,
+    ) {
         let mut helper = SwitchHelper::new();
         let mut has_default = false;
         for (i, stmt) in body.list.iter().enumerate() {
@@ -669,10 +688,14 @@ impl<'a> CodeGen<'a> {
                 None => has_default = true,
             }
         }
+        let func = current_func_mut!(self);
+        let jump = func.next_code_index();
+        func.emit_code(Opcode::JUMP, None);
         if has_default {
-            let func = current_func_mut!(self);
-            helper.tags.add_default(func.next_code_index());
-            func.emit_code(Opcode::JUMP, None);
+            helper.tags.add_default(jump);
+        } else {
+            // jump to the end if no case matches
+            helper.ends.add_default(jump);
         }
 
         for (i, stmt) in body.list.iter().enumerate() {
@@ -684,6 +707,9 @@ impl<'a> CodeGen<'a> {
             } else {
                 helper.tags.patch_case(func, i, func.next_code_index());
             }
+            if let Some(index) = binding {
+                self.gen_type_switch_binding(index, cc);
+            }
             for s in cc.body.iter() {
                 self.visit_stmt(s);
             }
@@ -704,8 +730,33 @@ impl<'a> CodeGen<'a> {
         current_func_emitter!(self).emit_pop(1, None);
     }
 
+    /// In a clause listing exactly one type, the variable of a type switch has that type,
+    /// otherwise it keeps the interface value of the switch expression.
+    fn gen_type_switch_binding(&mut self, index: EntIndex, cc: &CaseClause) {
+        let typ = match &cc.list {
+            Some(l) if l.len() == 1 => &l[0],
+            _ => return,
+        };
+        if self.tlookup.get_expr_mode(typ) != &OperandMode::TypeExpr {
+            // case nil
+            return;
+        }
+        let pos = Some(typ.pos(&self.ast_objs));
+        let t = self.tlookup.get_expr_tc_type(typ);
+        let meta = self.tlookup.meta_from_tc(t, self.objects, self.dummy_gcv);
+        let vt = self.tlookup.value_type_from_tc(t);
+        current_func_emitter!(self).emit_load(index, None, ValueType::Interface, pos);
+        let func = current_func_mut!(self);
+        let i = func.add_const(None, GosValue::Metadata(meta));
+        func.emit_code_with_flag_imm(Opcode::TYPE_ASSERT, false, i.into(), pos);
+        let mut emitter = current_func_emitter!(self);
+        emitter.emit_store(&LeftHandSide::Primitive(index), -1, None, None, vt, pos);
+        emitter.emit_pop(1, pos);
+    }
+
     fn gen_func_def(
         &mut self,
+        name: String,
         tc_type: TCTypeKey, // GosMetadata,
         fkey: FuncTypeKey,
         recv: Option<FieldList>,
@@ -723,6 +774,16 @@ impl<'a> CodeGen<'a> {
             FuncFlag::Default,
         );
         let fkey = *f.as_function();
+        // named before the body is generated, which may name literals after it
+        self.objects.functions[fkey].name = name;
+        // the receiver and the results are locals the body may assign to
+        let locals = recv
+            .iter()
+            .chain(typ.results.iter())
+            .flat_map(|fl| fl.list.iter())
+            .flat_map(|f| self.ast_objs.fields[*f].names.iter())
+            .filter_map(|name| self.ast_objs.idents[*name].entity_key())
+            .collect();
         let mut emitter = Emitter::new(&mut self.objects.functions[fkey]);
         if let Some(fl) = &typ.results {
             emitter.add_params(&fl, self.ast_objs);
@@ -737,6 +798,7 @@ impl<'a> CodeGen<'a> {
         };
         self.func_stack.push(fkey);
         self.func_t_stack.push(tc_type);
+        self.gen_param_copies(&typ.params, locals, body);
         // process function body
         self.visit_stmt_block(body);
         // it will not be executed if it's redundant
@@ -747,20 +809,93 @@ impl<'a> CodeGen<'a> {
         fkey
     }
 
+    /// methods are named like Go names them in backtraces: T.M or (*T).M
+    fn func_decl_name(&self, decl: &FuncDecl) -> String {
+        let name = &self.ast_objs.idents[decl.name].name;
+        let recv = match &decl.recv {
+            Some(recv) => &self.ast_objs.fields[recv.list[0]].typ,
+            None => return name.clone(),
+        };
+        let type_name = |e: &Expr| match e {
+            Expr::Ident(i) => self.ast_objs.idents[*i].name.clone(),
+            _ => "?".to_string(),
+        };
+        match recv {
+            Expr::Star(s) => format!("(*{}).{}", type_name(&s.expr), name),
+            _ => format!("{}.{}", type_name(recv), name),
+        }
+    }
+
+    /// struct and array parameters are values, they are copied on entry unless
+    /// the body can share them with the caller
+    fn gen_param_copies(
+        &mut self,
+        params: &FieldList,
+        locals: HashSet<EntityKey>,
+        body: &BlockStmt,
+    ) {
+        let keys = params
+            .list
+            .iter()
+            .flat_map(|f| self.ast_objs.fields[*f].names.iter())
+            .filter_map(|name| self.ast_objs.idents[*name].entity_key())
+            .collect();
+        let shared = SharedParams::find(keys, locals, body, self.ast_objs, &self.tlookup);
+        for f in params.list.iter() {
+            for name in self.ast_objs.fields[*f].names.iter() {
+                let tc_type = self.tlookup.get_def_tc_type(*name);
+                match self.tlookup.underlying_value_type_from_tc(tc_type) {
+                    ValueType::Struct | ValueType::Array => {}
+                    _ => continue,
+                }
+                let ident = &self.ast_objs.idents[*name];
+                let key = match ident.entity_key() {
+                    Some(key) if !shared.contains(&key) => key,
+                    _ => continue,
+                };
+                let pos = Some(ident.pos);
+                let typ = self.tlookup.get_def_value_type(*name);
+                let index = *current_func!(self).entity_index(&key).unwrap();
+                let mut emitter = current_func_emitter!(self);
+                emitter.emit_load(index, None, typ, pos);
+                let lhs = LeftHandSide::Primitive(index);
+                emitter.emit_store(&lhs, -1, None, None, typ, pos);
+                emitter.emit_pop(1, pos);
+            }
+        }
+    }
+
     fn gen_call(&mut self, func_expr: &Expr, params: &Vec<Expr>, ellipsis: bool, style: CallStyle) {
         let pos = Some(func_expr.pos(&self.ast_objs));
         match *self.tlookup.get_expr_mode(func_expr) {
             // built in function
             OperandMode::Builtin(builtin) => {
+                // the length and capacity of an array are always the size of the
+                // array, the operand is only evaluated for its side effects
+                if let Builtin::Len | Builtin::Cap = builtin {
+                    if let Some(size) = self.tlookup.array_len(&params[0]) {
+                        self.visit_expr(&params[0]);
+                        let mut emitter = current_func_emitter!(self);
+                        emitter.emit_pop(1, pos);
+                        let i = emitter.add_const(None, GosValue::Int(size as isize));
+                        emitter.emit_load(i, None, ValueType::Int, pos);
+                        return;
+                    }
+                }
                 let opcode = match builtin {
                     Builtin::New => Opcode::NEW,
                     Builtin::Make => Opcode::MAKE,
                     Builtin::Len => Opcode::LEN,
                     Builtin::Cap => Opcode::CAP,
                     Builtin::Append => Opcode::APPEND,
+                    Builtin::Copy => Opcode::COPY,
+                    Builtin::Clear => Opcode::CLEAR,
+                    Builtin::Delete => Opcode::DELETE,
                     Builtin::Close => Opcode::CLOSE,
                     Builtin::Panic => Opcode::PANIC,
                     Builtin::Recover => Opcode::RECOVER,
+                    Builtin::Print => Opcode::PRINT,
+                    Builtin::Println => Opcode::PRINTLN,
                     Builtin::Assert => Opcode::ASSERT,
                     Builtin::Ffi => Opcode::FFI,
                     _ => unimplemented!(),
@@ -771,8 +906,8 @@ impl<'a> CodeGen<'a> {
                 // some of the built in funcs are not recorded
                 if let Some(t) = self.tlookup.try_get_expr_tc_type(func_expr) {
                     self.try_cast_params_to_iface(t, params, ellipsis);
-                    if opcode == Opcode::FFI {
-                        // FFI needs the signature of the call
+                    if let Opcode::FFI | Opcode::PRINT | Opcode::PRINTLN = opcode {
+                        // the signature of the call carries the types of the arguments
                         let meta = self.tlookup.meta_from_tc(t, self.objects, self.dummy_gcv);
                         let mut emitter = current_func_emitter!(self);
                         let i = emitter.add_const(None, GosValue::Metadata(meta));
@@ -801,8 +936,15 @@ impl<'a> CodeGen<'a> {
                 } else {
                     (None, Some(param_count as OpIndex))
                 };
+                // COPY needs the type of the src, which can be a string,
+                // DELETE needs the type of the key
+                let t1 = if let Opcode::COPY | Opcode::DELETE = opcode {
+                    param_last_t
+                } else {
+                    t_variadic
+                };
                 let func = current_func_mut!(self);
-                func.emit_inst(opcode, [param0t, t_variadic, None], count, pos);
+                func.emit_inst(opcode, [param0t, t1, None], count, pos);
             }
             // conversion
             // from the specs:
@@ -831,9 +973,14 @@ impl<'a> CodeGen<'a> {
                 let t0 = self.tlookup.value_type_from_tc(utct0);
                 let tct1 = self.tlookup.get_expr_tc_type(&params[0]);
                 let utct1 = self.tlookup.underlying_tc(tct1);
-                let t1 = self.tlookup.value_type_from_tc(utct1);
+                let mut t1 = self.tlookup.value_type_from_tc(utct1);
                 // just ignore conversion if it's nil or types are identical
-                if t1 != ValueType::Nil && !identical(utct0, utct1, self.tc_objs) {
+                if t1 == ValueType::Nil {
+                    self.try_cast_nil(Some(tct0), Some(tct1), -1, pos.unwrap());
+                } else if !identical(utct0, utct1, self.tc_objs) {
+                    if t0 == ValueType::Interface {
+                        t1 = self.try_wrap_named_basic(tct1, t1, -1, pos);
+                    }
                     let iface_index = match t0 {
                         ValueType::Interface => {
                             if t1 != ValueType::Nil {
@@ -890,17 +1037,44 @@ impl<'a> CodeGen<'a> {
         let t1 = self.tlookup.get_expr_value_type(index);
         self.visit_expr(expr);
         let pos = Some(expr.pos(&self.ast_objs));
-        if let Some(const_val) = self.tlookup.get_tc_const_value(index.id()) {
-            let (ival, _) = const_val.to_int().int_as_i64();
-            if let Ok(i) = OpIndex::try_from(ival) {
-                current_func_emitter!(self).emit_load_index_imm(i, t0, comma_ok, pos);
-                return;
-            }
+        if let Some(t1) = self.try_gen_iface_map_key(expr, index) {
+            current_func_emitter!(self).emit_load_index(t0, t1, comma_ok, pos);
+            return;
+        }
+        if let Some(i) = self.try_index_imm(index) {
+            current_func_emitter!(self).emit_load_index_imm(i, t0, comma_ok, pos);
+            return;
         }
         self.visit_expr(index);
         current_func_emitter!(self).emit_load_index(t0, t1, comma_ok, pos);
     }
 
+    /// only constant indices of type int are emitted as immediates, the VM turns
+    /// them back into ints, which would be the wrong keys for maps keyed by other types
+    fn try_index_imm(&mut self, index: &Expr) -> Option<OpIndex> {
+        if self.tlookup.get_expr_value_type(index) != ValueType::Int {
+            return None;
+        }
+        let const_val = self.tlookup.get_tc_const_value(index.id())?;
+        let (ival, _) = const_val.to_int().int_as_i64();
+        OpIndex::try_from(ival).ok()
+    }
+
+    /// keys of interface keyed maps are converted to interfaces so that they
+    /// carry their dynamic types
+    fn try_gen_iface_map_key(&mut self, map: &Expr, key: &Expr) -> Option<ValueType> {
+        let map_type = self.tlookup.get_expr_tc_type(map);
+        let utype = self.tlookup.underlying_tc(map_type);
+        let key_type = self.tc_objs.types[utype].try_as_map()?.key();
+        if self.tlookup.underlying_value_type_from_tc(key_type) != ValueType::Interface {
+            return None;
+        }
+        self.visit_expr(key);
+        let rhs = self.tlookup.get_expr_tc_type(key);
+        let pos = key.pos(&self.ast_objs);
+        Some(self.try_cast_to_iface(Some(key_type), Some(rhs), -1, pos))
+    }
+
     fn try_cast_to_iface(
         &mut self,
         lhs: Option<TCTypeKey>,
@@ -908,6 +1082,9 @@ impl<'a> CodeGen<'a> {
         rhs_index: OpIndex,
         pos: usize,
     ) -> ValueType {
+        if let Some(typ) = self.try_cast_nil(lhs, rhs, rhs_index, pos) {
+            return typ;
+        }
         let mut ret_type = None;
         if let Some(t0) = lhs {
             if self.tlookup.underlying_value_type_from_tc(t0) == ValueType::Interface {
@@ -919,6 +1096,10 @@ impl<'a> CodeGen<'a> {
                     None => (true, ValueType::Slice), // it must be a variadic parameter
                 };
                 if cast {
+                    let typ = match rhs {
+                        Some(t1) => self.try_wrap_named_basic(t1, typ, rhs_index, Some(pos)),
+                        None => typ,
+                    };
                     let index = self.iface_mapping.get_index(
                         &(t0, rhs),
                         &mut self.tlookup,
@@ -940,27 +1121,86 @@ impl<'a> CodeGen<'a> {
         ret_type.unwrap_or(self.tlookup.value_type_from_tc(rhs.unwrap()))
     }
 
+    /// nil stays untyped after type checking, when it's assigned to a slice or a map
+    /// it's replaced with the zero value of the type, so that it can be appended to,
+    /// ranged over etc. just like a zero value
+    fn try_cast_nil(
+        &mut self,
+        lhs: Option<TCTypeKey>,
+        rhs: Option<TCTypeKey>,
+        rhs_index: OpIndex,
+        pos: usize,
+    ) -> Option<ValueType> {
+        let (t0, t1) = (lhs?, rhs?);
+        if self.tlookup.value_type_from_tc(t1) != ValueType::Nil {
+            return None;
+        }
+        match self.tlookup.underlying_value_type_from_tc(t0) {
+            ValueType::Slice | ValueType::Map => {
+                let meta = self.tlookup.meta_from_tc(t0, self.objects, self.dummy_gcv);
+                let zero_val = zero_val!(meta, self.objects, self.dummy_gcv);
+                let typ = self.tlookup.value_type_from_tc(t0);
+                let mut emitter = current_func_emitter!(self);
+                let i = emitter.add_const(None, zero_val);
+                emitter.emit_cast(typ, ValueType::Nil, None, rhs_index, i.into(), Some(pos));
+                Some(typ)
+            }
+            _ => None,
+        }
+    }
+
+    /// values of named basic types are stored as their underlying values, they need
+    /// to be wrapped up with their metadata before being converted to interfaces
+    fn try_wrap_named_basic(
+        &mut self,
+        typ: TCTypeKey,
+        vt: ValueType,
+        index: OpIndex,
+        pos: Option<usize>,
+    ) -> ValueType {
+        if !self.tlookup.is_named_basic(typ) {
+            return vt;
+        }
+        let meta = self.tlookup.meta_from_tc(typ, self.objects, self.dummy_gcv);
+        let mut emitter = current_func_emitter!(self);
+        let i = emitter.add_const(None, GosValue::Metadata(meta));
+        emitter.emit_cast(ValueType::Named, vt, None, index, i.into(), pos);
+        ValueType::Named
+    }
+
     fn try_cast_params_to_iface(&mut self, func: TCTypeKey, params: &Vec<Expr>, ellipsis: bool) {
         let (sig_params, variadic) = self.tlookup.get_sig_params_tc_types(func);
         let non_variadic_params = variadic.map_or(sig_params.len(), |_| sig_params.len() - 1);
+        // the types and positions of the arguments on the stack, f(g()) passes all
+        // the values of g() as the arguments
+        let mut args: Vec<(TCTypeKey, usize)> = params
+            .iter()
+            .map(|e| (self.tlookup.get_expr_tc_type(e), e.pos(&self.ast_objs)))
+            .collect();
+        if args.len() == 1 && self.tc_objs.types[args[0].0].try_as_tuple().is_some() {
+            let pos = args[0].1;
+            args = self
+                .tlookup
+                .get_tuple_tc_types(&params[0])
+                .into_iter()
+                .map(|t| (t, pos))
+                .collect();
+        }
         for (i, v) in sig_params[..non_variadic_params].iter().enumerate() {
-            let rhs_index = i as OpIndex - params.len() as OpIndex;
-            let rhs = if i == params.len() - 1 && ellipsis {
+            let rhs_index = i as OpIndex - args.len() as OpIndex;
+            let rhs = if i == args.len() - 1 && ellipsis {
                 None
             } else {
-                Some(self.tlookup.get_expr_tc_type(&params[i]))
+                Some(args[i].0)
             };
-            let pos = params[i].pos(&self.ast_objs);
-            self.try_cast_to_iface(Some(*v), rhs, rhs_index, pos);
+            self.try_cast_to_iface(Some(*v), rhs, rhs_index, args[i].1);
         }
         if !ellipsis {
             if let Some((_, t)) = variadic {
                 if self.tlookup.underlying_value_type_from_tc(t) == ValueType::Interface {
-                    for (i, p) in params.iter().enumerate().skip(non_variadic_params) {
-                        let rhs_index = i as OpIndex - params.len() as OpIndex;
-                        let rhs = self.tlookup.get_expr_tc_type(p);
-                        let pos = p.pos(&self.ast_objs);
-                        self.try_cast_to_iface(Some(t), Some(rhs), rhs_index, pos);
+                    for (i, (rhs, pos)) in args.iter().enumerate().skip(non_variadic_params) {
+                        let rhs_index = i as OpIndex - args.len() as OpIndex;
+                        self.try_cast_to_iface(Some(t), Some(*rhs), rhs_index, *pos);
                     }
                 }
             }
@@ -976,7 +1216,24 @@ impl<'a> CodeGen<'a> {
 
     fn visit_composite_expr(&mut self, expr: &Expr, tctype: TCTypeKey) {
         match expr {
-            Expr::CompositeLit(clit) => self.gen_composite_literal(clit, tctype),
+            Expr::CompositeLit(clit) => {
+                let utype = self.tlookup.underlying_tc(tctype);
+                match self.tc_objs.types[utype].try_as_pointer() {
+                    // the element is &T{...} with &T elided
+                    Some(detail) => {
+                        let base = detail.base();
+                        self.gen_composite_literal(clit, base);
+                        let typ = self.tlookup.value_type_from_tc(base);
+                        current_func_mut!(self).emit_inst(
+                            Opcode::REF_LOCAL,
+                            [Some(typ), None, None],
+                            Some(-1),
+                            Some(clit.l_brace),
+                        );
+                    }
+                    None => self.gen_composite_literal(clit, tctype),
+                }
+            }
             _ => self.visit_expr(expr),
         }
         let t = self.tlookup.get_expr_tc_type(expr);
@@ -991,6 +1248,7 @@ impl<'a> CodeGen<'a> {
         let typ = &self.tc_objs.types[tctype].underlying_val(&self.tc_objs);
         let (mkey, mc) = meta.get_underlying(&self.objects.metas).unwrap_non_ptr();
         let mtype = &self.objects.metas[mkey].clone();
+        let mut count = clit.elts.len() as OpIndex;
         match mtype {
             MetadataType::SliceOrArray(_, _) => {
                 let elem = match mc {
@@ -1028,13 +1286,22 @@ impl<'a> CodeGen<'a> {
             MetadataType::Struct(f, _) => {
                 let struct_type = typ.try_as_struct().unwrap();
                 for (i, expr) in clit.elts.iter().enumerate() {
-                    let field_type = self.tc_objs.lobjs[struct_type.fields()[i]].typ().unwrap();
+                    let field = &self.tc_objs.lobjs[struct_type.fields()[i]];
+                    let field_type = field.typ().unwrap();
                     let index = match expr {
                         Expr::KeyValue(kv) => {
                             self.visit_composite_expr(&kv.val, field_type);
                             let ident = kv.key.try_as_ident().unwrap();
                             f.mapping[&self.ast_objs.idents[*ident].name]
                         }
+                        // blank fields always hold zero values, so that they never
+                        // make a difference when comparing or hashing structs
+                        _ if field.name() == "_" => {
+                            self.visit_composite_expr(expr, field_type);
+                            current_func_emitter!(self).emit_pop(1, pos);
+                            count -= 1;
+                            continue;
+                        }
                         _ => {
                             self.visit_composite_expr(expr, field_type);
                             i as OpIndex
@@ -1048,11 +1315,7 @@ impl<'a> CodeGen<'a> {
                 unreachable!()
             }
         }
-        current_func_emitter!(self).emit_push_imm(
-            ValueType::Int32,
-            clit.elts.len() as OpIndex,
-            pos,
-        );
+        current_func_emitter!(self).emit_push_imm(ValueType::Int32, count, pos);
 
         let mut emitter = current_func_emitter!(self);
         let i = emitter.add_const(None, GosValue::Metadata(meta));
@@ -1077,6 +1340,49 @@ impl<'a> CodeGen<'a> {
         emitter.emit_load(i, None, t, pos);
     }
 
+    /// collects the operands of a chain of string `+` so that they can be joined
+    /// in one go, constant sub-expressions are left to the checker's folding
+    fn collect_concat_operands<'e>(&self, e: &'e Expr, operands: &mut Vec<&'e Expr>) {
+        let is_const = matches!(
+            self.tlookup.try_get_expr_mode(e),
+            Some(OperandMode::Constant(_))
+        );
+        match e {
+            Expr::Binary(b) if b.op == Token::ADD && !is_const => {
+                self.collect_concat_operands(&b.expr_a, operands);
+                self.collect_concat_operands(&b.expr_b, operands);
+            }
+            Expr::Paren(p) if !is_const => self.collect_concat_operands(&p.expr, operands),
+            _ => operands.push(e),
+        }
+    }
+
+    /// the local indices of the vars declared by a loop header, since Go 1.22
+    /// every iteration has its own copy of them
+    fn loop_var_indices(&self, token: &Token, lhs: &[&Expr]) -> Vec<OpIndex> {
+        if *token != Token::DEFINE {
+            return vec![];
+        }
+        lhs.iter()
+            .filter_map(|e| match e {
+                Expr::Ident(i) => self.ast_objs.idents[*i].entity_key(),
+                _ => None,
+            })
+            .filter_map(|k| match current_func!(self).entity_index(&k) {
+                Some(EntIndex::LocalVar(i)) => Some(*i),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// detaches the closures created in this iteration from the loop vars
+    fn gen_close_loop_vars(&mut self, indices: &[OpIndex], pos: Pos) {
+        let func = current_func_mut!(self);
+        for i in indices.iter() {
+            func.emit_code_with_imm(Opcode::CLOSE_UPVALUE, *i, Some(pos));
+        }
+    }
+
     fn gen_load_embedded_member(
         &mut self,
         indices: &[usize],
@@ -1107,6 +1413,264 @@ impl<'a> CodeGen<'a> {
         }
     }
 
+    /// load a field or bind a method, load_recv is false if the receiver is
+    /// already on the stack
+    fn gen_selector(&mut self, this: &Expr, expr: &Expr, load_recv: bool) {
+        let pos = Some(expr.pos(&self.ast_objs));
+        let mut lhs_meta =
+            self.tlookup
+                .get_meta_by_node_id(expr.id(), self.objects, self.dummy_gcv);
+        let (t0, _, indices, method_p_recv) = self
+            .tlookup
+            .get_selection_vtypes_indices_ptr_recv(this.id());
+        let p_recv = method_p_recv.unwrap_or(false);
+        let index_count = indices.len();
+        let index = indices[index_count - 1] as OpIndex; // the final index
+        let embedded_indices = Vec::from_iter(indices[..index_count - 1].iter().cloned());
+        let lhs_type = t0;
+        let lhs_has_embedded = index_count > 1;
+
+        if !lhs_has_embedded {
+            let recv_prep = CodeGen::recv_prep(p_recv, lhs_type);
+            match &recv_prep {
+                ReceiverPreprocess::Default => {
+                    if load_recv {
+                        self.visit_expr(expr)
+                    }
+                }
+                ReceiverPreprocess::Ref => self.visit_expr_unary(this, expr, &Token::AND),
+                ReceiverPreprocess::Deref => {
+                    if load_recv {
+                        self.visit_expr(expr);
+                    }
+                    current_func_mut!(self).emit_code_with_type(Opcode::DEREF, lhs_type, pos);
+                    lhs_meta = lhs_meta.unptr_to();
+                }
+            };
+        } else {
+            if load_recv {
+                self.visit_expr(expr);
+            }
+            lhs_meta =
+                self.gen_load_embedded_recv(&embedded_indices, lhs_meta, lhs_type, p_recv, pos);
+        }
+        self.gen_bind_method_or_load_field(lhs_meta, index, method_p_recv.is_some(), pos);
+    }
+
+    fn recv_prep(recv_is_ptr: bool, typ: ValueType) -> ReceiverPreprocess {
+        if recv_is_ptr && typ != ValueType::Pointer {
+            ReceiverPreprocess::Ref
+        } else if !recv_is_ptr && typ == ValueType::Pointer {
+            ReceiverPreprocess::Deref
+        } else {
+            ReceiverPreprocess::Default
+        }
+    }
+
+    /// loads the embedded field at the end of the path `indices` from the struct
+    /// on the stack, as the receiver of a method if there is one, and returns
+    /// the metadata of what is loaded
+    fn gen_load_embedded_recv(
+        &mut self,
+        indices: &[usize],
+        lhs_meta: GosMetadata,
+        lhs_type: ValueType,
+        p_recv: bool,
+        pos: Option<usize>,
+    ) -> GosMetadata {
+        let index_count_m1 = indices.len() - 1;
+        let (m, t) =
+            self.gen_load_embedded_member(&indices[0..index_count_m1], lhs_meta, lhs_type, pos);
+        let index = indices[index_count_m1];
+        let final_meta = self.get_embedded_member_meta(&m, index);
+        let final_typ = final_meta.get_value_type(&self.objects.metas);
+        let recv_prep = CodeGen::recv_prep(p_recv, final_typ);
+        match &recv_prep {
+            ReceiverPreprocess::Ref => {
+                current_func_mut!(self).emit_code_with_type_imm(
+                    Opcode::REF_STRUCT_FIELD,
+                    t,
+                    index as OpIndex,
+                    pos,
+                );
+                final_meta.ptr_to()
+            }
+            ReceiverPreprocess::Deref => {
+                current_func_emitter!(self).emit_load_struct_field(index as OpIndex, t, pos);
+                current_func_mut!(self).emit_code_with_type(Opcode::DEREF, lhs_type, pos);
+                final_meta.unptr_to()
+            }
+            ReceiverPreprocess::Default => {
+                current_func_emitter!(self).emit_load_struct_field(index as OpIndex, t, pos);
+                final_meta
+            }
+        }
+    }
+
+    /// binds the method or loads the field `index` of the value on the stack
+    fn gen_bind_method_or_load_field(
+        &mut self,
+        lhs_meta: GosMetadata,
+        index: OpIndex,
+        is_method: bool,
+        pos: Option<usize>,
+    ) {
+        let typ = lhs_meta.get_value_type(&self.objects.metas);
+        if is_method {
+            if lhs_meta
+                .get_underlying(&self.objects.metas)
+                .get_value_type(&self.objects.metas)
+                == ValueType::Interface
+            {
+                current_func_mut!(self).emit_code_with_type_imm(
+                    Opcode::BIND_INTERFACE_METHOD,
+                    typ,
+                    index,
+                    pos,
+                );
+            } else {
+                let func = current_func_mut!(self);
+                func.emit_code_with_type(Opcode::BIND_METHOD, typ, pos);
+                let point = func.next_code_index();
+                func.emit_raw_inst(0, pos); // placeholder for FunctionKey
+                let fkey = *self.func_stack.last().unwrap();
+                self.call_helper.add_call(fkey, point, lhs_meta, index);
+            }
+        } else {
+            current_func_emitter!(self).emit_load_struct_field(index, typ, pos);
+        }
+    }
+
+    /// T.m is turned into a function literal that binds m to its first param
+    /// and calls it with the rest, for interfaces the binding is dynamic
+    fn gen_method_expr(&mut self, this: &Expr, expr: &Expr) {
+        let pos = Some(expr.pos(&self.ast_objs));
+        let tc_type = self.tlookup.get_node_tc_type(this.id());
+        let fmeta = self
+            .tlookup
+            .meta_from_tc(tc_type, &mut self.objects, self.dummy_gcv);
+        let f = GosValue::new_function(
+            self.pkg_key,
+            fmeta,
+            self.objects,
+            self.dummy_gcv,
+            FuncFlag::Default,
+        );
+        let fkey = *f.as_function();
+        let (params, _) = self.tlookup.get_sig_params_tc_types(tc_type);
+        let returns = self.tlookup.get_sig_returns_tc_types(tc_type);
+        let ret_count = returns.len() as OpIndex;
+        let func = &mut self.objects.functions[fkey];
+        for _ in 0..(returns.len() + params.len()) {
+            func.add_local(None);
+        }
+        self.func_stack.push(fkey);
+        self.func_t_stack.push(tc_type);
+
+        let recv_type = self.tlookup.value_type_from_tc(params[0]);
+        current_func_emitter!(self).emit_load(EntIndex::LocalVar(ret_count), None, recv_type, pos);
+        self.gen_selector(this, expr, false);
+        self.gen_forward_call(&params[1..], &returns, pos);
+        self.func_stack.pop();
+        self.func_t_stack.pop();
+
+        let mut emitter = current_func_emitter!(self);
+        let i = emitter.add_const(None, GosValue::Function(fkey));
+        emitter.emit_literal(ValueType::Function, i.into(), pos);
+    }
+
+    /// calls the bound method on the stack with the params of the current function
+    /// that follow the receiver, and returns its results
+    fn gen_forward_call(
+        &mut self,
+        params: &[TCTypeKey],
+        returns: &[TCTypeKey],
+        pos: Option<usize>,
+    ) {
+        let ret_count = returns.len() as OpIndex;
+        current_func_emitter!(self).emit_pre_call(pos);
+        for (i, p) in params.iter().enumerate() {
+            let t = self.tlookup.value_type_from_tc(*p);
+            let index = EntIndex::LocalVar(ret_count + 1 + i as OpIndex);
+            current_func_emitter!(self).emit_load(index, None, t, pos);
+        }
+        // a variadic param is forwarded as it is
+        current_func_emitter!(self).emit_call(CallStyle::Default, false, pos);
+        for (i, r) in returns.iter().enumerate() {
+            let t = self.tlookup.value_type_from_tc(*r);
+            current_func_emitter!(self).emit_store(
+                &LeftHandSide::Primitive(EntIndex::LocalVar(i as OpIndex)),
+                i as OpIndex - ret_count,
+                None,
+                None,
+                t,
+                pos,
+            );
+        }
+        let mut emitter = current_func_emitter!(self);
+        if ret_count > 0 {
+            emitter.emit_pop(ret_count, pos);
+        }
+        emitter.emit_return(None, pos);
+    }
+
+    /// the code of a promoted method loads the embedded field from the receiver
+    /// and calls the method of the field with the params
+    fn gen_promoted_methods(&mut self) {
+        loop {
+            let promoted = self.tlookup.take_promoted_methods();
+            if promoted.is_empty() {
+                break;
+            }
+            for p in promoted.into_iter() {
+                let mobj = &self.tc_objs.lobjs[p.method];
+                let tc_type = mobj.typ().unwrap();
+                let p_recv = mobj.entity_type().func_has_ptr_recv();
+                let method_meta =
+                    self.tlookup
+                        .meta_from_tc(tc_type, &mut self.objects, self.dummy_gcv);
+                let sig = self.objects.metas[method_meta.as_non_ptr()].as_signature();
+                let fmeta = GosMetadata::new_sig(
+                    Some(p.recv),
+                    sig.params.clone(),
+                    sig.results.clone(),
+                    sig.variadic,
+                    &mut self.objects.metas,
+                );
+                let f = GosValue::new_function(
+                    self.pkg_key,
+                    fmeta,
+                    self.objects,
+                    self.dummy_gcv,
+                    FuncFlag::Default,
+                );
+                let fkey = *f.as_function();
+                let (params, _) = self.tlookup.get_sig_params_tc_types(tc_type);
+                let returns = self.tlookup.get_sig_returns_tc_types(tc_type);
+                let func = &mut self.objects.functions[fkey];
+                func.name = p.func_name;
+                for _ in 0..(returns.len() + params.len() + 1) {
+                    func.add_local(None);
+                }
+                self.func_stack.push(fkey);
+                self.func_t_stack.push(tc_type);
+
+                let recv_type = p.recv.get_value_type(&self.objects.metas);
+                let recv_index = EntIndex::LocalVar(returns.len() as OpIndex);
+                current_func_emitter!(self).emit_load(recv_index, None, recv_type, None);
+                let (embedded, index) = p.indices.split_at(p.indices.len() - 1);
+                let lhs_meta =
+                    self.gen_load_embedded_recv(embedded, p.recv, recv_type, p_recv, None);
+                self.gen_bind_method_or_load_field(lhs_meta, index[0] as OpIndex, true, None);
+                self.gen_forward_call(&params, &returns, None);
+                self.func_stack.pop();
+                self.func_t_stack.pop();
+                p.recv
+                    .set_method_code(&p.name, fkey, &mut self.objects.metas);
+            }
+        }
+    }
+
     fn current_func_add_const_def(&mut self, ident: &Ident, cst: GosValue) -> EntIndex {
         let func = current_func_mut!(self);
         let entity = ident.entity.clone().into_key().unwrap();
@@ -1139,6 +1703,7 @@ impl<'a> CodeGen<'a> {
         let f =
             GosValue::new_function(pkey, fmeta, self.objects, self.dummy_gcv, FuncFlag::PkgCtor);
         let fkey = *f.as_function();
+        self.objects.functions[fkey].name = "init".to_string();
         // the 0th member is the constructor
         self.objects.packages[pkey].add_member(
             String::new(),
@@ -1163,9 +1728,25 @@ impl<'a> CodeGen<'a> {
             self.gen_def_var(v);
         }
 
+        // the init functions run after all the package vars are initialized,
+        // in the order they are declared
+        let inits: Vec<GosValue> = self
+            .init_funcs
+            .iter()
+            .map(|f| GosValue::new_closure(*f, &self.objects.functions))
+            .collect();
         let mut emitter = Emitter::new(&mut self.objects.functions[fkey]);
+        emitter.emit_init_pkg(index);
+        for cls in inits.into_iter() {
+            let i = emitter.add_const(None, cls);
+            emitter.emit_load(i, None, ValueType::Closure, None);
+            emitter.emit_pre_call(None);
+            emitter.emit_call(CallStyle::Default, false, None);
+        }
         emitter.emit_return(Some(index), None);
         self.func_stack.pop();
+
+        self.gen_promoted_methods();
     }
 }
 
@@ -1206,7 +1787,12 @@ impl<'a> ExprVisitor for CodeGen<'a> {
     /// Add function as a const and then generate a closure of it
     fn visit_expr_func_lit(&mut self, this: &Expr, flit: &FuncLit) {
         let tc_type = self.tlookup.get_node_tc_type(this.id());
-        let fkey = self.gen_func_def(tc_type, flit.typ, None, &flit.body);
+        // literals are numbered in their enclosing functions like Go does: main.func1
+        let parent = *self.func_stack.last().unwrap();
+        let count = self.func_lit_counts.entry(parent).or_insert(0);
+        *count += 1;
+        let name = format!("{}.func{}", self.objects.functions[parent].name, count);
+        let fkey = self.gen_func_def(name, tc_type, flit.typ, None, &flit.body);
         let mut emitter = current_func_emitter!(self);
         let i = emitter.add_const(None, GosValue::Function(fkey));
         let pos = Some(flit.body.l_brace);
@@ -1236,97 +1822,10 @@ impl<'a> ExprVisitor for CodeGen<'a> {
             );
             return;
         }
-
-        let mut lhs_meta =
-            self.tlookup
-                .get_meta_by_node_id(expr.id(), self.objects, self.dummy_gcv);
-        let (t0, t1, indices, p_recv) = self
-            .tlookup
-            .get_selection_vtypes_indices_ptr_recv(this.id());
-        let index_count = indices.len();
-        let index = indices[index_count - 1] as OpIndex; // the final index
-        let embedded_indices = Vec::from_iter(indices[..index_count - 1].iter().cloned());
-        let lhs_type = t0;
-        let lhs_has_embedded = index_count > 1;
-        let get_recv_prep = |recv_is_ptr, typ: ValueType| -> ReceiverPreprocess {
-            if recv_is_ptr && typ != ValueType::Pointer {
-                ReceiverPreprocess::Ref
-            } else if !recv_is_ptr && typ == ValueType::Pointer {
-                ReceiverPreprocess::Deref
-            } else {
-                ReceiverPreprocess::Default
-            }
-        };
-
-        if !lhs_has_embedded {
-            let recv_prep = get_recv_prep(p_recv, lhs_type);
-            match &recv_prep {
-                ReceiverPreprocess::Default => self.visit_expr(expr),
-                ReceiverPreprocess::Ref => self.visit_expr_unary(this, expr, &Token::AND),
-                ReceiverPreprocess::Deref => {
-                    self.visit_expr(expr);
-                    current_func_mut!(self).emit_code_with_type(Opcode::DEREF, lhs_type, pos);
-                    lhs_meta = lhs_meta.unptr_to();
-                }
-            };
+        if self.tlookup.is_method_expr(this.id()) {
+            self.gen_method_expr(this, expr);
         } else {
-            self.visit_expr(expr);
-            let index_count_m1 = embedded_indices.len() - 1;
-            let (m, t) = self.gen_load_embedded_member(
-                &embedded_indices[0..index_count_m1],
-                lhs_meta,
-                lhs_type,
-                pos,
-            );
-            let index = embedded_indices[index_count_m1];
-            let final_meta = self.get_embedded_member_meta(&m, index);
-            let final_typ = final_meta.get_value_type(&self.objects.metas);
-            let recv_prep = get_recv_prep(p_recv, final_typ);
-            match &recv_prep {
-                ReceiverPreprocess::Ref => {
-                    current_func_mut!(self).emit_code_with_type_imm(
-                        Opcode::REF_STRUCT_FIELD,
-                        t,
-                        index as OpIndex,
-                        pos,
-                    );
-                    lhs_meta = final_meta.ptr_to();
-                }
-                ReceiverPreprocess::Deref => {
-                    current_func_emitter!(self).emit_load_struct_field(index as OpIndex, t, pos);
-                    current_func_mut!(self).emit_code_with_type(Opcode::DEREF, lhs_type, pos);
-                    lhs_meta = final_meta.unptr_to();
-                }
-                ReceiverPreprocess::Default => {
-                    current_func_emitter!(self).emit_load_struct_field(index as OpIndex, t, pos);
-                    lhs_meta = final_meta;
-                }
-            }
-        }
-
-        let typ = lhs_meta.get_value_type(&self.objects.metas);
-        if t1 == ValueType::Closure {
-            if lhs_meta
-                .get_underlying(&self.objects.metas)
-                .get_value_type(&self.objects.metas)
-                == ValueType::Interface
-            {
-                current_func_mut!(self).emit_code_with_type_imm(
-                    Opcode::BIND_INTERFACE_METHOD,
-                    typ,
-                    index,
-                    pos,
-                );
-            } else {
-                let func = current_func_mut!(self);
-                func.emit_code_with_type(Opcode::BIND_METHOD, typ, pos);
-                let point = func.next_code_index();
-                func.emit_raw_inst(0, pos); // placeholder for FunctionKey
-                let fkey = *self.func_stack.last().unwrap();
-                self.call_helper.add_call(fkey, point, lhs_meta, index);
-            }
-        } else {
-            current_func_emitter!(self).emit_load_struct_field(index, typ, pos);
+            self.gen_selector(this, expr, true);
         }
     }
 
@@ -1349,12 +1848,16 @@ impl<'a> ExprVisitor for CodeGen<'a> {
             None => current_func_emitter!(self).emit_push_imm(ValueType::Int, 0, pos),
             Some(e) => self.visit_expr(e),
         }
-        match high {
-            None => current_func_emitter!(self).emit_push_imm(ValueType::Int, -1, pos),
-            Some(e) => self.visit_expr(e),
-        }
+        // an omitted high index is not pushed, but flagged on the instruction
+        let high_flag = match high {
+            None => Some(ValueType::FlagA),
+            Some(e) => {
+                self.visit_expr(e);
+                None
+            }
+        };
         match max {
-            None => current_func_mut!(self).emit_code_with_type(Opcode::SLICE, t, pos),
+            None => current_func_mut!(self).emit_code_with_type2(Opcode::SLICE, t, high_flag, pos),
             Some(e) => {
                 self.visit_expr(e);
                 current_func_mut!(self).emit_code_with_type(Opcode::SLICE_FULL, t, pos);
@@ -1550,9 +2053,48 @@ impl<'a> ExprVisitor for CodeGen<'a> {
         current_func_mut!(self).emit_code_with_type(code, t, pos);
     }
 
-    fn visit_expr_binary(&mut self, _: &Expr, left: &Expr, op: &Token, right: &Expr) {
-        self.visit_expr(left);
+    fn visit_expr_binary(&mut self, this: &Expr, left: &Expr, op: &Token, right: &Expr) {
         let t = self.tlookup.get_expr_value_type(left);
+        if *op == Token::ADD && t == ValueType::Str {
+            let mut operands = vec![];
+            self.collect_concat_operands(this, &mut operands);
+            if operands.len() > 2 {
+                for e in operands.iter() {
+                    self.visit_expr(e);
+                }
+                let pos = Some(left.pos(&self.ast_objs));
+                current_func_mut!(self).emit_code_with_imm(
+                    Opcode::CONCAT,
+                    operands.len() as OpIndex,
+                    pos,
+                );
+                return;
+            }
+        }
+        // a concrete operand compared with an interface is converted to the
+        // interface type first, then they are compared as interfaces
+        let iface_cmp = match op {
+            Token::EQL | Token::NEQ => {
+                let (lt, rt) = (
+                    self.tlookup.get_expr_tc_type(left),
+                    self.tlookup.get_expr_tc_type(right),
+                );
+                let is_iface =
+                    |t| self.tlookup.underlying_value_type_from_tc(t) == ValueType::Interface;
+                (is_iface(lt) || is_iface(rt)).then(|| (lt, rt))
+            }
+            _ => None,
+        };
+        let t = if iface_cmp.is_some() {
+            ValueType::Interface
+        } else {
+            t
+        };
+        let pos = Some(left.pos(&self.ast_objs));
+        self.visit_expr(left);
+        if let Some((lt, rt)) = iface_cmp {
+            self.try_cast_to_iface(Some(rt), Some(lt), -1, pos.unwrap());
+        }
         let code = match op {
             Token::ADD => Opcode::ADD,
             Token::SUB => Opcode::SUB,
@@ -1576,7 +2118,6 @@ impl<'a> ExprVisitor for CodeGen<'a> {
             Token::GEQ => Opcode::GEQ,
             _ => unreachable!(),
         };
-        let pos = Some(left.pos(&self.ast_objs));
         // handles short circuit
         let mark_code = match op {
             Token::LAND => {
@@ -1592,6 +2133,9 @@ impl<'a> ExprVisitor for CodeGen<'a> {
             _ => None,
         };
         self.visit_expr(right);
+        if let Some((lt, rt)) = iface_cmp {
+            self.try_cast_to_iface(Some(lt), Some(rt), -1, pos.unwrap());
+        }
 
         if let Some((i, c)) = mark_code {
             let func = current_func_mut!(self);
@@ -1675,7 +2219,7 @@ impl<'a> StmtVisitor for CodeGen<'a> {
                             self.gen_def_var(vs);
                         }
                     }
-                    Token::CONST => self.gen_def_const(&vs.names, &vs.values),
+                    Token::CONST => self.gen_def_const(&vs.names),
                     _ => unreachable!(),
                 },
             }
@@ -1689,7 +2233,8 @@ impl<'a> StmtVisitor for CodeGen<'a> {
         }
         let tc_type = self.tlookup.get_def_tc_type(decl.name);
         let stmt = decl.body.as_ref().unwrap();
-        let fkey = self.gen_func_def(tc_type, decl.typ, decl.recv.clone(), stmt);
+        let name = self.func_decl_name(decl);
+        let fkey = self.gen_func_def(name, tc_type, decl.typ, decl.recv.clone(), stmt);
         let cls = GosValue::new_closure(fkey, &self.objects.functions);
         // this is a struct method
         if let Some(self_ident) = &decl.recv {
@@ -1701,8 +2246,13 @@ impl<'a> StmtVisitor for CodeGen<'a> {
             meta.set_method_code(name, fkey, &mut self.objects.metas);
         } else {
             let ident = &self.ast_objs.idents[decl.name];
-            let pkg = &mut self.objects.packages[self.pkg_key];
-            pkg.add_member(ident.name.clone(), cls);
+            // init functions can't be referred to, and there can be many of them
+            if ident.name == "init" {
+                self.init_funcs.push(fkey);
+            } else {
+                let pkg = &mut self.objects.packages[self.pkg_key];
+                pkg.add_member(ident.name.clone(), cls);
+            }
         }
     }
 
@@ -1711,7 +2261,11 @@ impl<'a> StmtVisitor for CodeGen<'a> {
         let offset = current_func!(self).code().len();
         let entity = self.ast_objs.idents[stmt.label].entity_key().unwrap();
         let is_breakable = match &stmt.stmt {
-            Stmt::For(_) | Stmt::Range(_) | Stmt::Select(_) | Stmt::Switch(_) => true,
+            Stmt::For(_)
+            | Stmt::Range(_)
+            | Stmt::Select(_)
+            | Stmt::Switch(_)
+            | Stmt::TypeSwitch(_) => true,
             _ => false,
         };
         self.branch.add_label(entity, offset, is_breakable);
@@ -1772,6 +2326,34 @@ impl<'a> StmtVisitor for CodeGen<'a> {
         let types = self
             .tlookup
             .get_sig_returns_tc_types(*self.func_t_stack.last().unwrap());
+        if rstmt.results.len() == 1 && types.len() > 1 {
+            // return f(), the values of the call are all on the stack
+            let expr = &rstmt.results[0];
+            self.visit_expr(expr);
+            let count = types.len() as OpIndex;
+            let tc_types = self.tlookup.get_tuple_tc_types(expr);
+            for (i, tc_type) in tc_types.into_iter().enumerate() {
+                let rhs_index = i as OpIndex - count;
+                let t = self.try_cast_to_iface(
+                    Some(types[i]),
+                    Some(tc_type),
+                    rhs_index,
+                    expr.pos(&self.ast_objs),
+                );
+                current_func_emitter!(self).emit_store(
+                    &LeftHandSide::Primitive(EntIndex::LocalVar(i as OpIndex)),
+                    rhs_index,
+                    None,
+                    None,
+                    t,
+                    pos,
+                );
+            }
+            let mut emitter = current_func_emitter!(self);
+            emitter.emit_pop(count, pos);
+            emitter.emit_return(None, pos);
+            return;
+        }
         for (i, expr) in rstmt.results.iter().enumerate() {
             self.visit_expr(expr);
             let tc_type = self.tlookup.get_expr_tc_type(expr);
@@ -1863,7 +2445,7 @@ impl<'a> StmtVisitor for CodeGen<'a> {
     }
 
     fn visit_stmt_switch(&mut self, sstmt: &SwitchStmt) {
-        self.branch.enter_block();
+        self.branch.enter_block(false);
 
         if let Some(init) = &sstmt.init {
             self.visit_stmt(init);
@@ -1879,12 +2461,14 @@ impl<'a> StmtVisitor for CodeGen<'a> {
             }
         };
 
-        self.gen_switch_body(&*sstmt.body, tag_type);
+        self.gen_switch_body(&*sstmt.body, tag_type, None);
 
         self.branch.leave_block(current_func_mut!(self), None);
     }
 
     fn visit_stmt_type_switch(&mut self, tstmt: &TypeSwitchStmt) {
+        self.branch.enter_block(false);
+
         if let Some(init) = &tstmt.init {
             self.visit_stmt(init);
         }
@@ -1909,14 +2493,18 @@ impl<'a> StmtVisitor for CodeGen<'a> {
             let index = func.add_local(ident_key);
             func.add_local_zero(GosValue::new_nil());
             self.visit_expr(v);
-            let func = current_func_mut!(self);
-            func.emit_code_with_flag_imm(Opcode::TYPE, true, index.into(), pos);
+            // the variable holds the interface value, each clause narrows it
+            let lhs = LeftHandSide::Primitive(index);
+            current_func_emitter!(self).emit_store(&lhs, -1, None, None, ValueType::Interface, pos);
+            current_func_mut!(self).emit_code(Opcode::TYPE, pos);
+            self.gen_switch_body(&*tstmt.body, ValueType::Metadata, Some(index));
         } else {
             self.visit_expr(v);
             current_func_mut!(self).emit_code(Opcode::TYPE, pos);
+            self.gen_switch_body(&*tstmt.body, ValueType::Metadata, None);
         }
 
-        self.gen_switch_body(&*tstmt.body, ValueType::Metadata);
+        self.branch.leave_block(current_func_mut!(self), None);
     }
 
     fn visit_stmt_comm(&mut self, _cclause: &CommClause) {
@@ -1948,7 +2536,7 @@ impl<'a> StmtVisitor for CodeGen<'a> {
         Since communication on nil channels can never proceed, a select with only nil
         channels and no default case blocks forever.
         */
-        self.branch.enter_block();
+        self.branch.enter_block(false);
 
         let mut helper = SelectHelper::new();
         let comms: Vec<&CommClause> = sstmt
@@ -2027,10 +2615,16 @@ impl<'a> StmtVisitor for CodeGen<'a> {
     }
 
     fn visit_stmt_for(&mut self, fstmt: &ForStmt) {
-        self.branch.enter_block();
+        self.branch.enter_block(true);
 
+        let mut loop_vars = vec![];
         if let Some(init) = &fstmt.init {
             self.visit_stmt(init);
+            if let Stmt::Assign(key) = init {
+                let a = &self.ast_objs.a_stmts[*key];
+                let lhs: Vec<&Expr> = a.lhs.iter().collect();
+                loop_vars = self.loop_var_indices(&a.token, &lhs);
+            }
         }
         let top_marker = current_func!(self).next_code_index();
         let out_marker = if let Some(cond) = &fstmt.cond {
@@ -2042,10 +2636,14 @@ impl<'a> StmtVisitor for CodeGen<'a> {
             None
         };
         self.visit_stmt_block(&fstmt.body);
-        let continue_marker = if let Some(post) = &fstmt.post {
-            // "continue" jumps to post statements
+        let continue_marker = if fstmt.post.is_some() || !loop_vars.is_empty() {
+            // "continue" jumps to post statements, which update the copy of
+            // the loop vars for the next iteration
             let m = current_func!(self).next_code_index();
-            self.visit_stmt(post);
+            self.gen_close_loop_vars(&loop_vars, fstmt.for_pos);
+            if let Some(post) = &fstmt.post {
+                self.visit_stmt(post);
+            }
             m
         } else {
             // "continue" jumps to top directly if no post statements
@@ -2064,12 +2662,16 @@ impl<'a> StmtVisitor for CodeGen<'a> {
             func.instruction_mut(m - 1).set_imm(offset);
         }
 
+        // break leaves the copy of the last iteration, which a later run of
+        // the loop would overwrite
+        let end = current_func!(self).next_code_index();
+        self.gen_close_loop_vars(&loop_vars, fstmt.for_pos);
         self.branch
-            .leave_block(current_func_mut!(self), Some(continue_marker));
+            .leave_block_with_end(current_func_mut!(self), Some(continue_marker), end);
     }
 
     fn visit_stmt_range(&mut self, rstmt: &RangeStmt) {
-        self.branch.enter_block();
+        self.branch.enter_block(true);
 
         let blank = Expr::Ident(self.blank_ident);
         let lhs = vec![
@@ -2081,6 +2683,14 @@ impl<'a> StmtVisitor for CodeGen<'a> {
             .unwrap();
 
         self.visit_stmt_block(&rstmt.body);
+        let loop_vars = self.loop_var_indices(&rstmt.token, &lhs);
+        let continue_marker = if loop_vars.is_empty() {
+            marker
+        } else {
+            let m = current_func!(self).next_code_index();
+            self.gen_close_loop_vars(&loop_vars, rstmt.token_pos);
+            m
+        };
         // jump to the top
         let func = current_func_mut!(self);
         let offset = -func.offset(marker) - 1;
@@ -2089,8 +2699,10 @@ impl<'a> StmtVisitor for CodeGen<'a> {
         func.instruction_mut(marker).set_imm(end_offset);
         func.emit_code_with_imm(Opcode::JUMP, offset, Some(rstmt.token_pos));
 
+        let end = current_func!(self).next_code_index();
+        self.gen_close_loop_vars(&loop_vars, rstmt.token_pos);
         self.branch
-            .leave_block(current_func_mut!(self), Some(marker));
+            .leave_block_with_end(current_func_mut!(self), Some(continue_marker), end);
     }
 
     fn visit_empty_stmt(&mut self, _e: &EmptyStmt) {}
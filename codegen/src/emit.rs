@@ -339,6 +339,11 @@ impl<'a> Emitter<'a> {
         );
     }
 
+    pub fn emit_init_pkg(&mut self, index: OpIndex) {
+        self.f
+            .emit_inst(Opcode::INIT_PKG, [None, None, None], Some(index), None);
+    }
+
     pub fn emit_pre_call(&mut self, pos: Option<usize>) {
         self.f
             .emit_inst(Opcode::PRE_CALL, [None, None, None], None, pos);
@@ -140,6 +140,28 @@ pub fn parse_check_gen(
     config: &Config,
     fset: &mut FileSet,
     el: &ErrorList,
+) -> Result<ByteCode, usize> {
+    check_gen(path, None, config, fset, el)
+}
+
+/// Like parse_check_gen, but the main package is the single file `src` instead
+/// of being read from `name`, which is only used in diagnostics
+pub fn parse_check_gen_source(
+    name: &str,
+    src: &str,
+    config: &Config,
+    fset: &mut FileSet,
+    el: &ErrorList,
+) -> Result<ByteCode, usize> {
+    check_gen(name, Some(src), config, fset, el)
+}
+
+fn check_gen(
+    path: &str,
+    src: Option<&str>,
+    config: &Config,
+    fset: &mut FileSet,
+    el: &ErrorList,
 ) -> Result<ByteCode, usize> {
     let asto = &mut AstObjects::new();
     let tco = &mut goscript_types::TCObjects::new();
@@ -149,7 +171,10 @@ pub fn parse_check_gen(
     let importer =
         &mut goscript_types::Importer::new(&config, fset, pkgs, results, asto, tco, el, 0);
     let key = goscript_types::ImportKey::new(path, "./");
-    let main_pkg = importer.import(&key);
+    let main_pkg = match src {
+        Some(src) => importer.import_source(path, src),
+        None => importer.import(&key).map_err(|_| el.len()),
+    };
 
     if el.len() > 0 {
         Err(el.len())
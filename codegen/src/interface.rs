@@ -5,13 +5,18 @@ use goscript_types::TypeKey as TCTypeKey;
 use goscript_vm::gc::GcoVec;
 use goscript_vm::instruction::*;
 use goscript_vm::metadata::*;
-use goscript_vm::objects::{FunctionKey, VMObjects};
+use goscript_vm::objects::VMObjects;
+use goscript_vm::vm::IfaceBinding;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
 pub struct IfaceMapping {
-    ifaces: Vec<(GosMetadata, Option<Vec<Rc<RefCell<MethodDesc>>>>)>,
+    ifaces: Vec<(
+        GosMetadata,
+        Option<Vec<Rc<RefCell<MethodDesc>>>>,
+        Option<GosMetadata>,
+    )>,
     iface_indices: HashMap<(TCTypeKey, Option<TCTypeKey>), OpIndex>,
 }
 
@@ -23,13 +28,14 @@ impl IfaceMapping {
         }
     }
 
-    pub fn into_result(self) -> Vec<(GosMetadata, Option<Rc<Vec<FunctionKey>>>)> {
+    pub fn into_result(self) -> Vec<IfaceBinding> {
         self.ifaces
             .into_iter()
-            .map(|(meta, method)| {
+            .map(|(meta, method, src)| {
                 (
                     meta,
                     method.map(|m| Rc::new(m.iter().map(|x| x.borrow().func.unwrap()).collect())),
+                    src,
                 )
             })
             .collect()
@@ -57,10 +63,14 @@ impl IfaceMapping {
         lookup: &mut TypeLookup,
         objs: &mut VMObjects,
         dummy_gcv: &mut GcoVec,
-    ) -> (GosMetadata, Option<Vec<Rc<RefCell<MethodDesc>>>>) {
+    ) -> (
+        GosMetadata,
+        Option<Vec<Rc<RefCell<MethodDesc>>>>,
+        Option<GosMetadata>,
+    ) {
         let i = lookup.meta_from_tc(i_s.0, objs, dummy_gcv);
         if i_s.1.is_none() {
-            return (i, None);
+            return (i, None, None);
         }
         let s = lookup.meta_from_tc(i_s.1.unwrap(), objs, dummy_gcv);
         let ifields = match &objs.metas[i.as_non_ptr()] {
@@ -81,6 +91,6 @@ impl IfaceMapping {
             // primitive types
             _ => None,
         };
-        (i, methods.map(|x| ifields.iface_named_mapping(x)))
+        (i, methods.map(|x| ifields.iface_named_mapping(x)), Some(s))
     }
 }
@@ -3,6 +3,7 @@ mod call;
 mod emit;
 mod interface;
 mod package;
+mod param;
 mod types;
 
 pub mod codegen;
@@ -0,0 +1,313 @@
+/// Helper for passing structs and arrays by value
+/// The callee copies a struct or array parameter on entry, unless it can share
+/// the caller's value without anyone telling the difference.
+///
+/// A parameter is shared only if the body of the function only reads its
+/// fields and elements of non-aggregate types, and the body can neither modify
+/// the caller's value nor let anything else run while it is running: it makes
+/// no calls other than conversions and len, cap and assert, has no closures,
+/// takes no addresses, does not communicate over channels and only assigns to
+/// its own local variables.
+/// The arguments of go and defer calls are copied by the caller, so only
+/// synchronous calls ever share a parameter.
+use std::collections::HashSet;
+
+use super::types::TypeLookup;
+
+use goscript_parser::ast::*;
+use goscript_parser::objects::Objects as AstObjects;
+use goscript_parser::objects::*;
+use goscript_parser::token::Token;
+use goscript_types::{Builtin, OperandMode};
+use goscript_vm::instruction::ValueType;
+
+pub struct SharedParams<'a, 'b> {
+    ast_objs: &'a AstObjects,
+    tlookup: &'a TypeLookup<'b>,
+    params: HashSet<EntityKey>,
+    copied: HashSet<EntityKey>,
+    locals: HashSet<EntityKey>,
+    assigned: Vec<Option<EntityKey>>,
+    inert: bool,
+}
+
+impl<'a, 'b> SharedParams<'a, 'b> {
+    /// returns the parameters in `params` that `body` can share with the caller,
+    /// `locals` are the other parameters and the results of the function
+    pub fn find(
+        params: HashSet<EntityKey>,
+        locals: HashSet<EntityKey>,
+        body: &BlockStmt,
+        ast_objs: &'a AstObjects,
+        tlookup: &'a TypeLookup<'b>,
+    ) -> HashSet<EntityKey> {
+        let mut sp = SharedParams {
+            ast_objs,
+            tlookup,
+            params,
+            copied: HashSet::new(),
+            locals,
+            assigned: vec![],
+            inert: true,
+        };
+        sp.locals.extend(sp.params.iter());
+        sp.block(body);
+        let locals = &sp.locals;
+        if !sp.inert
+            || !sp
+                .assigned
+                .iter()
+                .all(|k| k.map_or(false, |k| locals.contains(&k)))
+        {
+            return HashSet::new();
+        }
+        sp.params.difference(&sp.copied).cloned().collect()
+    }
+
+    fn param(&self, ikey: &IdentKey) -> Option<EntityKey> {
+        self.ast_objs.idents[*ikey]
+            .entity_key()
+            .filter(|k| self.params.contains(k))
+    }
+
+    fn define(&mut self, ikey: &IdentKey) {
+        if let Some(key) = self.ast_objs.idents[*ikey].entity_key() {
+            self.locals.insert(key);
+        }
+    }
+
+    /// `e` is assigned to, only a plain local variable keeps the body inert
+    fn assign(&mut self, e: &Expr) {
+        match e {
+            Expr::Ident(i) => {
+                let ident = &self.ast_objs.idents[*i];
+                if ident.name == "_" {
+                    return;
+                }
+                if let Some(key) = self.param(i) {
+                    self.copied.insert(key);
+                }
+                self.assigned.push(ident.entity_key());
+            }
+            Expr::Paren(p) => self.assign(&p.expr),
+            _ => self.inert = false,
+        }
+    }
+
+    /// the parameter `e` reads a field or an element of, if any
+    fn read_root(&self, e: &Expr) -> Option<EntityKey> {
+        match e {
+            Expr::Ident(i) => self.param(i),
+            Expr::Paren(p) => self.read_root(&p.expr),
+            Expr::Selector(s) if self.tlookup.is_field_selection(e.id()) => self.read_root(&s.expr),
+            Expr::Index(i) => self.read_root(&i.expr),
+            _ => None,
+        }
+    }
+
+    /// visits the indices of a chain of selectors and index expressions
+    fn read_indices(&mut self, e: &Expr) {
+        match e {
+            Expr::Paren(p) => self.read_indices(&p.expr),
+            Expr::Selector(s) => self.read_indices(&s.expr),
+            Expr::Index(i) => {
+                self.read_indices(&i.expr);
+                self.expr(&i.index);
+            }
+            _ => {}
+        }
+    }
+
+    fn block(&mut self, b: &BlockStmt) {
+        for s in b.list.iter() {
+            self.stmt(s);
+        }
+    }
+
+    fn opt_stmt(&mut self, s: &Option<Stmt>) {
+        if let Some(s) = s {
+            self.stmt(s);
+        }
+    }
+
+    fn opt_expr(&mut self, e: &Option<Expr>) {
+        if let Some(e) = e {
+            self.expr(e);
+        }
+    }
+
+    fn stmt(&mut self, s: &Stmt) {
+        match s {
+            Stmt::Decl(d) => {
+                if let Decl::Gen(g) = &**d {
+                    for key in g.specs.iter() {
+                        if let Spec::Value(v) = &self.ast_objs.specs[*key] {
+                            for e in v.values.iter() {
+                                self.expr(e);
+                            }
+                            for name in v.names.iter() {
+                                self.define(name);
+                            }
+                        }
+                    }
+                }
+            }
+            Stmt::Labeled(key) => {
+                let l = &self.ast_objs.l_stmts[*key];
+                self.stmt(&l.stmt);
+            }
+            Stmt::Expr(e) => self.expr(e),
+            Stmt::IncDec(s) => {
+                self.assign(&s.expr);
+                self.expr(&s.expr);
+            }
+            Stmt::Assign(key) => {
+                let a = &self.ast_objs.a_stmts[*key];
+                for e in a.rhs.iter() {
+                    self.expr(e);
+                }
+                for e in a.lhs.iter() {
+                    match (&a.token, e) {
+                        (Token::DEFINE, Expr::Ident(i)) => self.define(i),
+                        _ => {
+                            self.assign(e);
+                            self.expr(e);
+                        }
+                    }
+                }
+            }
+            Stmt::Return(r) => {
+                for e in r.results.iter() {
+                    self.expr(e);
+                }
+            }
+            Stmt::Block(b) => self.block(b),
+            Stmt::If(i) => {
+                self.opt_stmt(&i.init);
+                self.expr(&i.cond);
+                self.block(&i.body);
+                self.opt_stmt(&i.els);
+            }
+            Stmt::Case(c) => {
+                if let Some(l) = &c.list {
+                    for e in l.iter() {
+                        self.expr(e);
+                    }
+                }
+                for s in c.body.iter() {
+                    self.stmt(s);
+                }
+            }
+            Stmt::Switch(s) => {
+                self.opt_stmt(&s.init);
+                self.opt_expr(&s.tag);
+                self.block(&s.body);
+            }
+            Stmt::TypeSwitch(s) => {
+                self.opt_stmt(&s.init);
+                self.stmt(&s.assign);
+                self.block(&s.body);
+            }
+            Stmt::For(f) => {
+                self.opt_stmt(&f.init);
+                self.opt_expr(&f.cond);
+                self.opt_stmt(&f.post);
+                self.block(&f.body);
+            }
+            Stmt::Range(r) => {
+                self.expr(&r.expr);
+                for e in r.key.iter().chain(r.val.iter()) {
+                    match (&r.token, e) {
+                        (Token::DEFINE, Expr::Ident(i)) => self.define(i),
+                        _ => {
+                            self.assign(e);
+                            self.expr(e);
+                        }
+                    }
+                }
+                self.block(&r.body);
+            }
+            Stmt::Go(_) | Stmt::Defer(_) | Stmt::Send(_) | Stmt::Select(_) | Stmt::Comm(_) => {
+                self.inert = false
+            }
+            Stmt::Bad(_) | Stmt::Empty(_) | Stmt::Branch(_) => {}
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        match e {
+            Expr::Ident(i) => {
+                if let Some(key) = self.param(i) {
+                    self.copied.insert(key);
+                }
+            }
+            Expr::Ellipsis(el) => self.opt_expr(&el.elt),
+            Expr::FuncLit(_) => self.inert = false,
+            Expr::CompositeLit(c) => {
+                for e in c.elts.iter() {
+                    self.expr(e);
+                }
+            }
+            Expr::Paren(p) => self.expr(&p.expr),
+            Expr::Selector(_) | Expr::Index(_) if self.read_root(e).is_some() => {
+                // a field or an element of an aggregate type would share
+                // its value with the parameter
+                let tc_type = self.tlookup.get_expr_tc_type(e);
+                match self.tlookup.underlying_value_type_from_tc(tc_type) {
+                    ValueType::Struct | ValueType::Array => {
+                        self.copied.insert(self.read_root(e).unwrap());
+                    }
+                    _ => {}
+                }
+                self.read_indices(e);
+            }
+            Expr::Selector(s) => self.expr(&s.expr),
+            Expr::Index(i) => {
+                self.expr(&i.expr);
+                self.expr(&i.index);
+            }
+            Expr::Slice(s) => {
+                self.expr(&s.expr);
+                self.opt_expr(&s.low);
+                self.opt_expr(&s.high);
+                self.opt_expr(&s.max);
+            }
+            Expr::TypeAssert(t) => self.expr(&t.expr),
+            Expr::Call(c) => {
+                match self.tlookup.get_expr_mode(&c.func) {
+                    OperandMode::TypeExpr
+                    | OperandMode::Builtin(Builtin::Len)
+                    | OperandMode::Builtin(Builtin::Cap)
+                    | OperandMode::Builtin(Builtin::Assert) => {}
+                    _ => self.inert = false,
+                }
+                for e in c.args.iter() {
+                    self.expr(e);
+                }
+            }
+            Expr::Star(s) => self.expr(&s.expr),
+            Expr::Unary(u) => {
+                if u.op == Token::AND || u.op == Token::ARROW {
+                    self.inert = false;
+                }
+                self.expr(&u.expr);
+            }
+            Expr::Binary(b) => {
+                self.expr(&b.expr_a);
+                self.expr(&b.expr_b);
+            }
+            Expr::KeyValue(kv) => {
+                self.expr(&kv.key);
+                self.expr(&kv.val);
+            }
+            Expr::Bad(_)
+            | Expr::BasicLit(_)
+            | Expr::Array(_)
+            | Expr::Struct(_)
+            | Expr::Func(_)
+            | Expr::Interface(_)
+            | Expr::Map(_)
+            | Expr::Chan(_) => {}
+        }
+    }
+}
@@ -3,8 +3,9 @@ use goscript_parser::ast::Node;
 use goscript_parser::ast::{Expr, NodeId};
 use goscript_parser::objects::IdentKey;
 use goscript_types::{
-    BasicType, ChanDir, ConstValue, EntityType, ObjKey, OperandMode, PackageKey as TCPackageKey,
-    TCObjects, Type, TypeInfo, TypeKey as TCTypeKey,
+    lookup_field_or_method, BasicType, ChanDir, ConstValue, EntityType, LookupResult, ObjKey,
+    OperandMode, PackageKey as TCPackageKey, SelectionKind, TCObjects, Type, TypeInfo,
+    TypeKey as TCTypeKey,
 };
 use goscript_vm::gc::GcoVec;
 use goscript_vm::instruction::{OpIndex, ValueType};
@@ -14,11 +15,22 @@ use std::collections::HashMap;
 
 pub type TypeCache = HashMap<TCTypeKey, GosMetadata>;
 
+/// A method of an embedded field promoted to a named struct type, the code of
+/// the method is a function that calls the method of the embedded field
+pub struct PromotedMethod {
+    pub recv: GosMetadata,
+    pub name: String,
+    pub func_name: String,
+    pub method: ObjKey,
+    pub indices: Vec<usize>,
+}
+
 pub struct TypeLookup<'a> {
     tc_objs: &'a TCObjects,
     ti: &'a TypeInfo,
     types_cache: &'a mut TypeCache,
     unsafe_ptr_meta: GosMetadata,
+    promoted: Vec<PromotedMethod>,
 }
 
 impl<'a> TypeLookup<'a> {
@@ -33,6 +45,7 @@ impl<'a> TypeLookup<'a> {
             ti: ti,
             types_cache: cache,
             unsafe_ptr_meta: u_p_meta,
+            promoted: vec![],
         }
     }
 
@@ -51,6 +64,13 @@ impl<'a> TypeLookup<'a> {
         self.const_value(typ_val.typ, const_val)
     }
 
+    /// the value of a declared constant, which is also available when the constant
+    /// spec omits the expressions and repeats the previous ones
+    pub fn get_def_const_value(&mut self, ikey: IdentKey) -> Option<GosValue> {
+        let obj = &self.tc_objs.lobjs[self.ti.defs[&ikey]?];
+        Some(self.const_value(obj.typ().unwrap(), obj.const_val()))
+    }
+
     pub fn get_expr_tc_type(&self, e: &Expr) -> TCTypeKey {
         self.get_node_tc_type(e.id())
     }
@@ -162,16 +182,37 @@ impl<'a> TypeLookup<'a> {
         self.tuple_tc_types(typ)
     }
 
+    pub fn is_method_expr(&self, id: NodeId) -> bool {
+        match self.ti.selections.get(&id).map(|s| s.kind()) {
+            Some(SelectionKind::MethodExpr) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_field_selection(&self, id: NodeId) -> bool {
+        match self.ti.selections.get(&id).map(|s| s.kind()) {
+            Some(SelectionKind::FieldVal) => true,
+            _ => false,
+        }
+    }
+
+    /// the last element tells if the selected method has a pointer receiver,
+    /// it's None if a field is selected
     pub fn get_selection_vtypes_indices_ptr_recv(
         &mut self,
         id: NodeId,
-    ) -> (ValueType, ValueType, &Vec<usize>, bool) {
+    ) -> (ValueType, ValueType, &Vec<usize>, Option<bool>) {
         let sel = &self.ti.selections[&id];
         let t0 = self.value_type_from_tc(sel.recv().unwrap());
         let obj = &self.tc_objs.lobjs[sel.obj()];
         let t1 = self.value_type_from_tc(obj.typ().unwrap());
-        let has_ptr_recv = t1 == ValueType::Closure && obj.entity_type().func_has_ptr_recv();
-        (t0, t1, &sel.indices(), has_ptr_recv)
+        // a field of a func type is not a method
+        let ptr_recv = if obj.entity_type().is_func() {
+            Some(obj.entity_type().func_has_ptr_recv())
+        } else {
+            None
+        };
+        (t0, t1, &sel.indices(), ptr_recv)
     }
 
     pub fn meta_from_tc(
@@ -187,6 +228,11 @@ impl<'a> TypeLookup<'a> {
         self.types_cache.get(&typ).unwrap().clone()
     }
 
+    /// the promoted methods of the named types created since the last call
+    pub fn take_promoted_methods(&mut self) -> Vec<PromotedMethod> {
+        std::mem::take(&mut self.promoted)
+    }
+
     pub fn get_sig_params_tc_types(
         &mut self,
         func: TCTypeKey,
@@ -329,7 +375,7 @@ impl<'a> TypeLookup<'a> {
         match &self.tc_objs.types[typ] {
             Type::Basic(_) => self.basic_type_from_tc(typ, vm_objs),
             Type::Array(detail) => {
-                let elem = self.meta_from_tc_impl(detail.elem(), vm_objs, dummy_gcv);
+                let elem = self.meta_from_tc(detail.elem(), vm_objs, dummy_gcv);
                 GosMetadata::new_array(elem, detail.len().unwrap() as usize, &mut vm_objs.metas)
             }
             Type::Slice(detail) => {
@@ -401,15 +447,17 @@ impl<'a> TypeLookup<'a> {
                 inner.ptr_to()
             }
             Type::Named(detail) => {
-                // put a place holder there to avoid recursion
-                let mdph = GosMetadata::new(
+                // put a place holder there to avoid recursion, and fill it in later
+                // so that recursive references share the same metadata (and methods)
+                let md = GosMetadata::new(
                     MetadataType::Named(Methods::new(), GosMetadata::Untyped),
                     &mut vm_objs.metas,
                 );
-                self.types_cache.insert(typ, mdph);
+                self.types_cache.insert(typ, md);
                 let underlying = self.meta_from_tc(detail.underlying(), vm_objs, dummy_gcv);
                 self.types_cache.remove(&typ);
-                let md = GosMetadata::new_named(underlying, &mut vm_objs.metas);
+                debug_assert!(underlying.get_value_type(&vm_objs.metas) != ValueType::Named);
+                vm_objs.metas[md.as_non_ptr()] = MetadataType::Named(Methods::new(), underlying);
                 for key in detail.methods().iter() {
                     let mobj = &self.tc_objs.lobjs[*key];
                     md.add_method(
@@ -418,6 +466,27 @@ impl<'a> TypeLookup<'a> {
                         &mut vm_objs.metas,
                     )
                 }
+                let type_name = detail
+                    .obj()
+                    .map_or("?".to_string(), |o| self.tc_objs.lobjs[o].name().clone());
+                for (name, method, indices, indirect) in self.promoted_methods(typ) {
+                    let mobj = &self.tc_objs.lobjs[method];
+                    // the field is addressable only if there is a pointer on the path to it
+                    let ptr_recv = mobj.entity_type().func_has_ptr_recv() && !indirect;
+                    md.add_method(name.clone(), ptr_recv, &mut vm_objs.metas);
+                    let func_name = if ptr_recv {
+                        format!("(*{}).{}", type_name, name)
+                    } else {
+                        format!("{}.{}", type_name, name)
+                    };
+                    self.promoted.push(PromotedMethod {
+                        recv: if ptr_recv { md.ptr_to() } else { md },
+                        name: name,
+                        func_name: func_name,
+                        method: method,
+                        indices: indices,
+                    });
+                }
                 md
             }
             _ => {
@@ -434,6 +503,23 @@ impl<'a> TypeLookup<'a> {
         }
     }
 
+    pub fn is_named_basic(&self, typ: TCTypeKey) -> bool {
+        match &self.tc_objs.types[typ] {
+            Type::Named(_) => self.value_type_from_tc(typ) != ValueType::Named,
+            _ => false,
+        }
+    }
+
+    /// the length of the array, or of the array pointed to, that `e` evaluates to
+    pub fn array_len(&self, e: &Expr) -> Option<u64> {
+        let typ = self.underlying_tc(self.get_expr_tc_type(e));
+        let typ = match &self.tc_objs.types[typ] {
+            Type::Pointer(detail) => self.underlying_tc(detail.base()),
+            _ => typ,
+        };
+        self.tc_objs.types[typ].try_as_array().and_then(|x| x.len())
+    }
+
     pub fn underlying_value_type_from_tc(&self, typ: TCTypeKey) -> ValueType {
         self.value_type_from_tc(self.underlying_tc(typ))
     }
@@ -472,7 +558,15 @@ impl<'a> TypeLookup<'a> {
             Type::Chan(_) => ValueType::Channel,
             Type::Signature(_) => ValueType::Closure,
             Type::Pointer(_) => ValueType::Pointer,
-            Type::Named(_) => ValueType::Named,
+            // named basic types share the representation of the underlying types
+            Type::Named(n) => {
+                let vt = self.value_type_from_tc(n.underlying());
+                if vt.is_basic() {
+                    vt
+                } else {
+                    ValueType::Named
+                }
+            }
             _ => {
                 dbg!(&self.tc_objs.types[typ]);
                 unimplemented!()
@@ -496,6 +590,61 @@ impl<'a> TypeLookup<'a> {
         }
     }
 
+    /// returns the methods of the embedded fields of a named struct type that are
+    /// neither declared by the type nor shadowed by a field or another method,
+    /// with the lookup indices of each and whether the path has a pointer on it
+    fn promoted_methods(&self, named: TCTypeKey) -> Vec<(String, ObjKey, Vec<usize>, bool)> {
+        let mut candidates: Vec<ObjKey> = vec![];
+        let mut seen = vec![named];
+        let mut next = vec![self.underlying_tc(named)];
+        while let Some(t) = next.pop() {
+            match &self.tc_objs.types[t] {
+                Type::Struct(detail) => {
+                    for f in detail.fields().iter() {
+                        let field = &self.tc_objs.lobjs[*f];
+                        if !field.var_embedded() {
+                            continue;
+                        }
+                        let mut ft = field.typ().unwrap();
+                        if let Type::Pointer(p) = &self.tc_objs.types[ft] {
+                            ft = p.base();
+                        }
+                        if seen.contains(&ft) {
+                            continue;
+                        }
+                        seen.push(ft);
+                        if let Type::Named(n) = &self.tc_objs.types[ft] {
+                            candidates.extend(n.methods().iter());
+                        }
+                        next.push(self.underlying_tc(ft));
+                    }
+                }
+                Type::Interface(detail) => {
+                    candidates.extend(detail.all_methods().as_ref().unwrap().iter());
+                }
+                _ => {}
+            }
+        }
+
+        let mut result: Vec<(String, ObjKey, Vec<usize>, bool)> = vec![];
+        for c in candidates.into_iter() {
+            let cobj = &self.tc_objs.lobjs[c];
+            if result.iter().any(|x| x.0 == *cobj.name()) {
+                continue;
+            }
+            match lookup_field_or_method(named, true, cobj.pkg(), cobj.name(), self.tc_objs) {
+                LookupResult::Entry(okey, indices, indirect) => {
+                    let obj = &self.tc_objs.lobjs[okey];
+                    if indices.len() > 1 && obj.entity_type().is_func() {
+                        result.push((obj.name().clone(), okey, indices, indirect));
+                    }
+                }
+                _ => {}
+            }
+        }
+        result
+    }
+
     fn tuple_tc_types(&self, typ: TCTypeKey) -> Vec<TCTypeKey> {
         match &self.tc_objs.types[typ] {
             Type::Tuple(detail) => detail
@@ -519,7 +668,10 @@ impl<'a> TypeLookup<'a> {
             let field = &self.tc_objs.lobjs[*f];
             let f_type = self.meta_from_tc(field.typ().unwrap(), vm_objs, dummy_gcv);
             vec.push(f_type);
-            map.insert(field.name().clone(), i as OpIndex);
+            // blank fields take up a slot but can't be referred to by name
+            if field.name() != "_" {
+                map.insert(field.name().clone(), i as OpIndex);
+            }
         }
         Fields::new(vec, map)
     }
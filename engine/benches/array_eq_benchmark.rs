@@ -0,0 +1,17 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+mod common;
+
+use common::run;
+
+fn array_eq() {
+    let err_cnt = run("./tests/demo/array_eq.gos", false);
+    assert!(err_cnt == 0);
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    c.bench_function("array_eq", |b| b.iter(|| array_eq()));
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+mod common;
+
+use common::run;
+
+fn bool_conditions() {
+    let err_cnt = run("./tests/demo/bool.gos", false);
+    assert!(err_cnt == 0);
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    c.bench_function("bool", |b| b.iter(|| bool_conditions()));
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,14 @@
+extern crate goscript_engine as engine;
+
+pub fn run(path: &str, trace: bool) -> usize {
+    let cfg = engine::Config {
+        work_dir: Some("./".to_string()),
+        base_path: Some("./std/".to_string()),
+        trace_parser: trace,
+        trace_checker: trace,
+        trace_vm: true,
+        ..Default::default()
+    };
+    let engine = engine::Engine::new(cfg);
+    engine.run(path)
+}
@@ -1,18 +1,8 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
-extern crate goscript_engine as engine;
+mod common;
 
-fn run(path: &str, trace: bool) -> usize {
-    let cfg = engine::Config {
-        work_dir: Some("./".to_string()),
-        base_path: Some("./std/".to_string()),
-        trace_parser: trace,
-        trace_checker: trace,
-        trace_vm: true,
-    };
-    let engine = engine::Engine::new(cfg);
-    engine.run(path)
-}
+use common::run;
 
 fn leetcode5() {
     let err_cnt = run("./tests/demo/leetcode5.gos", false);
@@ -0,0 +1,17 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+mod common;
+
+use common::run;
+
+fn struct_param() {
+    let err_cnt = run("./tests/demo/struct_param.gos", false);
+    assert!(err_cnt == 0);
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    c.bench_function("struct_param", |b| b.iter(|| struct_param()));
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -2,7 +2,15 @@ extern crate goscript_codegen as cg;
 extern crate goscript_parser as fe;
 extern crate goscript_types as types;
 extern crate goscript_vm as vm;
-use super::std::{fmt, sync};
+use super::std::{binary, fmt, hash, sync, utf8};
+use std::cell::RefCell;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+pub use vm::gc::GcConfig;
+pub use vm::metadata::MetaKind;
+pub use vm::value::GosValue;
+pub use vm::vm::PanicInfo;
 
 pub struct Config {
     // working directory
@@ -15,45 +23,212 @@ pub struct Config {
     pub trace_checker: bool,
     // proint debug info for vm
     pub trace_vm: bool,
+    // called with unrecovered panics before the default output is printed,
+    // which the hook can suppress
+    pub panic_hook: Option<Box<vm::vm::PanicHook<'static>>>,
+    // panic(nil) panics with a runtime error that recover() returns, as in Go 1.21,
+    // instead of with nil
+    pub go121_panic_nil: bool,
+    // where fmt writes to, instead of the standard output
+    pub stdout: Option<Box<dyn Write>>,
+    // where print, println and the unrecovered panic messages write to, instead
+    // of the standard error
+    pub stderr: Option<Box<dyn Write>>,
+    // when the reference cycles are collected
+    pub gc_config: GcConfig,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            work_dir: None,
+            base_path: None,
+            trace_parser: false,
+            trace_checker: false,
+            trace_vm: false,
+            panic_hook: None,
+            go121_panic_nil: true,
+            stdout: None,
+            stderr: None,
+            gc_config: GcConfig::default(),
+        }
+    }
+}
+
+/// Why running a program with `Engine::run_with_result` failed
+#[derive(Debug)]
+pub enum GosError {
+    /// the program doesn't parse, with the number of errors
+    ParseError(usize),
+    /// the program doesn't type check, with the number of errors
+    CheckError(usize),
+    /// an unrecovered panic, with the value passed to panic() and the
+    /// (package, function, position) frames of the call stack, innermost first
+    Panic(GosValue, Vec<(String, String, String)>),
+    /// the VM stopped on a bug in the compiler, the VM or an extension rather
+    /// than in the program, with the message
+    VmError(String),
 }
 
 pub struct Engine {
     config: Config,
     ffi: vm::ffi::FfiFactory,
+    outputs: vm::vm::Outputs,
+    // shared by the runs, so that the cycles they leave can be collected later
+    gcv: vm::gc::GcoVec,
 }
 
 impl Engine {
-    pub fn new(config: Config) -> Engine {
+    pub fn new(mut config: Config) -> Engine {
+        let output = |w: Box<dyn Write>| -> vm::vm::Output { Rc::new(RefCell::new(w)) };
+        let outputs = vm::vm::Outputs {
+            stdout: config.stdout.take().map(output),
+            stderr: config.stderr.take().map(output),
+        };
         let mut ffi = vm::ffi::FfiFactory::new();
-        ffi.register("fmt", Box::new(fmt::Fmt::new));
+        let fmt_outputs = outputs.clone();
+        ffi.register(
+            "fmt",
+            Box::new(move |v| fmt::Fmt::new(v, fmt_outputs.clone())),
+        );
         ffi.register("mutex", Box::new(sync::Mutex::new));
+        ffi.register("binary.big_endian", Box::new(binary::Binary::new_big_endian));
+        ffi.register("binary.little_endian", Box::new(binary::Binary::new_little_endian));
+        ffi.register("hash.fnv", Box::new(hash::Fnv::new));
+        ffi.register("hash.crc32", Box::new(hash::Crc32::new));
+        ffi.register("unicode.utf8", Box::new(utf8::Utf8::new));
+        let gcv = vm::gc::GcoVec::with_config(config.gc_config);
         Engine {
             config: config,
             ffi: ffi,
+            outputs: outputs,
+            gcv: gcv,
         }
     }
 
     pub fn run(&self, path: &str) -> usize {
+        self.run_entry(path, None)
+    }
+
+    /// Like `run`, but the main package is the single file `src` instead of being
+    /// read from the file system. `name` is the file name shown in diagnostics,
+    /// imports are still resolved with `base_path`.
+    pub fn run_source(&self, name: &str, src: &str) -> usize {
+        self.run_entry(name, Some(src))
+    }
+
+    /// Like `run_with_result`, but runs `src` as `run_source` does
+    pub fn run_source_with_result(&self, name: &str, src: &str) -> Result<GosValue, GosError> {
+        self.run_entry_with_result(name, Some(src))
+    }
+
+    fn run_entry(&self, path: &str, src: Option<&str>) -> usize {
+        let mut fs = fe::FileSet::new();
+        match self.compile(path, src, &mut fs) {
+            Ok(bc) => {
+                let vm = vm::vm::GosVM::new(
+                    bc,
+                    &self.ffi,
+                    Some(&fs),
+                    self.config.panic_hook.as_deref(),
+                    self.config.go121_panic_nil,
+                    self.outputs.clone(),
+                    &self.gcv,
+                );
+                vm.run();
+                0
+            }
+            Err(GosError::ParseError(n)) | Err(GosError::CheckError(n)) => n,
+            Err(GosError::Panic(_, _)) | Err(GosError::VmError(_)) => unreachable!(),
+        }
+    }
+
+    /// Like `run`, but tells compile errors and panics apart. The first
+    /// unrecovered panic is returned, after the panic hook in the config is
+    /// called with it. main returns nothing, so a successful run returns nil.
+    /// A Rust panic in the VM or in an extension is returned as `VmError`.
+    pub fn run_with_result(&self, path: &str) -> Result<GosValue, GosError> {
+        self.run_entry_with_result(path, None)
+    }
+
+    fn run_entry_with_result(&self, path: &str, src: Option<&str>) -> Result<GosValue, GosError> {
+        let mut fs = fe::FileSet::new();
+        let bc = self.compile(path, src, &mut fs)?;
+        let panicked = RefCell::new(None);
+        let hook = |info: &PanicInfo| {
+            if let Some(h) = &self.config.panic_hook {
+                h(info);
+            }
+            let mut panicked = panicked.borrow_mut();
+            if panicked.is_none() {
+                let val = info
+                    .value
+                    .iface_underlying()
+                    .unwrap_or_else(|| info.value.clone());
+                *panicked = Some(GosError::Panic(val, info.backtrace.clone()));
+            }
+        };
+        let vm = vm::vm::GosVM::new(
+            bc,
+            &self.ffi,
+            Some(&fs),
+            Some(&hook),
+            self.config.go121_panic_nil,
+            self.outputs.clone(),
+            &self.gcv,
+        );
+        // such bugs panic in Rust, and the state they leave is not to be used
+        let crashed = panic::catch_unwind(AssertUnwindSafe(|| vm.run())).err();
+        match (panicked.into_inner(), crashed) {
+            (Some(e), _) => Err(e),
+            (None, Some(payload)) => {
+                let msg = match payload.downcast::<String>() {
+                    Ok(s) => *s,
+                    Err(payload) => match payload.downcast::<&str>() {
+                        Ok(s) => s.to_string(),
+                        Err(_) => "unknown error".to_string(),
+                    },
+                };
+                Err(GosError::VmError(msg))
+            }
+            (None, None) => Ok(GosValue::new_nil()),
+        }
+    }
+
+    fn compile(
+        &self,
+        path: &str,
+        src: Option<&str>,
+        fs: &mut fe::FileSet,
+    ) -> Result<vm::vm::ByteCode, GosError> {
         let config = types::Config {
             work_dir: self.config.work_dir.clone(),
             base_path: self.config.base_path.clone(),
             trace_parser: self.config.trace_parser,
             trace_checker: self.config.trace_checker,
         };
-        let mut fs = fe::FileSet::new();
         let el = &mut fe::errors::ErrorList::new();
-        let code = cg::entry::parse_check_gen(path, &config, &mut fs, el);
-        if let Ok(bc) = code {
-            let vm = vm::vm::GosVM::new(bc, &self.ffi, Some(&fs));
-            vm.run();
-            0
-        } else {
+        let result = match src {
+            Some(src) => cg::entry::parse_check_gen_source(path, src, &config, fs, el),
+            None => cg::entry::parse_check_gen(path, &config, fs, el),
+        };
+        result.map_err(|n| {
             if self.config.trace_vm {
                 el.sort();
                 print!("{}", el);
             }
-            code.unwrap_err()
-        }
+            if el.borrow().iter().any(|e| e.by_parser) {
+                GosError::ParseError(n)
+            } else {
+                GosError::CheckError(n)
+            }
+        })
+    }
+
+    /// Collects the reference cycles that are no longer reachable, mostly for
+    /// when `GcConfig::auto` is off
+    pub fn collect_garbage(&self) {
+        vm::gc::gc(&self.gcv);
     }
 
     pub fn register_extension(&mut self, name: &'static str, ctor: Box<vm::ffi::Ctor>) {
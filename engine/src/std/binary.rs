@@ -0,0 +1,116 @@
+use goscript_vm::ffi::{Ffi, FfiCtorResult};
+use goscript_vm::value::{GosValue, RtEmptyResult, RtMultiValResult, RuntimeResult};
+use std::cell::RefCell;
+use std::convert::TryInto;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+pub struct Binary {
+    big_endian: bool,
+}
+
+impl Ffi for Binary {
+    fn call(
+        &self,
+        func_name: &str,
+        params: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RtMultiValResult> + '_>> {
+        let re = match func_name {
+            "uint16" => self.read::<2>(&params[0]).map(|b| {
+                let v = if self.big_endian {
+                    u16::from_be_bytes(b)
+                } else {
+                    u16::from_le_bytes(b)
+                };
+                vec![GosValue::Uint16(v)]
+            }),
+            "uint32" => self.read::<4>(&params[0]).map(|b| {
+                let v = if self.big_endian {
+                    u32::from_be_bytes(b)
+                } else {
+                    u32::from_le_bytes(b)
+                };
+                vec![GosValue::Uint32(v)]
+            }),
+            "uint64" => self.read::<8>(&params[0]).map(|b| {
+                let v = if self.big_endian {
+                    u64::from_be_bytes(b)
+                } else {
+                    u64::from_le_bytes(b)
+                };
+                vec![GosValue::Uint64(v)]
+            }),
+            "put_uint16" => {
+                let v = *params[1].as_uint16();
+                let b = if self.big_endian {
+                    v.to_be_bytes()
+                } else {
+                    v.to_le_bytes()
+                };
+                self.write(&params[0], &b)
+            }
+            "put_uint32" => {
+                let v = *params[1].as_uint32();
+                let b = if self.big_endian {
+                    v.to_be_bytes()
+                } else {
+                    v.to_le_bytes()
+                };
+                self.write(&params[0], &b)
+            }
+            "put_uint64" => {
+                let v = *params[1].as_uint64();
+                let b = if self.big_endian {
+                    v.to_be_bytes()
+                } else {
+                    v.to_le_bytes()
+                };
+                self.write(&params[0], &b)
+            }
+            _ => unreachable!(),
+        };
+        Box::pin(async move { re })
+    }
+}
+
+impl Binary {
+    pub fn new_big_endian(_v: Vec<GosValue>) -> FfiCtorResult<Rc<RefCell<dyn Ffi>>> {
+        Ok(Rc::new(RefCell::new(Binary { big_endian: true })))
+    }
+
+    pub fn new_little_endian(_v: Vec<GosValue>) -> FfiCtorResult<Rc<RefCell<dyn Ffi>>> {
+        Ok(Rc::new(RefCell::new(Binary { big_endian: false })))
+    }
+
+    fn read<const N: usize>(&self, b: &GosValue) -> RuntimeResult<[u8; N]> {
+        let slice = &b.as_slice().0;
+        Binary::check_len(slice.len(), N)?;
+        let bytes: Vec<u8> = (0..N)
+            .map(|i| *slice.get(i).unwrap().as_uint8())
+            .collect();
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn write(&self, b: &GosValue, bytes: &[u8]) -> RtMultiValResult {
+        let slice = &b.as_slice().0;
+        Binary::check_len(slice.len(), bytes.len())?;
+        for (i, byte) in bytes.iter().enumerate() {
+            slice.set(i, GosValue::Uint8(*byte));
+        }
+        Ok(vec![])
+    }
+
+    /// panics like the bounds check hint in Go's encoding/binary
+    fn check_len(len: usize, need: usize) -> RtEmptyResult {
+        if len < need {
+            Err(format!(
+                "runtime error: index out of range [{}] with length {}",
+                need - 1,
+                len
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
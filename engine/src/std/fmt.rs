@@ -1,11 +1,14 @@
 use goscript_vm::ffi::{Ffi, FfiCtorResult};
 use goscript_vm::value::{GosValue, RtMultiValResult};
+use goscript_vm::vm::Outputs;
 use std::cell::RefCell;
 use std::future::Future;
 use std::pin::Pin;
 use std::rc::Rc;
 
-pub struct Fmt {}
+pub struct Fmt {
+    outputs: Outputs,
+}
 
 impl Ffi for Fmt {
     fn call(
@@ -13,40 +16,179 @@ impl Ffi for Fmt {
         func_name: &str,
         params: Vec<GosValue>,
     ) -> Pin<Box<dyn Future<Output = RtMultiValResult> + '_>> {
-        match func_name {
-            "println" => self.println(params),
-            "printf" => self.printf(params),
+        let re = match func_name {
+            "println" => {
+                self.println(params);
+                vec![]
+            }
+            "printf" => {
+                self.outputs.print_out(&self.sprintf(params));
+                vec![]
+            }
+            "sprintf" => vec![GosValue::new_str(self.sprintf(params))],
+            "sprint" => vec![GosValue::new_str(self.sprint(params))],
             _ => unreachable!(),
-        }
-        Box::pin(async move { Ok(vec![]) })
+        };
+        Box::pin(async move { Ok(re) })
     }
 }
 
 impl Fmt {
-    pub fn new(_v: Vec<GosValue>) -> FfiCtorResult<Rc<RefCell<dyn Ffi>>> {
-        Ok(Rc::new(RefCell::new(Fmt {})))
+    pub fn new(_v: Vec<GosValue>, outputs: Outputs) -> FfiCtorResult<Rc<RefCell<dyn Ffi>>> {
+        Ok(Rc::new(RefCell::new(Fmt { outputs: outputs })))
     }
 
+    /// params: the values and the results of String() of the ones that are Stringers
     fn println(&self, params: Vec<GosValue>) {
         let vec = params[0].as_slice().0.get_vec();
+        let strs = params[1].as_slice().0.get_vec();
         let strs: Vec<String> = vec
             .iter()
-            .map(|x| {
-                if x.is_nil() {
-                    "<nil>".to_string()
+            .zip(strs.iter())
+            .map(|(x, s)| Fmt::display(x, s))
+            .collect();
+        self.outputs.print_out(&format!("{}\n", strs.join(", ")));
+    }
+
+    /// params: the values and the results of String() of the ones that are Stringers
+    /// like Go, spaces are added between operands when neither is a string
+    fn sprint(&self, params: Vec<GosValue>) -> String {
+        let vec = params[0].as_slice().0.get_vec();
+        let strs = params[1].as_slice().0.get_vec();
+        let mut result = String::new();
+        let mut prev_is_str = true;
+        for (i, (x, s)) in vec.iter().zip(strs.iter()).enumerate() {
+            let is_str = match Fmt::value(x) {
+                Some(GosValue::Str(_)) => true,
+                _ => false,
+            };
+            if i > 0 && !is_str && !prev_is_str {
+                result.push(' ');
+            }
+            result.push_str(&Fmt::display(x, s));
+            prev_is_str = is_str;
+        }
+        result
+    }
+
+    /// params: the format string, the values and the results of String() of the
+    /// ones that are Stringers.
+    /// Only the verbs are supported, flags, width and precision are not.
+    fn sprintf(&self, params: Vec<GosValue>) -> String {
+        let format = params[0].as_str();
+        let args = params[1].as_slice().0.get_vec();
+        let strs = params[2].as_slice().0.get_vec();
+        let mut result = String::new();
+        let mut next = 0;
+        let mut chars = format.as_str().chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                result.push(c);
+                continue;
+            }
+            let verb = match chars.next() {
+                Some('%') => {
+                    result.push('%');
+                    continue;
+                }
+                Some(v) => v,
+                None => {
+                    result.push_str("%!(NOVERB)");
+                    break;
+                }
+            };
+            if next >= args.len() {
+                result.push_str(&format!("%!{}(MISSING)", verb));
+                continue;
+            }
+            result.push_str(&Fmt::format_verb(verb, &args[next], &strs[next]));
+            next += 1;
+        }
+        if next < args.len() {
+            let extra: Vec<String> = args[next..]
+                .iter()
+                .zip(strs[next..].iter())
+                .map(|(x, s)| Fmt::display(x, s))
+                .collect();
+            result.push_str(&format!("%!(EXTRA {})", extra.join(", ")));
+        }
+        result
+    }
+
+    fn format_verb(verb: char, arg: &GosValue, s: &GosValue) -> String {
+        // like Go, String() is only used with the verbs that are valid for strings
+        let val = match verb {
+            'v' | 's' | 'q' | 'x' | 'X' => Fmt::value(s),
+            _ => None,
+        }
+        .or_else(|| Fmt::value(arg));
+        let int = val.as_ref().and_then(|v| Fmt::as_i128(v));
+        match (verb, &val, int) {
+            ('v', _, _) | ('s', _, _) => Fmt::display(arg, s),
+            ('q', Some(GosValue::Str(sval)), _) => format!("{:?}", sval.as_str()),
+            ('q', _, Some(i)) | ('c', _, Some(i)) => {
+                let c = std::char::from_u32(i as u32).unwrap_or('\u{FFFD}');
+                if verb == 'q' {
+                    format!("{:?}", c)
                 } else {
-                    match x.iface_underlying() {
-                        Some(v) => v.to_string(),
-                        None => "<ffi>".to_string(),
-                    }
+                    c.to_string()
                 }
-            })
-            .collect();
-        println!("{}", strs.join(", "));
+            }
+            ('d', _, Some(i)) => i.to_string(),
+            ('x', _, Some(i)) if i < 0 => format!("-{:x}", -i),
+            ('x', _, Some(i)) => format!("{:x}", i),
+            ('X', _, Some(i)) if i < 0 => format!("-{:X}", -i),
+            ('X', _, Some(i)) => format!("{:X}", i),
+            ('x', Some(GosValue::Str(sval)), _) => sval
+                .as_str()
+                .bytes()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            ('X', Some(GosValue::Str(sval)), _) => sval
+                .as_str()
+                .bytes()
+                .map(|b| format!("{:02X}", b))
+                .collect(),
+            ('t', Some(GosValue::Bool(b)), _) => b.to_string(),
+            ('f', Some(GosValue::Float32(f)), _) => format!("{:.6}", f.0),
+            ('f', Some(GosValue::Float64(f)), _) => format!("{:.6}", f.0),
+            _ => format!("%!{}({})", verb, Fmt::display(arg, s)),
+        }
+    }
+
+    fn display(arg: &GosValue, s: &GosValue) -> String {
+        match Fmt::value(s) {
+            Some(sval) => sval.to_string(),
+            None => match Fmt::value(arg) {
+                Some(v) => v.to_string(),
+                None if arg.is_nil() => "<nil>".to_string(),
+                None => "<ffi>".to_string(),
+            },
+        }
+    }
+
+    /// the dynamic value of the interface, None if it's nil or an ffi object
+    fn value(arg: &GosValue) -> Option<GosValue> {
+        if arg.is_nil() {
+            None
+        } else {
+            arg.iface_underlying().map(|v| v.unwrap_named_basic())
+        }
     }
 
-    fn printf(&self, params: Vec<GosValue>) {
-        let _vec = params[0].as_slice().0.get_vec();
-        unimplemented!();
+    fn as_i128(v: &GosValue) -> Option<i128> {
+        match v {
+            GosValue::Int(i) => Some(*i as i128),
+            GosValue::Int8(i) => Some(*i as i128),
+            GosValue::Int16(i) => Some(*i as i128),
+            GosValue::Int32(i) => Some(*i as i128),
+            GosValue::Int64(i) => Some(*i as i128),
+            GosValue::Uint(i) => Some(*i as i128),
+            GosValue::Uint8(i) => Some(*i as i128),
+            GosValue::Uint16(i) => Some(*i as i128),
+            GosValue::Uint32(i) => Some(*i as i128),
+            GosValue::Uint64(i) => Some(*i as i128),
+            _ => None,
+        }
     }
 }
@@ -0,0 +1,147 @@
+use goscript_vm::ffi::{Ffi, FfiCtorResult};
+use goscript_vm::value::{GosValue, PointerObj, RtMultiValResult, UserData};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+const FNV32_OFFSET: u32 = 0x811c9dc5;
+const FNV32_PRIME: u32 = 0x01000193;
+const CRC32_IEEE: u32 = 0xedb88320;
+
+/// A running 32-bit checksum, shared between the ffi object and the gos handle
+#[derive(Clone)]
+struct Sum32State {
+    sum: Rc<Cell<u32>>,
+}
+
+impl UserData for Sum32State {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Sum32State {
+    fn new(init: u32) -> Sum32State {
+        Sum32State {
+            sum: Rc::new(Cell::new(init)),
+        }
+    }
+
+    fn from_handle(v: &GosValue) -> Sum32State {
+        let ud = v.as_pointer().as_user_data();
+        ud.as_any().downcast_ref::<Sum32State>().unwrap().clone()
+    }
+
+    fn new_handle(init: u32) -> GosValue {
+        GosValue::new_pointer(PointerObj::UserData(Rc::new(Sum32State::new(init))))
+    }
+}
+
+fn bytes_of(v: &GosValue) -> Vec<u8> {
+    if v.is_nil() {
+        return vec![];
+    }
+    v.as_slice()
+        .0
+        .get_vec()
+        .iter()
+        .map(|x| *x.as_uint8())
+        .collect()
+}
+
+pub struct Fnv {}
+
+impl Ffi for Fnv {
+    fn call(
+        &self,
+        func_name: &str,
+        params: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RtMultiValResult> + '_>> {
+        let re = match func_name {
+            "new32a" => vec![Sum32State::new_handle(FNV32_OFFSET)],
+            "write" => {
+                let state = Sum32State::from_handle(&params[0]);
+                state.sum.set(fnv32a(state.sum.get(), &bytes_of(&params[1])));
+                vec![]
+            }
+            "sum32" => vec![GosValue::Uint32(Sum32State::from_handle(&params[0]).sum.get())],
+            "reset" => {
+                Sum32State::from_handle(&params[0]).sum.set(FNV32_OFFSET);
+                vec![]
+            }
+            _ => unreachable!(),
+        };
+        Box::pin(async move { Ok(re) })
+    }
+}
+
+impl Fnv {
+    pub fn new(_v: Vec<GosValue>) -> FfiCtorResult<Rc<RefCell<dyn Ffi>>> {
+        Ok(Rc::new(RefCell::new(Fnv {})))
+    }
+}
+
+fn fnv32a(mut hash: u32, data: &[u8]) -> u32 {
+    for b in data.iter() {
+        hash ^= *b as u32;
+        hash = hash.wrapping_mul(FNV32_PRIME);
+    }
+    hash
+}
+
+pub struct Crc32 {
+    table: [u32; 256],
+}
+
+impl Ffi for Crc32 {
+    fn call(
+        &self,
+        func_name: &str,
+        params: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RtMultiValResult> + '_>> {
+        let re = match func_name {
+            "new_ieee" => vec![Sum32State::new_handle(0)],
+            "write" => {
+                let state = Sum32State::from_handle(&params[0]);
+                state.sum.set(self.update(state.sum.get(), &bytes_of(&params[1])));
+                vec![]
+            }
+            "sum32" => vec![GosValue::Uint32(Sum32State::from_handle(&params[0]).sum.get())],
+            "reset" => {
+                Sum32State::from_handle(&params[0]).sum.set(0);
+                vec![]
+            }
+            "checksum_ieee" => vec![GosValue::Uint32(self.update(0, &bytes_of(&params[0])))],
+            _ => unreachable!(),
+        };
+        Box::pin(async move { Ok(re) })
+    }
+}
+
+impl Crc32 {
+    pub fn new(_v: Vec<GosValue>) -> FfiCtorResult<Rc<RefCell<dyn Ffi>>> {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut crc = i as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ CRC32_IEEE
+                } else {
+                    crc >> 1
+                };
+            }
+            *entry = crc;
+        }
+        Ok(Rc::new(RefCell::new(Crc32 { table: table })))
+    }
+
+    fn update(&self, crc: u32, data: &[u8]) -> u32 {
+        let mut crc = !crc;
+        for b in data.iter() {
+            crc = self.table[((crc as u8) ^ *b) as usize] ^ (crc >> 8);
+        }
+        !crc
+    }
+}
@@ -1,2 +1,5 @@
+pub mod binary;
 pub mod fmt;
+pub mod hash;
 pub mod sync;
+pub mod utf8;
@@ -0,0 +1,36 @@
+use goscript_vm::ffi::{Ffi, FfiCtorResult};
+use goscript_vm::value::{GosValue, RtMultiValResult};
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Lets package unicode/utf8 use the decoding of the underlying rust strings
+pub struct Utf8 {}
+
+impl Ffi for Utf8 {
+    fn call(
+        &self,
+        func_name: &str,
+        params: Vec<GosValue>,
+    ) -> Pin<Box<dyn Future<Output = RtMultiValResult> + '_>> {
+        let s = params[0].as_str();
+        let re = match func_name {
+            "rune_count_in_string" => vec![GosValue::Int(s.rune_count() as isize)],
+            "decode_rune_in_string" => {
+                let (r, size) = s
+                    .rune_at(0)
+                    .unwrap_or((std::char::REPLACEMENT_CHARACTER, 0));
+                vec![GosValue::Int32(r as i32), GosValue::Int(size as isize)]
+            }
+            _ => unreachable!(),
+        };
+        Box::pin(async move { Ok(re) })
+    }
+}
+
+impl Utf8 {
+    pub fn new(_v: Vec<GosValue>) -> FfiCtorResult<Rc<RefCell<dyn Ffi>>> {
+        Ok(Rc::new(RefCell::new(Utf8 {})))
+    }
+}
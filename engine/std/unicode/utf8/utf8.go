@@ -19,6 +19,13 @@ const (
 	UTFMax    = 4            // maximum number of bytes of a UTF-8 encoded Unicode character.
 )
 
+// The string functions delegate to the runtime, which already stores strings
+// as UTF-8 and can decode them without going through the bytes one by one.
+type ffiUtf8 interface {
+	rune_count_in_string(s string) int
+	decode_rune_in_string(s string) (rune, int)
+}
+
 // Code points in the surrogate range are not valid for UTF-8.
 const (
 	surrogateMin = 0xD800
@@ -197,43 +204,9 @@ func DecodeRune(p []byte) (r rune, size int) {
 // out of range, or is not the shortest possible UTF-8 encoding for the
 // value. No other validation is performed.
 func DecodeRuneInString(s string) (r rune, size int) {
-	n := len(s)
-	if n < 1 {
-		return RuneError, 0
-	}
-	s0 := s[0]
-	x := first[s0]
-	if x >= as {
-		// The following code simulates an additional check for x == xx and
-		// handling the ASCII and invalid cases accordingly. This mask-and-or
-		// approach prevents an additional branch.
-		mask := rune(x) << 31 >> 31 // Create 0x0000 or 0xFFFF.
-		return rune(s[0])&^mask | RuneError&mask, 1
-	}
-	sz := int(x & 7)
-	accept := acceptRanges[x>>4]
-	if n < sz {
-		return RuneError, 1
-	}
-	s1 := s[1]
-	if s1 < accept.lo || accept.hi < s1 {
-		return RuneError, 1
-	}
-	if sz <= 2 { // <= instead of == to help the compiler eliminate some bounds checks
-		return rune(s0&mask2)<<6 | rune(s1&maskx), 2
-	}
-	s2 := s[2]
-	if s2 < locb || hicb < s2 {
-		return RuneError, 1
-	}
-	if sz <= 3 {
-		return rune(s0&mask3)<<12 | rune(s1&maskx)<<6 | rune(s2&maskx), 3
-	}
-	s3 := s[3]
-	if s3 < locb || hicb < s3 {
-		return RuneError, 1
-	}
-	return rune(s0&mask4)<<18 | rune(s1&maskx)<<12 | rune(s2&maskx)<<6 | rune(s3&maskx), 4
+	f := ffi(ffiUtf8, "unicode.utf8")
+	r, size = f.decode_rune_in_string(s)
+	return
 }
 
 // DecodeLastRune unpacks the last UTF-8 encoding in p and returns the rune and
@@ -408,37 +381,8 @@ func RuneCount(p []byte) int {
 
 // RuneCountInString is like RuneCount but its input is a string.
 func RuneCountInString(s string) (n int) {
-	ns := len(s)
-	for i := 0; i < ns; n++ {
-		c := s[i]
-		if c < RuneSelf {
-			// ASCII fast path
-			i++
-			continue
-		}
-		x := first[c]
-		if x == xx {
-			i++ // invalid.
-			continue
-		}
-		size := int(x & 7)
-		if i+size > ns {
-			i++ // Short or invalid.
-			continue
-		}
-		accept := acceptRanges[x>>4]
-		if c := s[i+1]; c < accept.lo || accept.hi < c {
-			size = 1
-		} else if size == 2 {
-		} else if c := s[i+2]; c < locb || hicb < c {
-			size = 1
-		} else if size == 3 {
-		} else if c := s[i+3]; c < locb || hicb < c {
-			size = 1
-		}
-		i += size
-	}
-	return n
+	f := ffi(ffiUtf8, "unicode.utf8")
+	return f.rune_count_in_string(s)
 }
 
 // RuneStart reports whether the byte could be the first byte of an encoded,
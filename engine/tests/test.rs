@@ -1,6 +1,7 @@
 #[macro_use]
 extern crate time_test;
 extern crate goscript_engine as engine;
+extern crate goscript_vm;
 
 fn run(path: &str, trace: bool) -> usize {
     let cfg = engine::Config {
@@ -9,6 +10,11 @@ fn run(path: &str, trace: bool) -> usize {
         trace_parser: trace,
         trace_checker: trace,
         trace_vm: true,
+        panic_hook: None,
+        go121_panic_nil: true,
+        stdout: None,
+        stderr: None,
+        gc_config: Default::default(),
     };
     let engine = engine::Engine::new(cfg);
     engine.run(path)
@@ -58,12 +64,454 @@ fn test_unicode0() {
     assert!(err_cnt == 0);
 }
 
+#[test]
+fn test_utf8() {
+    let err_cnt = run("./tests/group1/utf8.gos", true);
+    assert!(err_cnt == 0);
+}
+
 #[test]
 fn test_sync_mutex() {
     let err_cnt = run("./tests/group2/sync_mutex.gos", true);
     assert!(err_cnt == 0);
 }
 
+#[test]
+fn test_binary() {
+    let err_cnt = run("./tests/group2/binary.gos", true);
+    assert!(err_cnt == 0);
+}
+
+#[test]
+fn test_hash() {
+    let err_cnt = run("./tests/group2/hash.gos", true);
+    assert!(err_cnt == 0);
+}
+
+#[test]
+fn test_context() {
+    let err_cnt = run("./tests/group2/context.gos", true);
+    assert!(err_cnt == 0);
+}
+
+#[test]
+fn test_fmt() {
+    let err_cnt = run("./tests/group2/fmt.gos", true);
+    assert!(err_cnt == 0);
+}
+
+#[test]
+fn test_panic_hook() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    let got: Rc<RefCell<Vec<(String, bool)>>> = Rc::new(RefCell::new(vec![]));
+    let got2 = got.clone();
+    let cfg = engine::Config {
+        work_dir: Some("./".to_string()),
+        base_path: Some("./std/".to_string()),
+        trace_parser: false,
+        trace_checker: false,
+        trace_vm: true,
+        panic_hook: Some(Box::new(move |info: &engine::PanicInfo| {
+            let deep = info.call_stack.len() > 3;
+            got2.borrow_mut().push((info.message.clone(), deep));
+            info.suppress_default_output();
+        })),
+        go121_panic_nil: true,
+        stdout: None,
+        stderr: None,
+        gc_config: Default::default(),
+    };
+    let engine = engine::Engine::new(cfg);
+    let err_cnt = engine.run("./tests/group2/panic_hook.gos");
+    assert!(err_cnt == 0);
+    assert_eq!(*got.borrow(), vec![("boom".to_string(), true)]);
+}
+
+#[test]
+fn test_panic_nil_legacy() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    let got: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(vec![]));
+    let got2 = got.clone();
+    let cfg = engine::Config {
+        work_dir: Some("./".to_string()),
+        base_path: Some("./std/".to_string()),
+        trace_vm: true,
+        panic_hook: Some(Box::new(move |info: &engine::PanicInfo| {
+            got2.borrow_mut().push(info.message.clone());
+        })),
+        go121_panic_nil: false,
+        ..Default::default()
+    };
+    let engine = engine::Engine::new(cfg);
+    let err_cnt = engine.run("./tests/group2/panic_nil.gos");
+    assert!(err_cnt == 0);
+    assert!(got.borrow().is_empty());
+}
+
+fn run_with_result(path: &str) -> Result<engine::GosValue, engine::GosError> {
+    let cfg = engine::Config {
+        work_dir: Some("./".to_string()),
+        base_path: Some("./std/".to_string()),
+        panic_hook: Some(Box::new(|info: &engine::PanicInfo| {
+            info.suppress_default_output();
+        })),
+        ..Default::default()
+    };
+    let engine = engine::Engine::new(cfg);
+    engine.run_with_result(path)
+}
+
+#[test]
+fn test_run_with_result() {
+    use engine::{GosError, GosValue};
+    assert!(run_with_result("./tests/group1/initorder.gos").is_ok());
+    match run_with_result("./tests/group2/parse_error.gos") {
+        Err(GosError::ParseError(n)) => assert!(n > 0),
+        r => panic!("{:?}", r),
+    }
+    match run_with_result("./tests/group2/check_error.gos") {
+        Err(GosError::CheckError(n)) => assert_eq!(n, 1),
+        r => panic!("{:?}", r),
+    }
+
+    match run_with_result("./tests/group2/panic_str.gos") {
+        Err(GosError::Panic(GosValue::Str(s), backtrace)) => {
+            assert_eq!(s.as_str(), "boom");
+            let frames: Vec<(&str, &str)> = backtrace
+                .iter()
+                .map(|(pkg, func, _)| (pkg.as_str(), func.as_str()))
+                .collect();
+            assert_eq!(
+                frames,
+                vec![("main", "fail"), ("main", "fail"), ("main", "main")]
+            );
+            assert!(backtrace[0].2.ends_with("panic_str.gos:5:9"));
+            assert!(backtrace[2].2.ends_with("panic_str.gos:11:5"));
+        }
+        r => panic!("{:?}", r),
+    }
+
+    match run_with_result("./tests/group2/panic_struct.gos") {
+        Err(GosError::Panic(val, backtrace)) => {
+            let fields = &val.try_get_struct().unwrap().0.borrow().fields;
+            assert_eq!(fields[0], GosValue::Int(42));
+            assert_eq!(fields[1], GosValue::new_str("bad".to_string()));
+            let funcs: Vec<&str> = backtrace.iter().map(|f| f.1.as_str()).collect();
+            assert_eq!(funcs, vec!["(*Error).raise", "main.func2", "main"]);
+        }
+        r => panic!("{:?}", r),
+    }
+}
+
+/// a writer the test can still read after handing it to the engine
+#[derive(Clone, Default)]
+struct SharedBuf(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+impl std::io::Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_captured_output() {
+    let (out, err) = (SharedBuf::default(), SharedBuf::default());
+    let cfg = engine::Config {
+        work_dir: Some("./".to_string()),
+        base_path: Some("./std/".to_string()),
+        stdout: Some(Box::new(out.clone())),
+        stderr: Some(Box::new(err.clone())),
+        ..Default::default()
+    };
+    let engine = engine::Engine::new(cfg);
+    let err_cnt = engine.run("./tests/group2/hello.gos");
+    assert!(err_cnt == 0);
+    assert_eq!(String::from_utf8_lossy(&out.0.borrow()), "hello\n42-x\n");
+    assert_eq!(String::from_utf8_lossy(&err.0.borrow()), "to stderr\n");
+
+    // like Go, the panic messages go to stderr too
+    out.0.borrow_mut().clear();
+    err.0.borrow_mut().clear();
+    let err_cnt = engine.run("./tests/group2/panic_str.gos");
+    assert!(err_cnt == 0);
+    assert!(out.0.borrow().is_empty());
+    let printed = String::from_utf8_lossy(&err.0.borrow()).to_string();
+    assert!(printed.starts_with("panic: boom\n"));
+    assert!(printed.contains("panic_str.gos:5:9\n"));
+}
+
+#[test]
+fn test_panic_in_defer() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    let got: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(vec![]));
+    let got2 = got.clone();
+    let err = SharedBuf::default();
+    let cfg = engine::Config {
+        work_dir: Some("./".to_string()),
+        base_path: Some("./std/".to_string()),
+        stderr: Some(Box::new(err.clone())),
+        panic_hook: Some(Box::new(move |info: &engine::PanicInfo| {
+            let mut got = got2.borrow_mut();
+            got.extend(info.aborted.iter().map(|v| v.to_string()));
+            got.push(info.message.clone());
+        })),
+        ..Default::default()
+    };
+    let engine = engine::Engine::new(cfg);
+    let err_cnt = engine.run("./tests/group2/panic_defer.gos");
+    assert!(err_cnt == 0);
+    assert_eq!(*got.borrow(), vec!["first", "second"]);
+    let printed = String::from_utf8_lossy(&err.0.borrow()).to_string();
+    assert!(printed.starts_with("panic: first\n\tpanic: second\n"));
+}
+
+#[test]
+fn test_run_source() {
+    use engine::GosError;
+    let (out, err) = (SharedBuf::default(), SharedBuf::default());
+    let cfg = engine::Config {
+        work_dir: Some("./".to_string()),
+        base_path: Some("./std/".to_string()),
+        stdout: Some(Box::new(out.clone())),
+        stderr: Some(Box::new(err.clone())),
+        ..Default::default()
+    };
+    let engine = engine::Engine::new(cfg);
+    let err_cnt = engine.run_source("hi.gos", "package main; func main(){ println(\"hi\") }");
+    assert!(err_cnt == 0);
+    assert_eq!(String::from_utf8_lossy(&err.0.borrow()), "hi\n");
+
+    // imports still come from base_path
+    let src = "package main\nimport \"fmt\"\nfunc main() { fmt.Println(40 + 2) }\n";
+    assert!(engine.run_source_with_result("fmt.gos", src).is_ok());
+    assert_eq!(String::from_utf8_lossy(&out.0.borrow()), "42\n");
+
+    match engine.run_source_with_result("bad.gos", "package main; func main() {") {
+        Err(GosError::ParseError(n)) => assert!(n > 0),
+        r => panic!("{:?}", r),
+    }
+}
+
+/// counts how many of its instances are dropped
+struct DropCounter(std::rc::Rc<std::cell::Cell<usize>>);
+
+impl goscript_vm::ffi::Ffi for DropCounter {
+    fn call(
+        &self,
+        func_name: &str,
+        _params: Vec<goscript_vm::value::GosValue>,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = goscript_vm::value::RtMultiValResult> + '_>,
+    > {
+        assert_eq!(func_name, "dropped");
+        let dropped = self.0.get() as isize;
+        Box::pin(async move { Ok(vec![goscript_vm::value::GosValue::Int(dropped)]) })
+    }
+}
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+#[test]
+fn test_gc_config() {
+    let src = r#"
+package main
+
+type counter interface {
+    dropped() int
+}
+
+type node struct {
+    next *node
+    c counter
+}
+
+func cycle() {
+    a := &node{c: ffi(counter, "drop_counter")}
+    a.next = &node{next: a}
+}
+
+func main() {
+    c := ffi(counter, "drop_counter")
+    for i := 0; i < 1000; i++ {
+        cycle()
+    }
+    println(c.dropped() > 0)
+}
+"#;
+    let run = |gc_config: engine::GcConfig| {
+        let err = SharedBuf::default();
+        let dropped = std::rc::Rc::new(std::cell::Cell::new(0));
+        let cfg = engine::Config {
+            work_dir: Some("./".to_string()),
+            base_path: Some("./std/".to_string()),
+            stderr: Some(Box::new(err.clone())),
+            gc_config: gc_config,
+            ..Default::default()
+        };
+        let mut engine = engine::Engine::new(cfg);
+        let counter = dropped.clone();
+        engine.register_extension(
+            "drop_counter",
+            Box::new(move |_| {
+                Ok(std::rc::Rc::new(std::cell::RefCell::new(DropCounter(
+                    counter.clone(),
+                ))))
+            }),
+        );
+        assert!(engine.run_source("gc.gos", src) == 0);
+        let printed = String::from_utf8_lossy(&err.0.borrow()).to_string();
+        (engine, dropped, printed)
+    };
+
+    // the cycles are kept until the host collects them
+    let manual = engine::GcConfig {
+        cycle_collect_threshold: 10,
+        auto: false,
+    };
+    let (engine, dropped, printed) = run(manual);
+    assert_eq!(printed, "false\n");
+    // only the counter outside of the cycles is gone
+    assert_eq!(dropped.get(), 1);
+    engine.collect_garbage();
+    assert_eq!(dropped.get(), 1001);
+
+    // a low threshold collects them while the program is running
+    let auto = engine::GcConfig {
+        cycle_collect_threshold: 10,
+        auto: true,
+    };
+    let (_, dropped, printed) = run(auto);
+    assert_eq!(printed, "true\n");
+    assert_eq!(dropped.get(), 1001);
+}
+
+/// returns its results with ffi_return!
+struct Pairs;
+
+impl goscript_vm::ffi::Ffi for Pairs {
+    fn call(
+        &self,
+        func_name: &str,
+        params: Vec<goscript_vm::value::GosValue>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = goscript_vm::ffi::FfiResult> + '_>>
+    {
+        use goscript_vm::ffi_return;
+        use goscript_vm::value::GosValue;
+        let n = *params[0].as_int();
+        match func_name {
+            "pair" => ffi_return!(
+                GosValue::Int(n + 1),
+                GosValue::new_str("x".repeat(n as usize))
+            ),
+            // one value short of the signature
+            "short" => ffi_return!(GosValue::Int(n)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn test_ffi_multiple_returns() {
+    use engine::{GosError, GosValue};
+    let src = r#"
+package main
+
+type pairs interface {
+    pair(n int) (int, string)
+    short(n int) (int, string)
+}
+
+func main() {
+    p := ffi(pairs, "pairs")
+    n, s := p.pair(2)
+    assert(n == 3)
+    assert(s == "xx")
+    _, s = p.pair(0)
+    assert(s == "")
+    p.short(1)
+}
+"#;
+    let out = SharedBuf::default();
+    let cfg = engine::Config {
+        work_dir: Some("./".to_string()),
+        base_path: Some("./std/".to_string()),
+        stdout: Some(Box::new(out.clone())),
+        ..Default::default()
+    };
+    let mut engine = engine::Engine::new(cfg);
+    engine.register_extension(
+        "pairs",
+        Box::new(|_| Ok(std::rc::Rc::new(std::cell::RefCell::new(Pairs)))),
+    );
+    match engine.run_source_with_result("pairs.gos", src) {
+        Err(GosError::Panic(GosValue::Str(s), _)) => {
+            assert_eq!(s.as_str(), "ffi short returned 1 values, 2 expected")
+        }
+        r => panic!("{:?}", r),
+    }
+}
+
+/// panics in Rust as a buggy extension would
+struct Crasher;
+
+impl goscript_vm::ffi::Ffi for Crasher {
+    fn call(
+        &self,
+        _func_name: &str,
+        _params: Vec<goscript_vm::value::GosValue>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = goscript_vm::ffi::FfiResult> + '_>>
+    {
+        panic!("crashed")
+    }
+}
+
+#[test]
+fn test_vm_error() {
+    use engine::GosError;
+    let src = r#"
+package main
+
+type crasher interface {
+    crash()
+}
+
+func main() {
+    defer func() {
+        recover()
+    }()
+    ffi(crasher, "crasher").crash()
+}
+"#;
+    let cfg = engine::Config {
+        work_dir: Some("./".to_string()),
+        base_path: Some("./std/".to_string()),
+        ..Default::default()
+    };
+    let mut engine = engine::Engine::new(cfg);
+    engine.register_extension(
+        "crasher",
+        Box::new(|_| Ok(std::rc::Rc::new(std::cell::RefCell::new(Crasher)))),
+    );
+    match engine.run_source_with_result("crash.gos", src) {
+        Err(GosError::VmError(msg)) => assert_eq!(msg, "crashed"),
+        r => panic!("{:?}", r),
+    }
+    // the engine can still run programs
+    let src = "package main; func main() {}";
+    assert!(engine.run_source_with_result("ok.gos", src).is_ok());
+}
+
 #[test]
 fn test_g1case1() {
     let err_cnt = run("./tests/group1/case1.gos", true);
@@ -118,6 +566,12 @@ fn test_slice1() {
     assert!(err_cnt == 0);
 }
 
+#[test]
+fn test_print() {
+    let err_cnt = run("./tests/group1/print.gos", true);
+    assert!(err_cnt == 0);
+}
+
 #[test]
 fn test_map1() {
     let err_cnt = run("./tests/group1/map1.gos", true);
@@ -130,6 +584,12 @@ fn test_pkg() {
     assert!(err_cnt == 0);
 }
 
+#[test]
+fn test_samename() {
+    let err_cnt = run("./tests/group1/samename.gos", true);
+    assert!(err_cnt == 0);
+}
+
 #[test]
 fn test_type() {
     let err_cnt = run("./tests/group1/type.gos", true);
@@ -166,6 +626,18 @@ fn test_interface() {
     assert!(err_cnt == 0);
 }
 
+#[test]
+fn test_method_expr() {
+    let err_cnt = run("./tests/group1/methodexpr.gos", true);
+    assert!(err_cnt == 0);
+}
+
+#[test]
+fn test_ifacecompare() {
+    let err_cnt = run("./tests/group1/ifacecompare.gos", true);
+    assert!(err_cnt == 0);
+}
+
 #[test]
 fn test_interface2() {
     let err_cnt = run("./tests/group1/interface2.gos", true);
@@ -196,6 +668,24 @@ fn test_basictypes() {
     assert!(err_cnt == 0);
 }
 
+#[test]
+fn test_hashkey() {
+    let err_cnt = run("./tests/group1/hashkey.gos", true);
+    assert!(err_cnt == 0);
+}
+
+#[test]
+fn test_untyped() {
+    let err_cnt = run("./tests/group1/untyped.gos", true);
+    assert!(err_cnt == 0);
+}
+
+#[test]
+fn test_assign() {
+    let err_cnt = run("./tests/group1/assign.gos", true);
+    assert!(err_cnt == 0);
+}
+
 #[test]
 fn test_composite() {
     let err_cnt = run("./tests/group1/composite.gos", true);
@@ -272,6 +762,24 @@ fn test_fibonacci() {
     assert!(err_cnt == 0);
 }
 
+#[test]
+fn test_array_eq() {
+    let err_cnt = run("./tests/demo/array_eq.gos", true);
+    assert!(err_cnt == 0);
+}
+
+#[test]
+fn test_bool() {
+    let err_cnt = run("./tests/demo/bool.gos", true);
+    assert!(err_cnt == 0);
+}
+
+#[test]
+fn test_struct_param() {
+    let err_cnt = run("./tests/demo/struct_param.gos", true);
+    assert!(err_cnt == 0);
+}
+
 #[test]
 fn test_issue8() {
     time_test!();
@@ -279,3 +787,58 @@ fn test_issue8() {
     let err_cnt = run("./tests/issues/issue8.gos", true);
     assert!(err_cnt == 0);
 }
+
+#[test]
+fn test_func_positions() {
+    use goscript_parser::{errors::ErrorList, FileSet};
+    use goscript_vm::instruction::Opcode;
+    use goscript_vm::value::GosValue;
+
+    let path = "./tests/group1/initorder.gos";
+    let config = goscript_types::Config {
+        work_dir: Some("./".to_string()),
+        base_path: Some("./std/".to_string()),
+        trace_parser: false,
+        trace_checker: false,
+    };
+    let mut fs = FileSet::new();
+    let el = ErrorList::new();
+    let bc = goscript_codegen::entry::parse_check_gen(path, &config, &mut fs, &el).unwrap();
+    let objs = &bc.objects;
+    let main = bc
+        .packages
+        .iter()
+        .find_map(|key| {
+            let pkg = &objs.packages[*key];
+            let index = pkg.get_member_index("main")?;
+            match &*pkg.member(*index) {
+                GosValue::Closure(c) => c.0.borrow().func,
+                _ => None,
+            }
+        })
+        .unwrap();
+    let func = &objs.functions[main];
+    assert_eq!(func.instruction_count(), func.code().len());
+    assert_eq!(func.pos_at(func.instruction_count()), None);
+    assert_eq!(func.pc_pos(0), None);
+
+    // the first assert in main is recorded at the start of the statement
+    let src = std::fs::read_to_string(path).unwrap();
+    let main_offset = src.find("func main()").unwrap();
+    let offset = main_offset + src[main_offset..].find("assert(b == 16)").unwrap();
+    let index = func
+        .code()
+        .iter()
+        .position(|i| i.op() == Opcode::ASSERT)
+        .unwrap();
+    assert_eq!(func.pc_pos(index + 1), func.pos_at(index));
+    let pos = func.position(index, &fs).unwrap();
+    assert_eq!(pos.offset, offset);
+    let line = src[..offset].matches('\n').count() + 1;
+    let column = offset - src[..offset].rfind('\n').unwrap();
+    assert_eq!(pos.line, line);
+    assert_eq!(pos.column, column);
+    assert!(pos
+        .to_string()
+        .ends_with(&format!("initorder.gos:{}:{}", line, column)));
+}
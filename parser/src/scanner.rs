@@ -278,6 +278,14 @@ impl<'a> Scanner<'a> {
         let result = self.scan_digits(&mut literal, valid, true);
         match result {
             Ok(count) => {
+                if ch == 'x' {
+                    match self.peek_char() {
+                        Some('.') | Some('p') | Some('P') => {
+                            return self.scan_hex_float_and_finish(literal, count)
+                        }
+                        _ => {}
+                    }
+                }
                 if count == 0 {
                     self.error(err);
                     return Token::ILLEGAL(literal.into());
@@ -304,9 +312,17 @@ impl<'a> Scanner<'a> {
             return Token::ILLEGAL(literal.into());
         }
         match self.peek_char() {
-            Some('e') | Some('E') => self.scan_exponent_and_finish(literal),
+            Some('e') | Some('E') => self.scan_exponent_and_finish(literal, 'e'),
             Some('.') => self.scan_fraction_and_finish(literal),
-            _ => Token::INT(literal.into()),
+            // 089i is a decimal imaginary literal, but 089 is an invalid octal one
+            Some('i') => Token::INT(literal.into()),
+            _ => {
+                if literal.starts_with('0') && literal.contains(['8', '9']) {
+                    self.error("invalid digit in octal literal");
+                    return Token::ILLEGAL(literal.into());
+                }
+                Token::INT(literal.into())
+            }
         }
     }
 
@@ -610,13 +626,38 @@ impl<'a> Scanner<'a> {
             return Token::ILLEGAL(lit.into());
         }
         match self.peek_char() {
-            Some('e') | Some('E') => self.scan_exponent_and_finish(lit),
+            Some('e') | Some('E') => self.scan_exponent_and_finish(lit, 'e'),
             _ => Token::FLOAT(lit.into()),
         }
     }
 
-    fn scan_exponent_and_finish(&mut self, mut lit: String) -> Token {
-        self.advance_and_push(&mut lit, 'e');
+    // 0x1.8p1, the hexadecimal mantissa before the fraction has been scanned
+    fn scan_hex_float_and_finish(&mut self, mut lit: String, mut count: usize) -> Token {
+        if let Some('.') = self.peek_char() {
+            self.advance_and_push(&mut lit, '.');
+            match self.scan_digits(&mut lit, is_hex, false) {
+                Ok(n) => count += n,
+                Err(e) => {
+                    self.error(e);
+                    return Token::ILLEGAL(lit.into());
+                }
+            }
+        }
+        if count == 0 {
+            self.error("hexadecimal literal has no digits");
+            return Token::ILLEGAL(lit.into());
+        }
+        match self.peek_char() {
+            Some('p') | Some('P') => self.scan_exponent_and_finish(lit, 'p'),
+            _ => {
+                self.error("hexadecimal mantissa requires a 'p' exponent");
+                Token::ILLEGAL(lit.into())
+            }
+        }
+    }
+
+    fn scan_exponent_and_finish(&mut self, mut lit: String, exp: char) -> Token {
+        self.advance_and_push(&mut lit, exp);
         match self.peek_char() {
             Some(&ch) if ch == '+' || ch == '-' => {
                 self.advance_and_push(&mut lit, ch);
@@ -857,4 +898,39 @@ mod test {
         }
         print!("\n<- {} ->\n", err);
     }
+
+    #[test]
+    fn test_scan_number_literals() {
+        let mut fs = FileSet::new();
+        let f = fs.add_file("testfile2.gs".to_string(), None, 1000);
+        let src = "0xA_B 0o7_7 0_7 1_2.5 0x1.8p1 0x.8P-1 089i 0b1i 08 0x1.8 0x1p 0b12";
+        let err = errors::ErrorList::new();
+        let mut scanner = Scanner::new(f, src, &err);
+        let mut toks = vec![];
+        loop {
+            let (tok, _) = scanner.scan();
+            match tok {
+                Token::EOF => break,
+                Token::SEMICOLON(_) => {}
+                _ => toks.push(format!("{}:{}", tok.text(), tok.get_literal())),
+            }
+        }
+        let expected = vec![
+            "INT:0xA_B",
+            "INT:0o7_7",
+            "INT:0_7",
+            "FLOAT:1_2.5",
+            "FLOAT:0x1.8p1",
+            "FLOAT:0x.8p-1",
+            "IMAG:089i",
+            "IMAG:0b1i",
+            "ILLEGAL:",
+            "ILLEGAL:",
+            "ILLEGAL:",
+            "ILLEGAL:",
+            "INT:2",
+        ];
+        assert_eq!(toks, expected);
+        assert_eq!(err.len(), 4);
+    }
 }
@@ -243,6 +243,21 @@ impl<'a> Checker<'a> {
                     _ => record(self, x.typ, &vec![ty], false),
                 }
             }
+            Builtin::Clear => {
+                // clear(m) or clear(s)
+                let tkey = typ::underlying_type(x.typ.unwrap(), self.tc_objs);
+                match self.otype(tkey) {
+                    Type::Map(_) | Type::Slice(_) => {
+                        x.mode = OperandMode::NoValue;
+                        record(self, None, &vec![x.typ.unwrap()], false);
+                    }
+                    _ => {
+                        let dis = self.new_dis(x);
+                        self.invalid_arg(dis.pos(), &format!("{} is not a map or slice", dis));
+                        return false;
+                    }
+                }
+            }
             Builtin::Close => {
                 // close(c)
                 let tkey = typ::underlying_type(x.typ.unwrap(), self.tc_objs);
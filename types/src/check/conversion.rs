@@ -63,7 +63,10 @@ impl<'a> Checker<'a> {
             //   not []byte as type for the constant "foo").
             // - Keep untyped nil for untyped nil arguments.
             // - For integer to string conversions, keep the argument type.
-            let final_t = if typ::is_interface(t, o) || const_arg && !typ::is_const_type(t, o) {
+            let final_t = if typ::is_interface(t, o)
+                || const_arg && !typ::is_const_type(t, o)
+                || x.is_nil(o.universe())
+            {
                 typ::untyped_default_type(xtype, o)
             } else if typ::is_integer(xtype, o) && typ::is_string(t, o) {
                 xtype
@@ -101,11 +101,23 @@ impl Value {
             Token::FLOAT(flit) => float_from_literal(flit.as_str()),
             Token::IMAG(imlit) => {
                 let s = imlit.as_str();
-                let v = float_from_literal(&s[..(s.len() - 1)]);
-                if let Value::Float(_) = &v {
-                    Value::Complex(Box::new(Value::with_f64(0.0)), Box::new(v))
+                let s = &s[..(s.len() - 1)];
+                // 0123i is decimal for backward compatibility, but 0o123i is octal
+                let is_int = match s.get(..2) {
+                    Some("0b") | Some("0o") => true,
+                    Some("0x") => !s.contains('p'),
+                    _ => false,
+                };
+                let v = if is_int {
+                    int_from_literal(s)
                 } else {
-                    Value::Unknown
+                    float_from_literal(s)
+                };
+                match &v {
+                    Value::Int(_) | Value::Float(_) => {
+                        Value::Complex(Box::new(Value::with_f64(0.0)), Box::new(v))
+                    }
+                    _ => Value::Unknown,
                 }
             }
             Token::CHAR(clit) => {
@@ -674,7 +686,7 @@ impl Value {
     }
 
     pub fn complex_as_complex64(&self) -> (F32, F32, bool) {
-        match self {
+        match self.to_complex() {
             Value::Complex(r, i) => {
                 let (num_r, exact_r) = r.num_as_f32();
                 let (num_i, exact_i) = i.num_as_f32();
@@ -685,7 +697,7 @@ impl Value {
     }
 
     pub fn complex_as_complex128(&self) -> (F64, F64, bool) {
-        match self {
+        match self.to_complex() {
             Value::Complex(r, i) => {
                 let (num_r, exact_r) = r.num_as_f64();
                 let (num_i, exact_i) = i.num_as_f64();
@@ -779,28 +791,45 @@ pub fn short_quote_str(s: &str, max: usize) -> String {
 }
 
 pub fn int_from_literal(lit: &str) -> Value {
-    let result = if lit.starts_with("0x") {
-        BigInt::from_str_radix(&lit[2..], 16)
-    } else if lit.starts_with("0o") {
-        BigInt::from_str_radix(&lit[2..], 10)
-    } else if lit.starts_with("0b") {
-        BigInt::from_str_radix(&lit[2..], 2)
-    } else {
-        BigInt::from_str_radix(lit, 10)
+    let lit = lit.replace('_', "");
+    let (digits, radix) = match lit.get(..2) {
+        Some("0x") => (&lit[2..], 16),
+        Some("0o") => (&lit[2..], 8),
+        Some("0b") => (&lit[2..], 2),
+        // the legacy octal form, 017
+        _ if lit.len() > 1 && lit.starts_with('0') => (&lit[1..], 8),
+        _ => (&lit[..], 10),
     };
-    match result {
+    match BigInt::from_str_radix(digits, radix) {
         Ok(i) => Value::Int(i),
         Err(_) => Value::Unknown,
     }
 }
 
 pub fn float_from_literal(lit: &str) -> Value {
-    match lit.parse::<f64>() {
-        Ok(f) => Value::with_f64(f),
-        Err(_) => Value::Unknown,
+    let lit = lit.replace('_', "");
+    let f = match lit.strip_prefix("0x") {
+        Some(hex) => hex_float_from_literal(hex),
+        None => lit.parse::<f64>().ok(),
+    };
+    match f {
+        Some(f) => Value::with_f64(f),
+        None => Value::Unknown,
     }
 }
 
+/// the value of a hexadecimal float without the 0x, like 1.8p1
+fn hex_float_from_literal(lit: &str) -> Option<f64> {
+    let (mantissa, exp) = lit.split_at(lit.find('p')?);
+    let exp: i32 = exp[1..].parse().ok()?;
+    let (int, frac) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], &mantissa[(i + 1)..]),
+        None => (mantissa, ""),
+    };
+    let m = BigInt::from_str_radix(&format!("{}{}", int, frac), 16).ok()?;
+    Some(m.to_f64()? * 2f64.powi(exp - 4 * frac.len() as i32))
+}
+
 fn shorten_with_ellipsis(s: String, max: usize) -> String {
     if s.len() <= max {
         s
@@ -826,4 +855,36 @@ mod test {
         let s = "\\111";
         dbg!(s);
     }
+
+    #[test]
+    fn test_number_literal_values() {
+        use super::*;
+        let int = |lit| int_from_literal(lit).int_as_i64().0;
+        assert_eq!(int("0x1f"), 31);
+        assert_eq!(int("0x_1F"), 31);
+        assert_eq!(int("0b1010"), 10);
+        assert_eq!(int("0o17"), 15);
+        assert_eq!(int("017"), 15);
+        assert_eq!(int("0_17"), 15);
+        assert_eq!(int("1_000"), 1000);
+        assert_eq!(int("0"), 0);
+
+        let float = |lit| float_from_literal(lit).num_as_f64().0 .0;
+        assert_eq!(float("1_000.5"), 1000.5);
+        assert_eq!(float("1e1_0"), 1e10);
+        assert_eq!(float("0x1p-2"), 0.25);
+        assert_eq!(float("0x_1.8p1"), 3.0);
+        assert_eq!(float("0x.8p1"), 1.0);
+        assert_eq!(float("0123.5"), 123.5);
+
+        let imag = |lit: &str| {
+            let tok = Token::IMAG(lit.to_string().into());
+            Value::with_literal(&tok).complex_as_complex128().1 .0
+        };
+        assert_eq!(imag("017i"), 17.0);
+        assert_eq!(imag("0o17i"), 15.0);
+        assert_eq!(imag("0x10i"), 16.0);
+        assert_eq!(imag("0x1p-2i"), 0.25);
+        assert_eq!(imag("1_0i"), 10.0);
+    }
 }
@@ -100,6 +100,22 @@ impl<'a> Importer<'a> {
         let pkg = self.tc_objs.new_package(import_path.clone());
         self.pkgs.insert(import_path, pkg);
         let files = self.parse_dir(path)?;
+        self.check(pkg, files)
+    }
+
+    /// import_source imports the package of the single file `src`, without reading
+    /// it from the file system. `name` is the file name shown in diagnostics, and
+    /// the imports are resolved as if the file were in the working dir.
+    /// On failure it returns the number of errors in the error list.
+    pub fn import_source(&mut self, name: &str, src: &str) -> Result<PackageKey, usize> {
+        let pkg = self.tc_objs.new_package(name.to_string());
+        self.pkgs.insert(name.to_string(), pkg);
+        self.parse_file(name.to_string(), src)
+            .and_then(|file| self.check(pkg, vec![file]))
+            .map_err(|_| self.errors.len())
+    }
+
+    fn check(&mut self, pkg: PackageKey, files: Vec<ast::File>) -> Result<PackageKey, ()> {
         Checker::new(
             self.tc_objs,
             self.ast_objs,
@@ -177,26 +193,7 @@ impl<'a> Importer<'a> {
                         }
                         .to_string_lossy()
                         .to_string();
-                        let mut pfile = self.fset.add_file(
-                            full_name,
-                            Some(self.fset.base()),
-                            content.chars().count(),
-                        );
-                        let afile = Parser::new(
-                            self.ast_objs,
-                            &mut pfile,
-                            self.errors,
-                            &content,
-                            self.config.trace_parser,
-                        )
-                        .parse_file();
-                        if afile.is_none() {
-                            // parse error, the details should be in the errorlist already.
-                            // give up
-                            return Err(());
-                        } else {
-                            afiles.push(afile.unwrap());
-                        }
+                        afiles.push(self.parse_file(full_name, &content)?);
                     }
                     Ok(afiles)
                 }
@@ -208,6 +205,22 @@ impl<'a> Importer<'a> {
         }
     }
 
+    fn parse_file(&mut self, name: String, content: &str) -> Result<ast::File, ()> {
+        let mut pfile = self
+            .fset
+            .add_file(name, Some(self.fset.base()), content.chars().count());
+        // on parse errors, the details should be in the errorlist already
+        Parser::new(
+            self.ast_objs,
+            &mut pfile,
+            self.errors,
+            content,
+            self.config.trace_parser,
+        )
+        .parse_file()
+        .ok_or(())
+    }
+
     fn error(&self, err: String) {
         let pos_file = self.fset.file(self.pos).unwrap();
         FilePosErrors::new(pos_file, self.errors).add(self.pos, err, false);
@@ -29,8 +29,10 @@ mod importer;
 pub use check::{DeclInfo, TypeInfo};
 pub use constant::Value as ConstValue;
 pub use importer::{Config, ImportKey, Importer};
+pub use lookup::{lookup_field_or_method, LookupResult};
 pub use obj::EntityType;
 pub use objects::{DeclInfoKey, ObjKey, PackageKey, ScopeKey, TCObjects, TypeKey};
 pub use operand::OperandMode;
+pub use selection::SelectionKind;
 pub use typ::{identical, BasicType, ChanDir, Type};
 pub use universe::{Builtin, Universe};
@@ -22,6 +22,7 @@ pub enum ExprKind {
 pub enum Builtin {
     Append,
     Cap,
+    Clear,
     Close,
     Complex,
     Copy,
@@ -331,6 +332,7 @@ impl Universe {
             // use vec becasue array doesn't have into_iter()!
             (Builtin::Append, "append", 1, true, ExprKind::Expression),
             (Builtin::Cap, "cap", 1, false, ExprKind::Expression),
+            (Builtin::Clear, "clear", 1, false, ExprKind::Statement),
             (Builtin::Close, "close", 1, false, ExprKind::Statement),
             (Builtin::Complex, "complex", 2, false, ExprKind::Expression),
             (Builtin::Copy, "copy", 2, false, ExprKind::Statement),
@@ -90,7 +90,8 @@ fn test_file(path: &str, trace: bool) {
         if e.msg.starts_with('\t') || e.by_parser {
             continue;
         }
-        if let Some(errs) = expected_errs.get_mut(&e.pos.line) {
+        let key = (file_name(e.pos.filename.as_str()), e.pos.line);
+        if let Some(errs) = expected_errs.get_mut(&key) {
             let mut found = false;
             for info in errs.iter_mut() {
                 if !info.checked {
@@ -121,17 +122,28 @@ fn test_file(path: &str, trace: bool) {
     }
 }
 
-fn parse_comment_errors<P>(path: P) -> io::Result<HashMap<usize, Vec<ErrInfo>>>
+fn file_name<P: AsRef<Path>>(path: P) -> String {
+    path.as_ref()
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .to_string()
+}
+
+// the expected errors by file name and line, the files of a package dir would
+// otherwise take each other's errors on the same lines
+fn parse_comment_errors<P>(path: P) -> io::Result<HashMap<(String, usize), Vec<ErrInfo>>>
 where
     P: AsRef<Path>,
 {
     let mut result = HashMap::new();
-    let mut parse_file = |lines: io::Lines<io::BufReader<File>>| -> io::Result<()> {
-        for (i, x) in lines.enumerate() {
+    let mut parse_file = |path: &Path| -> io::Result<()> {
+        let name = file_name(path);
+        for (i, x) in read_lines(path)?.enumerate() {
             let t = x?;
             let mut errors = parse_error(&t, i + 1)?;
             if !errors.is_empty() {
-                let entry = result.entry(i + 1).or_insert(vec![]);
+                let entry = result.entry((name.clone(), i + 1)).or_insert(vec![]);
                 entry.append(&mut errors);
             }
         }
@@ -139,15 +151,13 @@ where
     };
 
     if path.as_ref().is_file() {
-        let lines = read_lines(path)?;
-        parse_file(lines)?;
+        parse_file(path.as_ref())?;
     } else if path.as_ref().is_dir() {
         for entry in fs::read_dir(path)? {
             let entry = entry?;
             let path = entry.path();
             if !path.is_dir() {
-                let lines = read_lines(path)?;
-                parse_file(lines)?;
+                parse_file(&path)?;
             }
         }
     }
@@ -2,10 +2,12 @@ use super::instruction::*;
 use super::value::*;
 use futures_lite::future;
 use rand::prelude::*;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::mem;
 use std::rc::Rc;
 
+pub const SEND_ON_CLOSED_MSG: &str = "send on closed channel";
+
 #[derive(Clone, Debug)]
 pub enum RendezvousState {
     Empty,
@@ -13,25 +15,45 @@ pub enum RendezvousState {
     Closed,
 }
 
+/// the single slot of an unbuffered channel
+#[derive(Debug)]
+pub struct Rendezvous {
+    state: RefCell<RendezvousState>,
+    // the count of receivers parked in recv, a non-blocking send only
+    // succeeds when one of them is waiting
+    waiting: Cell<usize>,
+    // the counts of values put into and taken out of the slot, a blocking
+    // sender waits until the value it put is taken
+    put: Cell<usize>,
+    taken: Cell<usize>,
+}
+
 #[derive(Clone, Debug)]
 pub enum Channel {
     Bounded(
         async_channel::Sender<GosValue>,
         async_channel::Receiver<GosValue>,
     ),
-    Rendezvous(Rc<RefCell<RendezvousState>>),
+    Rendezvous(Rc<Rendezvous>),
 }
 
 impl Channel {
     pub fn new(cap: usize) -> Channel {
         if cap == 0 {
-            Channel::Rendezvous(Rc::new(RefCell::new(RendezvousState::Empty)))
+            Channel::Rendezvous(Rc::new(Rendezvous {
+                state: RefCell::new(RendezvousState::Empty),
+                waiting: Cell::new(0),
+                put: Cell::new(0),
+                taken: Cell::new(0),
+            }))
         } else {
             let (s, r) = async_channel::bounded(cap);
             Channel::Bounded(s, r)
         }
     }
 
+    /// only the values sitting in the buffer are counted, senders parked on
+    /// a full or an unbuffered channel are not
     #[inline]
     pub fn len(&self) -> usize {
         match self {
@@ -54,41 +76,37 @@ impl Channel {
             Channel::Bounded(s, _) => {
                 s.close();
             }
-            Channel::Rendezvous(state) => *state.borrow_mut() = RendezvousState::Closed,
+            Channel::Rendezvous(r) => *r.state.borrow_mut() = RendezvousState::Closed,
         }
     }
 
-    pub fn try_send(&self, v: GosValue) -> Result<(), async_channel::TrySendError<GosValue>> {
+    /// try_send sends without blocking, it fails with Full if the send would
+    /// block, that is if the buffer is full, or for an unbuffered channel, if
+    /// there is no receiver waiting
+    pub fn try_send(&self, v: &GosValue) -> Result<(), async_channel::TrySendError<GosValue>> {
         match self {
-            Channel::Bounded(s, _) => s.try_send(v),
-            Channel::Rendezvous(state) => {
-                let state_ref = state.borrow();
-                let s: &RendezvousState = &state_ref;
-                match s {
-                    RendezvousState::Empty => {
-                        drop(state_ref);
-                        *state.borrow_mut() = RendezvousState::Full(v);
-                        Ok(())
-                    }
-                    RendezvousState::Full(_) => Err(async_channel::TrySendError::Full(v)),
-                    RendezvousState::Closed => Err(async_channel::TrySendError::Closed(v)),
-                }
+            Channel::Rendezvous(r) if r.waiting.get() == 0 => {
+                Err(async_channel::TrySendError::Full(v.clone()))
             }
+            _ => self.try_put(v.clone()),
         }
     }
 
-    pub fn try_recv(&self) -> Result<GosValue, async_channel::TryRecvError> {
-        match self {
+    /// try_recv receives without blocking, it returns None if the channel is
+    /// closed and drained, and fails with Empty if the receive would block
+    pub fn try_recv(&self) -> Result<Option<GosValue>, async_channel::TryRecvError> {
+        let re = match self {
             Channel::Bounded(_, r) => r.try_recv(),
-            Channel::Rendezvous(state) => {
-                let state_ref = state.borrow();
+            Channel::Rendezvous(r) => {
+                let state_ref = r.state.borrow();
                 let s: &RendezvousState = &state_ref;
                 match s {
                     RendezvousState::Empty => Err(async_channel::TryRecvError::Empty),
                     RendezvousState::Full(_) => {
                         drop(state_ref);
-                        let cur_state: &mut RendezvousState = &mut state.borrow_mut();
+                        let cur_state: &mut RendezvousState = &mut r.state.borrow_mut();
                         let full = mem::replace(cur_state, RendezvousState::Empty);
+                        r.taken.set(r.taken.get() + 1);
                         if let RendezvousState::Full(v) = full {
                             Ok(v)
                         } else {
@@ -98,19 +116,72 @@ impl Channel {
                     RendezvousState::Closed => Err(async_channel::TryRecvError::Closed),
                 }
             }
+        };
+        match re {
+            Ok(v) => Ok(Some(v)),
+            Err(async_channel::TryRecvError::Closed) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// try_put is the first step of a blocking send, it leaves the value in
+    /// an unbuffered channel for a receiver to come and take it
+    fn try_put(&self, v: GosValue) -> Result<(), async_channel::TrySendError<GosValue>> {
+        match self {
+            Channel::Bounded(s, _) => s.try_send(v),
+            Channel::Rendezvous(r) => {
+                let state_ref = r.state.borrow();
+                let s: &RendezvousState = &state_ref;
+                match s {
+                    RendezvousState::Empty => {
+                        drop(state_ref);
+                        *r.state.borrow_mut() = RendezvousState::Full(v);
+                        r.put.set(r.put.get() + 1);
+                        Ok(())
+                    }
+                    RendezvousState::Full(_) => Err(async_channel::TrySendError::Full(v)),
+                    RendezvousState::Closed => Err(async_channel::TrySendError::Closed(v)),
+                }
+            }
+        }
+    }
+
+    /// the second step of a blocking send, on an unbuffered channel it waits
+    /// until a receiver takes the value just put
+    async fn wait_taken(&self) -> RtEmptyResult {
+        if let Channel::Rendezvous(r) = self {
+            let ticket = r.put.get();
+            while r.taken.get() < ticket {
+                if let RendezvousState::Closed = &*r.state.borrow() {
+                    return Err(SEND_ON_CLOSED_MSG.to_string());
+                }
+                future::yield_now().await;
+            }
+        }
+        Ok(())
+    }
+
+    /// counts a receiver in or out of the ones waiting on an unbuffered channel
+    fn set_receiver_waiting(&self, on: bool) {
+        if let Channel::Rendezvous(r) = self {
+            if on {
+                r.waiting.set(r.waiting.get() + 1);
+            } else {
+                r.waiting.set(r.waiting.get() - 1);
+            }
         }
     }
 
     pub async fn send(&self, v: &GosValue) -> RtEmptyResult {
         loop {
-            match self.try_send(v.clone()) {
-                Ok(()) => return Ok(()),
+            match self.try_put(v.clone()) {
+                Ok(()) => return self.wait_taken().await,
                 Err(e) => match e {
                     async_channel::TrySendError::Full(_) => {
                         future::yield_now().await;
                     }
                     async_channel::TrySendError::Closed(_) => {
-                        return Err("channel closed!".to_string());
+                        return Err(SEND_ON_CLOSED_MSG.to_string());
                     }
                 },
             }
@@ -120,13 +191,12 @@ impl Channel {
     pub async fn recv(&self) -> Option<GosValue> {
         loop {
             match self.try_recv() {
-                Ok(v) => return Some(v),
-                Err(e) => match e {
-                    async_channel::TryRecvError::Empty => {
-                        future::yield_now().await;
-                    }
-                    async_channel::TryRecvError::Closed => return None,
-                },
+                Ok(v) => return v,
+                Err(_) => {
+                    self.set_receiver_waiting(true);
+                    future::yield_now().await;
+                    self.set_receiver_waiting(false);
+                }
             }
         }
     }
@@ -162,22 +232,22 @@ impl Selector {
             {
                 match entry {
                     SelectComm::Send(c, val, _) => {
-                        match c.as_channel().chan.try_send(val.clone()) {
+                        // a send case is only chosen when it can go through now,
+                        // for an unbuffered channel a receiver must be waiting,
+                        // otherwise the select would block on this case alone
+                        match c.as_channel().chan.try_send(val) {
                             Ok(_) => return Ok((i, None)),
                             Err(e) => match e {
                                 async_channel::TrySendError::Full(_) => {}
                                 async_channel::TrySendError::Closed(_) => {
-                                    return Err("channel closed!".to_string());
+                                    return Err(SEND_ON_CLOSED_MSG.to_string());
                                 }
                             },
                         }
                     }
                     SelectComm::Recv(c, _, _) => match c.as_channel().chan.try_recv() {
-                        Ok(v) => return Ok((i, Some(v))),
-                        Err(e) => match e {
-                            async_channel::TryRecvError::Empty => {}
-                            async_channel::TryRecvError::Closed => return Ok((i, None)),
-                        },
+                        Ok(v) => return Ok((i, v)),
+                        Err(_) => {}
                     },
                 }
             }
@@ -185,7 +255,18 @@ impl Selector {
             if let Some(_) = self.default_offset {
                 return Ok((self.comms.len(), None));
             }
+            // waits as a receiver on all the receiving cases
+            self.set_receivers_waiting(true);
             future::yield_now().await;
+            self.set_receivers_waiting(false);
+        }
+    }
+
+    fn set_receivers_waiting(&self, on: bool) {
+        for entry in self.comms.iter() {
+            if let SelectComm::Recv(c, _, _) = entry {
+                c.as_channel().chan.set_receiver_waiting(on);
+            }
         }
     }
 }
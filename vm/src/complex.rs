@@ -0,0 +1,135 @@
+//! Arithmetic and formatting of complex64 and complex128 values.
+//!
+//! Like Go, both kinds are computed in f64 and complex64 results are rounded
+//! back to f32, so complex64 products and quotients match Go's bit for bit.
+use super::instruction::Opcode;
+use super::value::GosValue;
+use std::fmt;
+
+/// Returns the real and imaginary parts of a complex value
+#[inline]
+pub fn parts(c: &GosValue) -> (f64, f64) {
+    match c {
+        GosValue::Complex64(r, i) => (r.0 as f64, i.0 as f64),
+        GosValue::Complex128(c) => (c.0 .0, c.1 .0),
+        _ => unreachable!(),
+    }
+}
+
+/// Builds a complex value of the same kind as `like` from the parts
+#[inline]
+fn with_parts(like: &GosValue, r: f64, i: f64) -> GosValue {
+    match like {
+        GosValue::Complex64(_, _) => GosValue::Complex64((r as f32).into(), (i as f32).into()),
+        GosValue::Complex128(_) => GosValue::Complex128(Box::new((r.into(), i.into()))),
+        _ => unreachable!(),
+    }
+}
+
+#[inline]
+pub fn complex_add(a: &GosValue, b: &GosValue) -> GosValue {
+    let ((ar, ai), (br, bi)) = (parts(a), parts(b));
+    with_parts(a, ar + br, ai + bi)
+}
+
+#[inline]
+pub fn complex_sub(a: &GosValue, b: &GosValue) -> GosValue {
+    let ((ar, ai), (br, bi)) = (parts(a), parts(b));
+    with_parts(a, ar - br, ai - bi)
+}
+
+#[inline]
+pub fn complex_mul(a: &GosValue, b: &GosValue) -> GosValue {
+    let ((ar, ai), (br, bi)) = (parts(a), parts(b));
+    with_parts(a, ar * br - ai * bi, ar * bi + ai * br)
+}
+
+/// Divides like Go's runtime: Smith's algorithm, with the NaN results
+/// corrected to infinities and zeros as in C99 Annex G.
+/// Dividing by zero does not panic.
+pub fn complex_div(a: &GosValue, b: &GosValue) -> GosValue {
+    let ((nr, ni), (mr, mi)) = (parts(a), parts(b));
+    let (mut e, mut f) = if mr.abs() >= mi.abs() {
+        let ratio = mi / mr;
+        let denom = mr + ratio * mi;
+        ((nr + ni * ratio) / denom, (ni - nr * ratio) / denom)
+    } else {
+        let ratio = mr / mi;
+        let denom = mi + ratio * mr;
+        ((nr * ratio + ni) / denom, (ni * ratio - nr) / denom)
+    };
+    if e.is_nan() && f.is_nan() {
+        let (mut a, mut b, mut c, mut d) = (nr, ni, mr, mi);
+        let inf = f64::INFINITY;
+        let inf_as_one = |x: f64| (x.is_infinite() as u8 as f64).copysign(x);
+        if c == 0.0 && d == 0.0 && (!a.is_nan() || !b.is_nan()) {
+            e = inf.copysign(c) * a;
+            f = inf.copysign(c) * b;
+        } else if (a.is_infinite() || b.is_infinite()) && c.is_finite() && d.is_finite() {
+            a = inf_as_one(a);
+            b = inf_as_one(b);
+            e = inf * (a * c + b * d);
+            f = inf * (b * c - a * d);
+        } else if (c.is_infinite() || d.is_infinite()) && a.is_finite() && b.is_finite() {
+            c = inf_as_one(c);
+            d = inf_as_one(d);
+            e = 0.0 * (a * c + b * d);
+            f = 0.0 * (b * c - a * d);
+        }
+    }
+    with_parts(a, e, f)
+}
+
+/// Returns the absolute value as a float of the parts' kind
+pub fn abs(c: &GosValue) -> GosValue {
+    let (r, i) = parts(c);
+    let v = r.hypot(i);
+    match c {
+        GosValue::Complex64(_, _) => GosValue::Float32((v as f32).into()),
+        _ => GosValue::Float64(v.into()),
+    }
+}
+
+/// Returns the complex conjugate
+pub fn conj(c: &GosValue) -> GosValue {
+    let (r, i) = parts(c);
+    with_parts(c, r, -i)
+}
+
+#[inline]
+pub fn binary_op(a: &GosValue, b: &GosValue, op: Opcode) -> GosValue {
+    match op {
+        Opcode::ADD => complex_add(a, b),
+        Opcode::SUB => complex_sub(a, b),
+        Opcode::MUL => complex_mul(a, b),
+        Opcode::QUO => complex_div(a, b),
+        _ => unreachable!(),
+    }
+}
+
+/// Formats a complex value like Go's fmt does, e.g. "(3+4i)" or "(1-0.5i)"
+pub fn fmt<T>(f: &mut fmt::Formatter, r: T, i: T) -> fmt::Result
+where
+    T: fmt::Display + Into<f64> + Copy,
+{
+    f.write_str("(")?;
+    fmt_part(f, r, false)?;
+    fmt_part(f, i, true)?;
+    f.write_str("i)")
+}
+
+fn fmt_part<T>(f: &mut fmt::Formatter, v: T, sign: bool) -> fmt::Result
+where
+    T: fmt::Display + Into<f64> + Copy,
+{
+    let x: f64 = v.into();
+    if x.is_nan() {
+        f.write_str(if sign { "+NaN" } else { "NaN" })
+    } else if x.is_infinite() {
+        f.write_str(if x > 0.0 { "+Inf" } else { "-Inf" })
+    } else if sign && x.is_sign_positive() {
+        write!(f, "+{}", v)
+    } else {
+        write!(f, "{}", v)
+    }
+}
@@ -9,13 +9,30 @@ pub type FfiCtorResult<T> = std::result::Result<T, String>;
 
 pub type Ctor = dyn Fn(Vec<GosValue>) -> FfiCtorResult<Rc<RefCell<dyn Ffi>>>;
 
+/// The values an FFI call returns, one for each result of the method in the Go
+/// interface and in the same order, like `vec![n, s]` for `(int, string)`.
+/// A nil value is a nil `error`. Err panics in the calling goroutine.
+pub type FfiResult = RtMultiValResult;
+
+/// Returns the values from `Ffi::call`, e.g. `ffi_return!(GosValue::Int(1), s)`.
+/// They are evaluated right away, before the future is created.
+#[macro_export]
+macro_rules! ffi_return {
+    ($($val:expr),* $(,)?) => {{
+        let re: Vec<$crate::value::GosValue> = vec![$($val),*];
+        Box::pin(async move { Ok(re) })
+    }};
+}
+
 /// A FFI function call
 pub trait Ffi {
+    /// the number of the values returned must match the results of the method,
+    /// or the call panics
     fn call(
         &self,
         func_name: &str,
         params: Vec<GosValue>,
-    ) -> Pin<Box<dyn Future<Output = RtMultiValResult> + '_>>;
+    ) -> Pin<Box<dyn Future<Output = FfiResult> + '_>>;
 }
 
 impl std::fmt::Debug for dyn Ffi {
@@ -1,21 +1,60 @@
 use super::objects::*;
 use super::value::{GosValue, RCQueue, RCount, IRC};
 use std::cell::Ref;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::convert::TryFrom;
 use std::rc::{Rc, Weak};
 
+/// When the cycle collection runs
+#[derive(Clone, Copy, Debug)]
+pub struct GcConfig {
+    /// the number of potential-cycle objects tracked since the last pass,
+    /// that triggers a new one
+    pub cycle_collect_threshold: usize,
+    /// if false, the cycles are only collected when the host calls `gc`
+    pub auto: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> GcConfig {
+        GcConfig {
+            cycle_collect_threshold: 10000,
+            auto: true,
+        }
+    }
+}
+
 pub struct GcoVec {
     inner: Rc<RefCell<Vec<GcWeak>>>,
+    config: GcConfig,
+    // tracked since the last pass
+    added: Cell<usize>,
 }
 
 impl GcoVec {
     pub fn new() -> GcoVec {
+        GcoVec::with_config(GcConfig::default())
+    }
+
+    pub fn with_config(config: GcConfig) -> GcoVec {
         GcoVec {
             inner: Rc::new(RefCell::new(Vec::new())),
+            config: config,
+            added: Cell::new(0),
         }
     }
 
+    #[inline]
+    pub fn config(&self) -> &GcConfig {
+        &self.config
+    }
+
+    /// if an automatic pass is due
+    #[inline]
+    pub fn should_collect(&self) -> bool {
+        self.config.auto && self.added.get() >= self.config.cycle_collect_threshold
+    }
+
     #[inline]
     pub fn add(&self, v: &GosValue) {
         let weak = GcWeak::from_gosv(v);
@@ -25,6 +64,7 @@ impl GcoVec {
     #[inline]
     pub fn add_weak(&self, w: GcWeak) {
         self.inner.borrow_mut().push(w);
+        self.added.set(self.added.get() + 1);
     }
 
     fn borrow_data(&self) -> Ref<Vec<GcWeak>> {
@@ -60,6 +100,16 @@ impl GcWeak {
         }
     }
 
+    fn is_alive(&self) -> bool {
+        match &self {
+            GcWeak::Array(w) => w.strong_count() > 0,
+            GcWeak::Closure(w) => w.strong_count() > 0,
+            GcWeak::Slice(w) => w.strong_count() > 0,
+            GcWeak::Map(w) => w.strong_count() > 0,
+            GcWeak::Struct(w) => w.strong_count() > 0,
+        }
+    }
+
     fn to_gosv(&self) -> Option<GosValue> {
         match &self {
             GcWeak::Array(w) => w.upgrade().map(|v| {
@@ -187,19 +237,20 @@ fn partition_to_scan(to_scan: &mut Vec<GosValue>) -> usize {
     if len == 0 {
         return 0;
     }
+    // [0, p0) are known to be non-zero, [p1, len) are known to be zero
     let mut p0 = 0;
-    let mut p1 = len - 1;
+    let mut p1 = len;
     loop {
-        while p0 < len - 1 && to_scan[p0].rc() > 0 {
+        while p0 < p1 && to_scan[p0].rc() > 0 {
             p0 += 1;
         }
-        while p1 > 1 && to_scan[p1].rc() <= 0 {
+        while p1 > p0 && to_scan[p1 - 1].rc() <= 0 {
             p1 -= 1;
         }
         if p0 >= p1 {
             break;
         }
-        to_scan.swap(p0, p1);
+        to_scan.swap(p0, p1 - 1);
     }
     p0
 }
@@ -241,10 +292,30 @@ pub fn gc(objs: &GcoVec) {
         }
     }
 
-    let _result: Vec<GosValue> = objs
-        .borrow_data()
-        .iter()
-        .filter_map(|o| o.to_gosv())
-        .collect();
-    //print!("objs left after GC: {}\n", result.len());
+    // stop tracking the freed ones
+    objs.inner.borrow_mut().retain(|o| o.is_alive());
+    objs.added.set(0);
+    //print!("objs left after GC: {}\n", objs.borrow_data().len());
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::metadata::GosMetadata;
+    use super::*;
+
+    #[test]
+    fn test_gc_keeps_live() {
+        let mut objs = VMObjects::new();
+        let gcv = GcoVec::new();
+        let meta = GosMetadata::new_array(objs.metadata.mint, 1, &mut objs.metas);
+        // none of them is in a cycle, they all stay as they are
+        let arrs: Vec<GosValue> = (0..3)
+            .map(|i| GosValue::array_with_val(vec![GosValue::Int(i)], meta, &gcv))
+            .collect();
+        gc(&gcv);
+        for (i, a) in arrs.iter().enumerate() {
+            assert_eq!(a.as_array().0.len(), 1);
+            assert_eq!(a.as_array().0.get(0).unwrap(), GosValue::Int(i as isize));
+        }
+    }
 }
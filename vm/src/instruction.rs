@@ -19,6 +19,7 @@ pub enum Opcode {
     STORE_LOCAL, // stores the value on the top of the stack to local
     LOAD_UPVALUE,
     STORE_UPVALUE,
+    CLOSE_UPVALUE, // gives a loop var a new copy for the next iteration
     LOAD_INDEX,
     STORE_INDEX,
     LOAD_INDEX_IMM,
@@ -45,6 +46,7 @@ pub enum Opcode {
     SHL,       // <<
     SHR,       // >>
     AND_NOT,   // $^
+    CONCAT,    // + on more than two strings
     UNARY_ADD, // +
     UNARY_SUB, // -
     UNARY_XOR, // ^
@@ -87,6 +89,7 @@ pub enum Opcode {
 
     // built-in functinalities
     IMPORT,     // imports a package
+    INIT_PKG,   // stores the vars of a package being constructed
     SLICE,      // for slice expressions
     SLICE_FULL, // for full slice expressions
     LITERAL,    // for function literal or composite literal
@@ -95,9 +98,14 @@ pub enum Opcode {
     LEN,        // for built-in function len
     CAP,        // for built-in function cap
     APPEND,     // for built-in function append
+    COPY,       // for built-in function copy
+    CLEAR,      // for built-in function clear
+    DELETE,     // for built-in function delete
     CLOSE,      // for built-in function close
     PANIC,      // for built-in function panic
     RECOVER,    // for built-in function recover
+    PRINT,      // for built-in function print
+    PRINTLN,    // for built-in function println
     ASSERT,     // for built-in function assert
     FFI,        // for built-in function native
 }
@@ -121,6 +129,7 @@ impl Opcode {
             Opcode::STORE_LOCAL => ("STORE_LOCAL", 0),
             Opcode::LOAD_UPVALUE => ("LOAD_LOCAL", 1),
             Opcode::STORE_UPVALUE => ("STORE_UPVALUE", 0),
+            Opcode::CLOSE_UPVALUE => ("CLOSE_UPVALUE", 0),
             Opcode::LOAD_INDEX => ("LOAD_INDEX", -1),
             Opcode::STORE_INDEX => ("STORE_INDEX", 0),
             Opcode::LOAD_INDEX_IMM => ("LOAD_INDEX_IMM", 0),
@@ -147,6 +156,7 @@ impl Opcode {
             Opcode::SHL => ("SHL", -1),
             Opcode::SHR => ("SHR", -1),
             Opcode::AND_NOT => ("AND_NOT", -1),
+            Opcode::CONCAT => ("CONCAT", -128),
             Opcode::UNARY_ADD => ("UNARY_ADD", 0),
             Opcode::UNARY_SUB => ("UNARY_SUB", 0),
             Opcode::UNARY_XOR => ("UNARY_XOR", 0),
@@ -184,6 +194,7 @@ impl Opcode {
             Opcode::TYPE => ("TYPE", 1),
 
             Opcode::IMPORT => ("IMPORT", 0),
+            Opcode::INIT_PKG => ("INIT_PKG", -128),
             Opcode::SLICE => ("SLICE", -2),
             Opcode::SLICE_FULL => ("SLICE_FULL", -3),
             Opcode::LITERAL => ("LITERAL", 0),
@@ -192,9 +203,14 @@ impl Opcode {
             Opcode::LEN => ("LEN", 0),
             Opcode::CAP => ("CAP", 0),
             Opcode::APPEND => ("APPEND", -128),
+            Opcode::COPY => ("COPY", -1),
+            Opcode::CLEAR => ("CLEAR", -1),
+            Opcode::DELETE => ("DELETE", -2),
             Opcode::CLOSE => ("CLOSE", -1),
             Opcode::PANIC => ("PANIC", -1),
             Opcode::RECOVER => ("RECOVER", 1),
+            Opcode::PRINT => ("PRINT", -128),
+            Opcode::PRINTLN => ("PRINTLN", -128),
             Opcode::ASSERT => ("ASSERT", 0),
             Opcode::FFI => ("FFI", 0),
         }
@@ -268,6 +284,13 @@ impl ValueType {
     pub fn copyable(&self) -> bool {
         self <= &COPYABLE_END
     }
+
+    #[inline]
+    pub fn is_basic(&self) -> bool {
+        (self >= &ValueType::Bool && self <= &ValueType::Complex64)
+            || self == &ValueType::Complex128
+            || self == &ValueType::Str
+    }
 }
 
 /// Instruction is 64 bit
@@ -11,6 +11,8 @@ pub mod ffi;
 
 pub mod value;
 
+pub mod complex;
+
 mod stack;
 
 #[macro_use]
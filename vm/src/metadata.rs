@@ -91,7 +91,7 @@ impl Metadata {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MetaCategory {
     Default,
     Array,
@@ -99,7 +99,38 @@ pub enum MetaCategory {
     ArrayType,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// The kind of a type, like reflect.Kind in Go, named types have the kind
+/// of their underlying types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetaKind {
+    Invalid,
+    Bool,
+    Int,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Uint,
+    Uint8,
+    Uint16,
+    Uint32,
+    Uint64,
+    Float32,
+    Float64,
+    Complex64,
+    Complex128,
+    Array,
+    Chan,
+    Func,
+    Interface,
+    Map,
+    Ptr,
+    Slice,
+    String,
+    Struct,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum GosMetadata {
     Untyped,
     NonPtr(MetadataKey, MetaCategory),
@@ -286,6 +317,102 @@ impl GosMetadata {
         }
     }
 
+    /// type_string returns the type in Go syntax for runtime error messages,
+    /// named types are shown as their underlying types as the names are not
+    /// kept at runtime
+    pub fn type_string(&self, metas: &MetadataObjs) -> String {
+        let (k, mc, ptrs) = match self {
+            GosMetadata::Untyped => return "untyped".to_string(),
+            GosMetadata::NonPtr(k, mc) => (k, mc, 0),
+            GosMetadata::Ptr1(k, mc) => (k, mc, 1),
+            GosMetadata::Ptr2(k, mc) => (k, mc, 2),
+            GosMetadata::Ptr3(k, mc) => (k, mc, 3),
+            GosMetadata::Ptr4(k, mc) => (k, mc, 4),
+            GosMetadata::Ptr5(k, mc) => (k, mc, 5),
+            GosMetadata::Ptr6(k, mc) => (k, mc, 6),
+            GosMetadata::Ptr7(k, mc) => (k, mc, 7),
+        };
+        let list = |ms: &Vec<GosMetadata>| {
+            ms.iter()
+                .map(|m| m.type_string(metas))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let names = |f: &Fields| {
+            // blank fields are not in the mapping
+            let mut names = vec!["_".to_string(); f.fields.len()];
+            for (name, i) in f.mapping.iter() {
+                names[*i as usize] = name.clone();
+            }
+            names
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| (name, i))
+                .collect::<Vec<(String, usize)>>()
+        };
+        let base = match &metas[*k] {
+            MetadataType::Bool => "bool".to_string(),
+            MetadataType::Int => "int".to_string(),
+            MetadataType::Int8 => "int8".to_string(),
+            MetadataType::Int16 => "int16".to_string(),
+            MetadataType::Int32 => "int32".to_string(),
+            MetadataType::Int64 => "int64".to_string(),
+            MetadataType::Uint => "uint".to_string(),
+            MetadataType::Uint8 => "uint8".to_string(),
+            MetadataType::Uint16 => "uint16".to_string(),
+            MetadataType::Uint32 => "uint32".to_string(),
+            MetadataType::Uint64 => "uint64".to_string(),
+            MetadataType::Float32 => "float32".to_string(),
+            MetadataType::Float64 => "float64".to_string(),
+            MetadataType::Complex64 => "complex64".to_string(),
+            MetadataType::Complex128 => "complex128".to_string(),
+            MetadataType::Str(_) => "string".to_string(),
+            MetadataType::SliceOrArray(m, size) => match mc {
+                MetaCategory::Array | MetaCategory::ArrayType => {
+                    format!("[{}]{}", size, m.type_string(metas))
+                }
+                _ => format!("[]{}", m.type_string(metas)),
+            },
+            MetadataType::Struct(f, _) => {
+                let fields: Vec<String> = names(f)
+                    .into_iter()
+                    .map(|(name, i)| format!("{} {}", name, f.fields[i].type_string(metas)))
+                    .collect();
+                format!("struct {{ {} }}", fields.join("; "))
+            }
+            MetadataType::Signature(sig) => match sig.results.len() {
+                0 => format!("func({})", list(&sig.params)),
+                1 => format!(
+                    "func({}) {}",
+                    list(&sig.params),
+                    sig.results[0].type_string(metas)
+                ),
+                _ => format!("func({}) ({})", list(&sig.params), list(&sig.results)),
+            },
+            MetadataType::Map(km, vm) => {
+                format!("map[{}]{}", km.type_string(metas), vm.type_string(metas))
+            }
+            MetadataType::Interface(f) if f.fields.is_empty() => "interface {}".to_string(),
+            MetadataType::Interface(f) => {
+                let methods: Vec<String> = names(f)
+                    .into_iter()
+                    .map(|(name, i)| {
+                        let sig = f.fields[i].type_string(metas);
+                        format!("{}{}", name, sig.trim_start_matches("func"))
+                    })
+                    .collect();
+                format!("interface {{ {} }}", methods.join("; "))
+            }
+            MetadataType::Channel(t, m) => match t {
+                ChannelType::Send => format!("chan<- {}", m.type_string(metas)),
+                ChannelType::Recv => format!("<-chan {}", m.type_string(metas)),
+                ChannelType::SendRecv => format!("chan {}", m.type_string(metas)),
+            },
+            MetadataType::Named(_, u) => u.type_string(metas),
+        };
+        format!("{}{}", "*".repeat(ptrs), base)
+    }
+
     #[inline]
     pub fn get_value_type(&self, metas: &MetadataObjs) -> ValueType {
         match self {
@@ -313,7 +440,18 @@ impl GosMetadata {
                     MetadataType::Map(_, _) => ValueType::Map,
                     MetadataType::Interface(_) => ValueType::Interface,
                     MetadataType::Channel(_, _) => ValueType::Channel,
-                    MetadataType::Named(_, _) => ValueType::Named,
+                    // named basic types share the representation of the underlying types
+                    MetadataType::Named(_, u) => match u {
+                        GosMetadata::Untyped => ValueType::Named,
+                        _ => {
+                            let vt = u.get_value_type(metas);
+                            if vt.is_basic() {
+                                vt
+                            } else {
+                                ValueType::Named
+                            }
+                        }
+                    },
                 },
                 MetaCategory::Type | MetaCategory::ArrayType => ValueType::Metadata,
                 MetaCategory::Array => ValueType::Array,
@@ -325,6 +463,95 @@ impl GosMetadata {
         }
     }
 
+    /// kind returns the kind of the type, untyped nil and the metadata of
+    /// type values are Invalid
+    pub fn kind(&self, metas: &MetadataObjs) -> MetaKind {
+        let k = match self {
+            GosMetadata::Untyped => return MetaKind::Invalid,
+            GosMetadata::NonPtr(k, _) => k,
+            _ => return MetaKind::Ptr,
+        };
+        match self.get_value_type(metas) {
+            ValueType::Bool => MetaKind::Bool,
+            ValueType::Int => MetaKind::Int,
+            ValueType::Int8 => MetaKind::Int8,
+            ValueType::Int16 => MetaKind::Int16,
+            ValueType::Int32 => MetaKind::Int32,
+            ValueType::Int64 => MetaKind::Int64,
+            ValueType::Uint => MetaKind::Uint,
+            ValueType::Uint8 => MetaKind::Uint8,
+            ValueType::Uint16 => MetaKind::Uint16,
+            ValueType::Uint32 => MetaKind::Uint32,
+            ValueType::Uint64 => MetaKind::Uint64,
+            ValueType::Float32 => MetaKind::Float32,
+            ValueType::Float64 => MetaKind::Float64,
+            ValueType::Complex64 => MetaKind::Complex64,
+            ValueType::Complex128 => MetaKind::Complex128,
+            ValueType::Str => MetaKind::String,
+            ValueType::Array => MetaKind::Array,
+            ValueType::Slice => MetaKind::Slice,
+            ValueType::Map => MetaKind::Map,
+            ValueType::Struct => MetaKind::Struct,
+            ValueType::Interface => MetaKind::Interface,
+            ValueType::Channel => MetaKind::Chan,
+            ValueType::Closure => MetaKind::Func,
+            ValueType::Named => match &metas[*k] {
+                MetadataType::Named(_, u) => u.kind(metas),
+                _ => unreachable!(),
+            },
+            _ => MetaKind::Invalid,
+        }
+    }
+
+    /// size_align returns the size and the alignment of the values of the type
+    /// as laid out by Go on 64-bit platforms
+    pub fn size_align(&self, metas: &MetadataObjs) -> (usize, usize) {
+        match self.kind(metas) {
+            MetaKind::Invalid => (0, 1),
+            MetaKind::Bool | MetaKind::Int8 | MetaKind::Uint8 => (1, 1),
+            MetaKind::Int16 | MetaKind::Uint16 => (2, 2),
+            MetaKind::Int32 | MetaKind::Uint32 | MetaKind::Float32 => (4, 4),
+            MetaKind::Int
+            | MetaKind::Int64
+            | MetaKind::Uint
+            | MetaKind::Uint64
+            | MetaKind::Float64 => (8, 8),
+            MetaKind::Complex64 => (8, 4),
+            MetaKind::Complex128 | MetaKind::String | MetaKind::Interface => (16, 8),
+            MetaKind::Slice => (24, 8),
+            MetaKind::Ptr | MetaKind::Map | MetaKind::Chan | MetaKind::Func => (8, 8),
+            MetaKind::Array | MetaKind::Struct => {
+                match &metas[self.get_underlying(metas).as_non_ptr()] {
+                    MetadataType::SliceOrArray(m, size) => {
+                        let (s, a) = m.size_align(metas);
+                        (s * size, a)
+                    }
+                    MetadataType::Struct(f, _) => f.size_align(metas),
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+
+    /// may_hold_float returns whether the values of the type may hold floats or
+    /// complex numbers, in their elements or fields or behind interfaces
+    pub fn may_hold_float(&self, metas: &MetadataObjs) -> bool {
+        match self {
+            GosMetadata::NonPtr(k, _) => match &metas[*k] {
+                MetadataType::Float32
+                | MetadataType::Float64
+                | MetadataType::Complex64
+                | MetadataType::Complex128
+                | MetadataType::Interface(_) => true,
+                MetadataType::SliceOrArray(m, _) => m.may_hold_float(metas),
+                MetadataType::Struct(f, _) => f.fields.iter().any(|m| m.may_hold_float(metas)),
+                MetadataType::Named(_, u) => u.may_hold_float(metas),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
     #[inline]
     pub fn zero_val(&self, mobjs: &MetadataObjs, gcos: &GcoVec) -> GosValue {
         self.zero_val_impl(mobjs, gcos)
@@ -369,8 +596,12 @@ impl GosMetadata {
                 MetadataType::Interface(_) => GosValue::Nil(*self),
                 MetadataType::Channel(_, _) => GosValue::Nil(*self),
                 MetadataType::Named(_, gm) => {
-                    let val = gm.default_val(mobjs, gcos);
-                    GosValue::Named(Box::new((val, *gm)))
+                    let val = gm.zero_val_impl(mobjs, gcos);
+                    if val.get_type().is_basic() {
+                        val
+                    } else {
+                        GosValue::Named(Box::new((val, *self)))
+                    }
                 }
             },
             _ => GosValue::Nil(*self),
@@ -416,10 +647,15 @@ impl GosMetadata {
                 MetadataType::Channel(_, _) => GosValue::Nil(*self),
                 MetadataType::Named(_, gm) => {
                     let val = gm.default_val(mobjs, gcos);
-                    GosValue::Named(Box::new((val, *gm)))
+                    if val.get_type().is_basic() {
+                        val
+                    } else {
+                        GosValue::Named(Box::new((val, *self)))
+                    }
                 }
             },
-            _ => unreachable!(),
+            // pointers
+            _ => GosValue::Nil(*self),
         }
     }
 
@@ -562,6 +798,39 @@ impl Fields {
         result
     }
 
+    /// field_offsets returns the offsets of the fields of a struct as laid out by
+    /// Go, each field is aligned to the alignment of its type
+    pub fn field_offsets(&self, metas: &MetadataObjs) -> Vec<usize> {
+        self.layout(metas).0
+    }
+
+    /// size_align returns the size and the alignment of a struct with the fields,
+    /// the struct is aligned to its most aligned field
+    pub fn size_align(&self, metas: &MetadataObjs) -> (usize, usize) {
+        let (_, size, align) = self.layout(metas);
+        (size, align)
+    }
+
+    fn layout(&self, metas: &MetadataObjs) -> (Vec<usize>, usize, usize) {
+        let align_up = |n: usize, a: usize| (n + a - 1) / a * a;
+        let mut offsets = Vec::with_capacity(self.fields.len());
+        let (mut offset, mut align, mut last_size) = (0, 1, 1);
+        for f in self.fields.iter() {
+            let (size, a) = f.size_align(metas);
+            offset = align_up(offset, a);
+            offsets.push(offset);
+            offset += size;
+            align = align.max(a);
+            last_size = size;
+        }
+        // like gc, a trailing zero-sized field gets a byte of padding so that
+        // its address does not point past the struct
+        if offset > 0 && last_size == 0 {
+            offset += 1;
+        }
+        (offsets, align_up(offset, align), align)
+    }
+
     pub fn iface_methods_info(&self) -> Vec<(String, GosMetadata)> {
         let mut ret = vec![];
         for f in self.fields.iter() {
@@ -648,7 +917,12 @@ impl SigMetadata {
         } {
             return false;
         }
+        self.semantic_eq_no_recv(other, metas)
+    }
 
+    /// compares params, results and variadic only, as interface methods are
+    /// matched against methods with receivers
+    pub fn semantic_eq_no_recv(&self, other: &Self, metas: &MetadataObjs) -> bool {
         if self.params.len() != other.params.len() {
             return false;
         }
@@ -658,7 +932,7 @@ impl SigMetadata {
             }
         }
 
-        if self.results.len() != other.params.len() {
+        if self.results.len() != other.results.len() {
             return false;
         }
         for (i, r) in self.results.iter().enumerate() {
@@ -774,8 +1048,129 @@ impl MetadataType {
             (Self::Channel(at, avt), Self::Channel(bt, bvt)) => {
                 at == bt && avt.semantic_eq(bvt, metas)
             }
-            (Self::Named(_, a), Self::Named(_, b)) => a.semantic_eq(b, metas),
+            // named types are identical only if their keys are the same,
+            // types with the same name and underlying type can come from
+            // different packages
             _ => false,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_kind() {
+        let mut objs = VMObjects::new();
+        let md = &objs.metadata;
+        let (mint, mu8, mstr, miface) = (md.mint, md.muint8, md.mstr, md.empty_iface);
+        let metas = &mut objs.metas;
+        assert_eq!(mint.kind(metas), MetaKind::Int);
+        assert_eq!(mu8.kind(metas), MetaKind::Uint8);
+        assert_eq!(mstr.kind(metas), MetaKind::String);
+        assert_eq!(miface.kind(metas), MetaKind::Interface);
+        assert_eq!(GosMetadata::Untyped.kind(metas), MetaKind::Invalid);
+
+        let arr = GosMetadata::new_array(mint, 3, metas);
+        let slice = GosMetadata::new_slice(mstr, metas);
+        let map = GosMetadata::new_map(mstr, mint, metas);
+        let ch = GosMetadata::new_channel(ChannelType::SendRecv, mint, metas);
+        let sig = GosMetadata::new_sig(None, vec![mint], vec![], None, metas);
+        assert_eq!(arr.kind(metas), MetaKind::Array);
+        assert_eq!(slice.kind(metas), MetaKind::Slice);
+        assert_eq!(map.kind(metas), MetaKind::Map);
+        assert_eq!(ch.kind(metas), MetaKind::Chan);
+        assert_eq!(sig.kind(metas), MetaKind::Func);
+        assert_eq!(mint.ptr_to().kind(metas), MetaKind::Ptr);
+        assert_eq!(slice.ptr_to().ptr_to().kind(metas), MetaKind::Ptr);
+
+        // named types have the kind of their underlying types
+        let named_int = GosMetadata::new_named(mint, metas);
+        let named_map = GosMetadata::new_named(map, metas);
+        assert_eq!(named_int.kind(metas), MetaKind::Int);
+        assert_eq!(named_map.kind(metas), MetaKind::Map);
+        assert_eq!(named_map.ptr_to().kind(metas), MetaKind::Ptr);
+    }
+
+    #[test]
+    fn test_may_hold_float() {
+        let mut objs = VMObjects::new();
+        let mut gcv = GcoVec::new();
+        let md = &objs.metadata;
+        let (mint, mstr, miface) = (md.mint, md.mstr, md.empty_iface);
+        let (mf64, mc128) = (md.mfloat64, md.mcomplex128);
+        let fields = |metas: Vec<GosMetadata>| Fields::new(metas, HashMap::new());
+        assert!(!mint.may_hold_float(&objs.metas));
+        assert!(!mstr.may_hold_float(&objs.metas));
+        assert!(mf64.may_hold_float(&objs.metas));
+        assert!(mc128.may_hold_float(&objs.metas));
+        assert!(miface.may_hold_float(&objs.metas));
+        // pointers are compared by address
+        assert!(!mf64.ptr_to().may_hold_float(&objs.metas));
+
+        let ints = GosMetadata::new_array(mint, 3, &mut objs.metas);
+        let floats = GosMetadata::new_array(mf64, 3, &mut objs.metas);
+        let grid = GosMetadata::new_array(floats, 3, &mut objs.metas);
+        assert!(!ints.may_hold_float(&objs.metas));
+        assert!(floats.may_hold_float(&objs.metas));
+        assert!(grid.may_hold_float(&objs.metas));
+
+        let plain = GosMetadata::new_struct(fields(vec![mint, mstr, ints]), &mut objs, &mut gcv);
+        let named = GosMetadata::new_named(plain, &mut objs.metas);
+        let nested = GosMetadata::new_struct(fields(vec![mint, grid]), &mut objs, &mut gcv);
+        let boxed = GosMetadata::new_struct(fields(vec![miface]), &mut objs, &mut gcv);
+        assert!(!plain.may_hold_float(&objs.metas));
+        assert!(!named.may_hold_float(&objs.metas));
+        assert!(nested.may_hold_float(&objs.metas));
+        assert!(boxed.may_hold_float(&objs.metas));
+    }
+
+    #[test]
+    fn test_field_offsets() {
+        let mut objs = VMObjects::new();
+        let mut gcv = GcoVec::new();
+        let md = &objs.metadata;
+        let (mbool, mint8, mint32, mint64) = (md.mbool, md.mint8, md.mint32, md.mint64);
+        let (mu16, mc64, mstr, miface) = (md.muint16, md.mcomplex64, md.mstr, md.empty_iface);
+        let fields = |metas: Vec<GosMetadata>| {
+            let mapping = (0..metas.len())
+                .map(|i| (format!("f{}", i), i as OpIndex))
+                .collect();
+            Fields::new(metas, mapping)
+        };
+
+        // struct { a bool; b int64; c int32 }
+        let f = fields(vec![mbool, mint64, mint32]);
+        assert_eq!(f.field_offsets(&objs.metas), vec![0, 8, 16]);
+        assert_eq!(f.size_align(&objs.metas), (24, 8));
+
+        // struct { a int8; b uint16; c complex64; d string; e interface{} }
+        let f = fields(vec![mint8, mu16, mc64, mstr, miface]);
+        assert_eq!(f.field_offsets(&objs.metas), vec![0, 2, 4, 16, 32]);
+        assert_eq!(f.size_align(&objs.metas), (48, 8));
+
+        // struct { a bool; b [3]int32; c []int8; d *bool }
+        let arr = GosMetadata::new_array(mint32, 3, &mut objs.metas);
+        let slice = GosMetadata::new_slice(mint8, &mut objs.metas);
+        let f = fields(vec![mbool, arr, slice, mbool.ptr_to()]);
+        assert_eq!(f.field_offsets(&objs.metas), vec![0, 4, 16, 40]);
+        assert_eq!(f.size_align(&objs.metas), (48, 8));
+
+        // nested and named structs are aligned to their most aligned field,
+        // struct { a bool; b inner; c bool } with inner struct { x int32; y bool }
+        let inner = GosMetadata::new_struct(fields(vec![mint32, mbool]), &mut objs, &mut gcv);
+        let named = GosMetadata::new_named(inner, &mut objs.metas);
+        assert_eq!(inner.size_align(&objs.metas), (8, 4));
+        let f = fields(vec![mbool, named, mbool]);
+        assert_eq!(f.field_offsets(&objs.metas), vec![0, 4, 12]);
+        assert_eq!(f.size_align(&objs.metas), (16, 4));
+
+        // a trailing zero-sized field is padded
+        let empty = GosMetadata::new_struct(fields(vec![]), &mut objs, &mut gcv);
+        assert_eq!(empty.size_align(&objs.metas), (0, 1));
+        let f = fields(vec![mint32, empty]);
+        assert_eq!(f.field_offsets(&objs.metas), vec![0, 4]);
+        assert_eq!(f.size_align(&objs.metas), (8, 4));
+    }
+}
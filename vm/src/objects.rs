@@ -5,8 +5,12 @@ use super::gc::GcoVec;
 use super::instruction::{Instruction, OpIndex, Opcode, ValueType};
 use super::metadata::*;
 use super::stack::Stack;
-use super::value::{rcount_mark_and_queue, GosValue, RCQueue, RCount, RtEmptyResult};
+use super::value::{
+    rcount_mark_and_queue, GosValue, RCQueue, RCount, RtEmptyResult, RuntimeResult,
+};
+use goscript_parser::ast::is_exported;
 use goscript_parser::objects::{EntityKey, IdentKey};
+use goscript_parser::{FileSet, Position};
 use slotmap::{new_key_type, DenseSlotMap};
 use std::any::Any;
 use std::cell::{Cell, Ref, RefCell, RefMut};
@@ -74,6 +78,53 @@ impl VMObjects {
     }
 }
 
+/// checks the indices of a slice expression `[begin:end:max]` against the length
+/// and capacity of the operand, an omitted end or max is passed as None.
+/// `cap` is None for strings and arrays, which are bounded by their length,
+/// the panic messages then say "with length" like Go's.
+/// returns the indices relative to the operand, which satisfy
+/// 0 <= begin <= end <= max <= cap
+pub fn slice_bounds(
+    begin: isize,
+    end: Option<isize>,
+    max: Option<isize>,
+    len: usize,
+    cap: Option<usize>,
+) -> RuntimeResult<(usize, usize, usize)> {
+    let err = |s: String| Err(format!("runtime error: slice bounds out of range {}", s));
+    let (bound, what) = match cap {
+        Some(c) => (c, "capacity"),
+        None => (len, "length"),
+    };
+    let mi = match max {
+        None => bound,
+        Some(m) if m < 0 => return err(format!("[::{}]", m)),
+        Some(m) if m as usize > bound => return err(format!("[::{}] with {} {}", m, what, bound)),
+        Some(m) => m as usize,
+    };
+    let ei = match end {
+        None => len,
+        Some(e) if max.is_some() && e < 0 => return err(format!("[:{}:]", e)),
+        Some(e) if max.is_some() && e as usize > mi => return err(format!("[:{}:{}]", e, mi)),
+        Some(e) if e < 0 => return err(format!("[:{}]", e)),
+        Some(e) if e as usize > bound => return err(format!("[:{}] with {} {}", e, what, bound)),
+        Some(e) => e as usize,
+    };
+    if begin < 0 || begin as usize > ei {
+        let end = if begin < 0 {
+            "".to_string()
+        } else {
+            ei.to_string()
+        };
+        return if max.is_some() {
+            err(format!("[{}:{}:]", begin, end))
+        } else {
+            err(format!("[{}:{}]", begin, end))
+        };
+    }
+    Ok((begin as usize, ei, mi))
+}
+
 // ----------------------------------------------------------------------------
 // StringObj
 
@@ -119,17 +170,43 @@ impl StringObj {
         self.as_str().as_bytes().get(i)
     }
 
-    pub fn slice(&self, begin: isize, end: isize) -> StringObj {
-        let self_end = self.len() as isize + 1;
-        let bi = begin as usize;
-        let ei = ((self_end + end) % self_end) as usize;
-        StringObj {
-            data: Rc::clone(&self.data),
-            begin: bi,
-            end: ei,
+    /// joins `parts` into a string allocated once with the summed lengths
+    pub fn concat(parts: &[&StringObj]) -> StringObj {
+        let mut s = String::with_capacity(parts.iter().map(|x| x.len()).sum());
+        for p in parts.iter() {
+            s.push_str(p.as_str());
+        }
+        StringObj::with_str(s)
+    }
+
+    /// number of runes, as opposed to `len` which is the number of bytes
+    #[inline]
+    pub fn rune_count(&self) -> usize {
+        self.as_str().chars().count()
+    }
+
+    /// the rune starting at `byte_index` and its width in bytes,
+    /// a byte in the middle of a rune decodes to (RuneError, 1) like in Go
+    pub fn rune_at(&self, byte_index: usize) -> Option<(char, usize)> {
+        let s = self.as_str();
+        if byte_index >= s.len() {
+            None
+        } else if !s.is_char_boundary(byte_index) {
+            Some((std::char::REPLACEMENT_CHARACTER, 1))
+        } else {
+            s[byte_index..].chars().next().map(|c| (c, c.len_utf8()))
         }
     }
 
+    pub fn slice(&self, begin: isize, end: Option<isize>) -> RuntimeResult<StringObj> {
+        let (bi, ei, _) = slice_bounds(begin, end, None, self.len(), None)?;
+        Ok(StringObj {
+            data: Rc::clone(&self.data),
+            begin: self.begin + bi,
+            end: self.begin + ei,
+        })
+    }
+
     pub fn iter(&self) -> StringIter {
         self.as_str().chars()
     }
@@ -234,19 +311,33 @@ impl MapObj {
         self.map.is_none()
     }
 
+    /// deletes all the entries, it's a no-op on a nil map
+    #[inline]
+    pub fn clear(&self) {
+        if let Some(m) = &self.map {
+            m.borrow_mut().clear();
+        }
+    }
+
+    /// deletes the entry of 'key' and returns its value, it's a no-op on a nil map
+    #[inline]
+    pub fn delete(&self, key: &GosValue) -> Option<GosValue> {
+        self.map
+            .as_ref()
+            .and_then(|m| m.borrow_mut().remove(key))
+            .map(|x| x.into_inner())
+    }
+
+    /// a nil map reads like an empty map
     #[inline]
     pub fn get(&self, key: &GosValue) -> GosValue {
-        let mref = self.borrow_data();
-        let cell = match mref.get(key) {
-            Some(v) => v,
-            None => &self.default_val,
-        };
-        cell.clone().into_inner()
+        self.try_get(key)
+            .unwrap_or_else(|| self.default_val.clone().into_inner())
     }
 
     #[inline]
     pub fn try_get(&self, key: &GosValue) -> Option<GosValue> {
-        let mref = self.borrow_data();
+        let mref = self.map.as_ref()?.borrow();
         mref.get(key).map(|x| x.clone().into_inner())
     }
 
@@ -262,7 +353,7 @@ impl MapObj {
 
     #[inline]
     pub fn len(&self) -> usize {
-        self.borrow_data().len()
+        self.map.as_ref().map_or(0, |m| m.borrow().len())
     }
 
     #[inline]
@@ -279,6 +370,26 @@ impl MapObj {
     pub fn clone_inner(&self) -> Rc<RefCell<GosHashMap>> {
         self.map.as_ref().unwrap().clone()
     }
+
+    /// compares the entries of two maps the way reflect.DeepEqual does, a nil map
+    /// only equals another nil map, and the values are compared with deep_eq
+    pub fn structural_eq(&self, other: &MapObj, metas: &MetadataObjs) -> bool {
+        match (&self.map, &other.map) {
+            (None, None) => true,
+            (Some(a), Some(b)) => {
+                if Rc::ptr_eq(a, b) {
+                    return true;
+                }
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len()
+                    && a.iter().all(|(k, v)| match b.get(k) {
+                        Some(w) => v.borrow().deep_eq(&w.borrow(), metas),
+                        None => false,
+                    })
+            }
+            _ => false,
+        }
+    }
 }
 
 impl Clone for MapObj {
@@ -291,6 +402,8 @@ impl Clone for MapObj {
     }
 }
 
+/// maps are not comparable, the checker rejects `==` on them, so reaching this is
+/// a logic error, use structural_eq to compare the contents of two maps
 impl PartialEq for MapObj {
     fn eq(&self, _other: &MapObj) -> bool {
         unreachable!() //false
@@ -303,7 +416,11 @@ impl Display for MapObj {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str("map[")?;
         if let Some(m) = &self.map {
-            for (i, kv) in m.borrow().iter().enumerate() {
+            let data = m.borrow();
+            // like Go, the keys are sorted to make the output reproducible
+            let mut entries: Vec<(&GosValue, &RefCell<GosValue>)> = data.iter().collect();
+            entries.sort_by(|a, b| fmt_key_cmp(a.0, b.0));
+            for (i, kv) in entries.iter().enumerate() {
                 if i > 0 {
                     f.write_char(' ')?;
                 }
@@ -315,6 +432,50 @@ impl Display for MapObj {
     }
 }
 
+/// The order map keys are printed in, which follows Go's fmt: numbers and strings
+/// by value, false before true, arrays and structs element by element, and
+/// interfaces by the type of the dynamic value first, nil ones go first.
+/// Keys it can't order (pointers, channels) keep the iteration order.
+fn fmt_key_cmp(a: &GosValue, b: &GosValue) -> Ordering {
+    match (a, b) {
+        (GosValue::Interface(x), GosValue::Interface(y)) => {
+            match (x.borrow().underlying_value(), y.borrow().underlying_value()) {
+                (Some(xv), Some(yv)) => fmt_key_cmp(xv, yv),
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Less,
+                (Some(_), None) => Ordering::Greater,
+            }
+        }
+        (GosValue::Named(x), GosValue::Named(y)) => fmt_key_cmp(&x.0, &y.0),
+        (GosValue::Complex64(xr, xi), GosValue::Complex64(yr, yi)) => {
+            xr.cmp(yr).then_with(|| xi.cmp(yi))
+        }
+        (GosValue::Complex128(x), GosValue::Complex128(y)) => {
+            x.0.cmp(&y.0).then_with(|| x.1.cmp(&y.1))
+        }
+        (GosValue::Array(x), GosValue::Array(y)) => {
+            let (xd, yd) = (x.0.borrow_data(), y.0.borrow_data());
+            xd.iter()
+                .zip(yd.iter())
+                .map(|(xe, ye)| fmt_key_cmp(&xe.borrow(), &ye.borrow()))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        }
+        (GosValue::Struct(x), GosValue::Struct(y)) => {
+            let (xs, ys) = (x.0.borrow(), y.0.borrow());
+            xs.fields
+                .iter()
+                .zip(ys.fields.iter())
+                .map(|(xf, yf)| fmt_key_cmp(xf, yf))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        }
+        _ if a.get_type() != b.get_type() => a.get_type().cmp(&b.get_type()),
+        _ if a.get_type().is_basic() => a.cmp(b),
+        _ => Ordering::Equal,
+    }
+}
+
 // ----------------------------------------------------------------------------
 // ArrayObj
 
@@ -347,6 +508,19 @@ impl ArrayObj {
         }
     }
 
+    /// copies the elements as if they were assigned one by one
+    pub fn copy_semantic(&self, gcos: &GcoVec) -> ArrayObj {
+        ArrayObj {
+            meta: self.meta,
+            vec: Rc::new(RefCell::new(
+                self.borrow_data()
+                    .iter()
+                    .map(|x| RefCell::new(x.borrow().copy_semantic(gcos)))
+                    .collect(),
+            )),
+        }
+    }
+
     pub fn deep_clone(&self, gcos: &GcoVec) -> ArrayObj {
         ArrayObj {
             meta: self.meta,
@@ -383,6 +557,12 @@ impl ArrayObj {
     pub fn set_from(&self, other: &ArrayObj) {
         *self.borrow_data_mut() = other.borrow_data().clone()
     }
+
+    /// `==` for arrays that may have different types, like the dynamic values
+    /// of two interfaces, arrays of different types are never equal
+    pub fn equals(&self, b: &ArrayObj, metas: &MetadataObjs) -> bool {
+        self.meta.semantic_eq(&b.meta, metas) && self == b
+    }
 }
 
 impl Display for ArrayObj {
@@ -408,17 +588,12 @@ impl Hash for ArrayObj {
 
 impl Eq for ArrayObj {}
 
+/// only valid for arrays of the same type, which the checker guarantees for
+/// the operands of `==`, see `equals` for the other cases
 impl PartialEq for ArrayObj {
     fn eq(&self, b: &ArrayObj) -> bool {
-        if self.borrow_data().len() != b.borrow_data().len() {
-            return false;
-        }
-        for (i, e) in self.borrow_data().iter().enumerate() {
-            if e != b.borrow_data().get(i).unwrap() {
-                return false;
-            }
-        }
-        true
+        let (a_data, b_data) = (self.borrow_data(), b.borrow_data());
+        a_data.len() == b_data.len() && a_data.iter().zip(b_data.iter()).all(|(x, y)| x == y)
     }
 }
 
@@ -452,7 +627,8 @@ impl<'a> SliceObj {
     ) -> SliceObj {
         assert!(cap >= len);
         let mut val: GosVec = Vec::with_capacity(cap);
-        for _ in 0..len {
+        // fill up to cap so that it can be resliced beyond len
+        for _ in 0..cap {
             val.push(RefCell::new(default_val.unwrap().clone()));
         }
         SliceObj {
@@ -476,19 +652,22 @@ impl<'a> SliceObj {
         }
     }
 
-    pub fn with_array(arr: &ArrayObj, begin: isize, end: isize) -> SliceObj {
+    pub fn with_array(
+        arr: &ArrayObj,
+        begin: isize,
+        end: Option<isize>,
+        max: Option<isize>,
+    ) -> RuntimeResult<SliceObj> {
         let elem_meta = GosMetadata::new_slice_from_array(arr.meta);
         let len = arr.len();
-        let self_end = len as isize + 1;
-        let bi = begin as usize;
-        let ei = ((self_end + end) % self_end) as usize;
-        SliceObj {
+        let (bi, ei, mi) = slice_bounds(begin, end, max, len, None)?;
+        Ok(SliceObj {
             meta: elem_meta,
             begin: Cell::from(bi),
             end: Cell::from(ei),
-            soft_cap: Cell::from(len),
+            soft_cap: Cell::from(mi),
             vec: Some(arr.vec.clone()),
-        }
+        })
     }
 
     pub fn new_nil(meta: GosMetadata) -> SliceObj {
@@ -576,21 +755,68 @@ impl<'a> SliceObj {
         }
     }
 
+    /// the value is copied as if by assignment, so that values of struct and
+    /// array types are not shared with the caller
     #[inline]
-    pub fn push(&mut self, val: GosValue) {
+    pub fn push(&mut self, val: GosValue, gcos: &GcoVec) {
         self.try_grow_vec(self.len() + 1);
-        self.borrow_data_mut().push(RefCell::new(val));
+        self.borrow_data_mut()
+            .push(RefCell::new(val.copy_semantic(gcos)));
         *self.end.get_mut() += 1;
     }
 
+    /// append writes vals right after self.end(), overwriting what's already
+    /// in the backing vec if there is enough capacity, just like Go does
+    /// the vals are copied as if by assignment, like in push
     #[inline]
-    pub fn append(&mut self, vals: &mut GosVec) {
+    pub fn append(&mut self, vals: &mut GosVec, gcos: &GcoVec) {
         let new_len = self.len() + vals.len();
         self.try_grow_vec(new_len);
-        self.borrow_data_mut().append(vals);
+        {
+            let mut data = self.borrow_data_mut();
+            let end = self.end();
+            for (i, v) in vals.drain(..).enumerate() {
+                let v = RefCell::new(v.into_inner().copy_semantic(gcos));
+                if end + i < data.len() {
+                    data[end + i] = v;
+                } else {
+                    data.push(v);
+                }
+            }
+        }
         *self.end.get_mut() = self.begin() + new_len;
     }
 
+    /// the builtin copy, copies min(len(self), len(src)) elements as by
+    /// assignment and returns the count, the ranges may overlap
+    pub fn copy_from(&self, src: &SliceObj, gcos: &GcoVec) -> usize {
+        if self.is_nil() || src.is_nil() {
+            return 0;
+        }
+        let count = self.len().min(src.len());
+        // read them all out first, in case the ranges overlap
+        let vals: Vec<GosValue> = src.borrow_data()[src.begin()..src.begin() + count]
+            .iter()
+            .map(|x| x.borrow().copy_semantic(gcos))
+            .collect();
+        let data = self.borrow_data();
+        for (i, v) in vals.into_iter().enumerate() {
+            data[self.begin() + i].replace(v);
+        }
+        count
+    }
+
+    /// sets the elements within the length to copies of zero, the length
+    /// doesn't change
+    pub fn clear(&self, zero: &GosValue, gcos: &GcoVec) {
+        if self.is_nil() {
+            return;
+        }
+        for v in self.borrow().iter() {
+            v.replace(zero.copy_semantic(gcos));
+        }
+    }
+
     #[inline]
     pub fn get(&self, i: usize) -> Option<GosValue> {
         self.borrow_data()
@@ -604,19 +830,22 @@ impl<'a> SliceObj {
     }
 
     #[inline]
-    pub fn slice(&self, begin: isize, end: isize, max: isize) -> SliceObj {
-        let self_len = self.len() as isize + 1;
-        let self_cap = self.cap() as isize + 1;
-        let bi = begin as usize;
-        let ei = ((self_len + end) % self_len) as usize;
-        let mi = ((self_cap + max) % self_cap) as usize;
-        SliceObj {
+    pub fn slice(
+        &self,
+        begin: isize,
+        end: Option<isize>,
+        max: Option<isize>,
+    ) -> RuntimeResult<SliceObj> {
+        // a slice can be extended up to its cap, exposing the elements
+        // still in the backing vec
+        let (bi, ei, mi) = slice_bounds(begin, end, max, self.len(), Some(self.cap()))?;
+        Ok(SliceObj {
             meta: self.meta,
             begin: Cell::from(self.begin() + bi),
             end: Cell::from(self.begin() + ei),
             soft_cap: Cell::from(self.begin() + mi),
             vec: self.vec.clone(),
-        }
+        })
     }
 
     #[inline]
@@ -631,7 +860,7 @@ impl<'a> SliceObj {
     fn try_grow_vec(&mut self, len: usize) {
         let cap = self.cap();
         assert!(cap >= self.len());
-        if cap >= len {
+        if cap >= len && !self.is_nil() {
             return;
         }
         self.grow_vec(cap, len);
@@ -640,14 +869,19 @@ impl<'a> SliceObj {
     fn grow_vec(&mut self, cap: usize, len: usize) {
         let mut cap = cap;
         while cap < len {
-            if cap < 1024 {
+            if cap == 0 {
+                cap = len
+            } else if cap < 1024 {
                 cap *= 2
             } else {
                 cap = (cap as f32 * 1.25) as usize
             }
         }
         let data_len = self.len();
-        let mut vec = Vec::from_iter(self.borrow_data()[self.begin()..self.end()].iter().cloned());
+        let mut vec = match &self.vec {
+            Some(v) => Vec::from_iter(v.borrow()[self.begin()..self.end()].iter().cloned()),
+            None => Vec::new(),
+        };
         vec.reserve_exact(cap - vec.len());
         self.vec = Some(Rc::new(RefCell::new(vec)));
         self.begin.set(0);
@@ -670,6 +904,9 @@ impl Clone for SliceObj {
 
 impl Display for SliceObj {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_nil() {
+            return f.write_str("[]");
+        }
         f.write_char('[')?;
         for (i, e) in self.borrow().iter().enumerate() {
             if i > 0 {
@@ -728,6 +965,15 @@ pub struct StructObj {
 }
 
 impl StructObj {
+    /// copies the fields as if they were assigned one by one, so that fields
+    /// of struct and array types are not shared
+    pub fn copy_semantic(&self, gcos: &GcoVec) -> StructObj {
+        StructObj {
+            meta: self.meta,
+            fields: Vec::from_iter(self.fields.iter().map(|x| x.copy_semantic(gcos))),
+        }
+    }
+
     pub fn deep_clone(&self, gcos: &GcoVec) -> StructObj {
         StructObj {
             meta: self.meta,
@@ -741,6 +987,7 @@ impl Eq for StructObj {}
 impl PartialEq for StructObj {
     #[inline]
     fn eq(&self, other: &StructObj) -> bool {
+        // blank fields are compared too, but they always hold zero values
         for (i, f) in self.fields.iter().enumerate() {
             if f != &other.fields[i] {
                 return false;
@@ -782,11 +1029,30 @@ pub struct UnderlyingFfi {
 }
 
 impl UnderlyingFfi {
-    pub fn new(obj: Rc<RefCell<dyn Ffi>>, methods: Vec<(String, GosMetadata)>) -> UnderlyingFfi {
-        UnderlyingFfi {
+    /// fails with the names of the methods whose metadata are not signatures
+    pub fn new(
+        obj: Rc<RefCell<dyn Ffi>>,
+        methods: Vec<(String, GosMetadata)>,
+        metas: &MetadataObjs,
+    ) -> RuntimeResult<UnderlyingFfi> {
+        let bad: Vec<&str> = methods
+            .iter()
+            .filter(|(_, m)| match m.get_underlying(metas) {
+                GosMetadata::NonPtr(k, _) => !matches!(metas[k], MetadataType::Signature(_)),
+                _ => true,
+            })
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if !bad.is_empty() {
+            return Err(format!(
+                "ffi methods without signatures: {}",
+                bad.join(", ")
+            ));
+        }
+        Ok(UnderlyingFfi {
             ffi_obj: obj,
             methods: methods,
-        }
+        })
     }
 }
 
@@ -960,6 +1226,23 @@ pub trait UserData {
 
     /// If can_make_cycle returns true, implement this to break cycle
     fn break_cycle(&self) {}
+
+    /// Value equality, for when the pointers to the user data are compared and
+    /// they are not the same object. Implementations must override `hash` too,
+    /// so that equal user data hash the same as map keys.
+    fn eq(&self, _other: &dyn UserData) -> bool {
+        false
+    }
+
+    /// How the pointers to the user data are hashed, the address by default
+    fn hash(&self, state: &mut dyn Hasher) {
+        state.write_usize(self as *const Self as *const () as usize);
+    }
+
+    /// How the pointers to the user data are printed, the address by default
+    fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:p}", self)
+    }
 }
 
 impl std::fmt::Debug for dyn UserData {
@@ -1028,23 +1311,6 @@ impl PointerObj {
         }
     }
 
-    pub fn deep_clone(&self, gcos: &GcoVec) -> PointerObj {
-        match &self {
-            PointerObj::Released => PointerObj::Released,
-            PointerObj::Struct(s, m) => PointerObj::Struct(
-                Rc::new((RefCell::new(s.0.borrow().deep_clone(gcos)), Cell::new(0))),
-                *m,
-            ),
-            PointerObj::Slice(s, m) => {
-                PointerObj::Slice(Rc::new((s.0.deep_clone(gcos), Cell::new(0))), *m)
-            }
-            PointerObj::Map(map, m) => {
-                PointerObj::Map(Rc::new((map.0.deep_clone(gcos), Cell::new(0))), *m)
-            }
-            _ => unreachable!(),
-        }
-    }
-
     pub fn as_user_data(&self) -> &Rc<dyn UserData> {
         match self {
             Self::UserData(ud) => ud,
@@ -1081,6 +1347,13 @@ impl PointerObj {
     }
 }
 
+/// pointers to slice members are the same if they point to the same place of the
+/// same data, &a[i] of arrays and of different slices of it create different SliceObjs
+fn slice_member_key(s: &SliceObj, index: OpIndex) -> (*const RefCell<GosVec>, usize) {
+    let vec = s.vec.as_ref().map_or(std::ptr::null(), |v| Rc::as_ptr(v));
+    (vec, s.begin() + index as usize)
+}
+
 impl Eq for PointerObj {}
 
 impl PartialEq for PointerObj {
@@ -1088,13 +1361,17 @@ impl PartialEq for PointerObj {
     fn eq(&self, other: &PointerObj) -> bool {
         match (self, other) {
             (Self::UpVal(x), Self::UpVal(y)) => x == y,
-            (Self::Struct(x, _), Self::Struct(y, _)) => x == y,
-            (Self::Array(x, _), Self::Array(y, _)) => x == y,
-            (Self::Slice(x, _), Self::Slice(y, _)) => x == y,
-            (Self::Map(x, _), Self::Map(y, _)) => x == y,
-            (Self::SliceMember(x, ix), Self::SliceMember(y, iy)) => Rc::ptr_eq(x, y) && ix == iy,
+            (Self::Struct(x, _), Self::Struct(y, _)) => Rc::ptr_eq(x, y),
+            (Self::Array(x, _), Self::Array(y, _)) => Rc::ptr_eq(x, y),
+            (Self::Slice(x, _), Self::Slice(y, _)) => Rc::ptr_eq(x, y),
+            (Self::Map(x, _), Self::Map(y, _)) => Rc::ptr_eq(x, y),
+            (Self::SliceMember(x, ix), Self::SliceMember(y, iy)) => {
+                slice_member_key(&x.0, *ix) == slice_member_key(&y.0, *iy)
+            }
             (Self::StructField(x, ix), Self::StructField(y, iy)) => Rc::ptr_eq(x, y) && ix == iy,
-            (Self::UserData(udx), Self::UserData(udy)) => Rc::ptr_eq(udx, udy),
+            (Self::UserData(udx), Self::UserData(udy)) => {
+                Rc::ptr_eq(udx, udy) || udx.eq(udy.as_ref())
+            }
             (Self::PkgMember(ka, ix), Self::PkgMember(kb, iy)) => ka == kb && ix == iy,
             _ => false,
         }
@@ -1109,10 +1386,7 @@ impl Hash for PointerObj {
             Self::Array(s, _) => Rc::as_ptr(s).hash(state),
             Self::Slice(s, _) => Rc::as_ptr(s).hash(state),
             Self::Map(s, _) => Rc::as_ptr(s).hash(state),
-            Self::SliceMember(s, index) => {
-                Rc::as_ptr(s).hash(state);
-                index.hash(state);
-            }
+            Self::SliceMember(s, index) => slice_member_key(&s.0, *index).hash(state),
             Self::StructField(s, index) => {
                 Rc::as_ptr(s).hash(state);
                 index.hash(state);
@@ -1121,7 +1395,7 @@ impl Hash for PointerObj {
                 p.hash(state);
                 index.hash(state);
             }
-            Self::UserData(ud) => Rc::as_ptr(ud).hash(state),
+            Self::UserData(ud) => UserData::hash(ud.as_ref(), state),
             Self::Released => unreachable!(),
         }
     }
@@ -1138,7 +1412,7 @@ impl Display for PointerObj {
             Self::SliceMember(s, i) => f.write_fmt(format_args!("{:p}i{}", Rc::as_ptr(&s), i)),
             Self::StructField(s, i) => f.write_fmt(format_args!("{:p}i{}", Rc::as_ptr(&s), i)),
             Self::PkgMember(p, i) => f.write_fmt(format_args!("{:x}i{}", key_to_u64(*p), i)),
-            Self::UserData(ud) => f.write_fmt(format_args!("{:p}", Rc::as_ptr(&ud))),
+            Self::UserData(ud) => ud.display(f),
             Self::Released => f.write_str("released!!!"),
         }
     }
@@ -1198,7 +1472,7 @@ pub enum UpValueState {
     Closed(GosValue),
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct UpValue {
     pub inner: Rc<RefCell<UpValueState>>,
 }
@@ -1251,14 +1525,21 @@ impl UpValue {
     }
 }
 
+/// UpValues are compared by identity, all the pointers to the same variable
+/// share one UpValue
+impl PartialEq for UpValue {
+    #[inline]
+    fn eq(&self, other: &UpValue) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for UpValue {}
+
 impl Hash for UpValue {
     #[inline]
     fn hash<H: Hasher>(&self, state: &mut H) {
-        let b: &UpValueState = &self.inner.borrow();
-        match b {
-            UpValueState::Open(desc) => desc.index.hash(state),
-            UpValueState::Closed(_) => Rc::as_ptr(&self.inner).hash(state),
-        }
+        Rc::as_ptr(&self.inner).hash(state)
     }
 }
 
@@ -1380,6 +1661,11 @@ impl PackageVal {
         }
     }
 
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn add_member(&mut self, name: String, val: GosValue) -> OpIndex {
         self.members.push(Rc::new(RefCell::new(val)));
         let index = (self.members.len() - 1) as OpIndex;
@@ -1426,6 +1712,31 @@ impl PackageVal {
     pub fn member_mut(&self, i: OpIndex) -> RefMut<GosValue> {
         self.members[i as usize].borrow_mut()
     }
+
+    /// member_names returns the names of the exported members, in the order
+    /// they are added
+    pub fn member_names(&self) -> impl Iterator<Item = &str> {
+        self.exported_members().into_iter().map(|(name, _)| name)
+    }
+
+    /// iter_members returns the exported members with their names, in the order
+    /// they are added
+    pub fn iter_members(&self) -> impl Iterator<Item = (&str, Ref<'_, GosValue>)> {
+        self.exported_members()
+            .into_iter()
+            .map(move |(name, i)| (name, self.member(i)))
+    }
+
+    fn exported_members(&self) -> Vec<(&str, OpIndex)> {
+        let mut members: Vec<(&str, OpIndex)> = self
+            .member_indices
+            .iter()
+            .filter(|(name, _)| is_exported(name))
+            .map(|(name, i)| (name.as_str(), *i))
+            .collect();
+        members.sort_by_key(|(_, i)| *i);
+        members
+    }
 }
 
 // ----------------------------------------------------------------------------
@@ -1469,6 +1780,8 @@ pub enum FuncFlag {
 pub struct FunctionVal {
     pub package: PackageKey,
     pub meta: GosMetadata,
+    // for backtraces, like main, (*T).M or main.func1, it's empty if unknown
+    pub name: String,
     code: Vec<Instruction>,
     pos: Vec<Option<usize>>,
     pub consts: Vec<GosValue>,
@@ -1501,6 +1814,7 @@ impl FunctionVal {
         FunctionVal {
             package: package,
             meta: meta,
+            name: String::new(),
             code: Vec::new(),
             pos: Vec::new(),
             consts: Vec::new(),
@@ -1530,6 +1844,30 @@ impl FunctionVal {
         &self.pos
     }
 
+    #[inline]
+    pub fn instruction_count(&self) -> usize {
+        self.code.len()
+    }
+
+    /// pos_at returns the source position of the instruction at code_index
+    #[inline]
+    pub fn pos_at(&self, code_index: usize) -> Option<usize> {
+        *self.pos.get(code_index)?
+    }
+
+    /// pc_pos returns the source position of the instruction being executed by a
+    /// frame, whose pc already points to the next instruction
+    #[inline]
+    pub fn pc_pos(&self, pc: usize) -> Option<usize> {
+        self.pos_at(pc.checked_sub(1)?)
+    }
+
+    /// position resolves the source position of the instruction at code_index to
+    /// file:line:col with the file set the function is compiled from
+    pub fn position(&self, code_index: usize, fs: &FileSet) -> Option<Position> {
+        self.pos_at(code_index).map(|p| fs.position(p))
+    }
+
     #[inline]
     pub fn param_count(&self) -> usize {
         self.param_count
@@ -1699,3 +2037,108 @@ impl FunctionVal {
         et
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::value::RtMultiValResult;
+    use super::*;
+
+    #[test]
+    fn test_rune_count() {
+        let s = StringObj::with_str("héllo😀".to_string());
+        assert_eq!(s.len(), 10);
+        assert_eq!(s.rune_count(), 6);
+        assert_eq!(s.rune_at(0), Some(('h', 1)));
+        assert_eq!(s.rune_at(1), Some(('é', 2)));
+        assert_eq!(s.rune_at(2), Some((std::char::REPLACEMENT_CHARACTER, 1)));
+        assert_eq!(s.rune_at(6), Some(('😀', 4)));
+        assert_eq!(s.rune_at(10), None);
+
+        let sub = s.slice(1, Some(6)).unwrap();
+        assert_eq!(sub.rune_count(), 4);
+        assert_eq!(sub.rune_at(0), Some(('é', 2)));
+    }
+
+    #[test]
+    fn test_concat() {
+        let a = StringObj::with_str("héllo".to_string());
+        let b = a.slice(1, Some(3)).unwrap();
+        let empty = StringObj::with_str(String::new());
+        let s = StringObj::concat(&[&a, &empty, &b, &a]);
+        assert_eq!(s.as_str(), "hélloéhéllo");
+        assert_eq!(s.len(), a.len() * 2 + b.len());
+        assert_eq!(StringObj::concat(&[]).as_str(), "");
+    }
+
+    #[test]
+    fn test_array_equals() {
+        let mut objs = VMObjects::new();
+        let (mint, mint8, mstr) = (objs.metadata.mint, objs.metadata.mint8, objs.metadata.mstr);
+        let ints = GosMetadata::new_array(mint, 2, &mut objs.metas);
+        let ints2 = GosMetadata::new_array(mint, 2, &mut objs.metas);
+        let int8s = GosMetadata::new_array(mint8, 2, &mut objs.metas);
+        let a = ArrayObj::with_data(vec![GosValue::Int(1), GosValue::Int(2)], ints);
+        let b = ArrayObj::with_data(vec![GosValue::Int(1), GosValue::Int(2)], ints2);
+        let c = ArrayObj::with_data(vec![GosValue::Int8(1), GosValue::Int8(2)], int8s);
+        assert!(a.equals(&b, &objs.metas));
+        assert!(!a.equals(&c, &objs.metas));
+
+        let empty_ints = GosMetadata::new_array(mint, 0, &mut objs.metas);
+        let empty_strs = GosMetadata::new_array(mstr, 0, &mut objs.metas);
+        let e1 = ArrayObj::with_data(vec![], empty_ints);
+        let e2 = ArrayObj::with_data(vec![], empty_strs);
+        assert!(e1 == e2);
+        assert!(!e1.equals(&e2, &objs.metas));
+        assert!(e1.equals(&e1.clone(), &objs.metas));
+    }
+
+    #[test]
+    fn test_package_members() {
+        let mut pkg = PackageVal::new("main".to_string());
+        pkg.add_member("Alpha".to_string(), GosValue::Int(1));
+        pkg.add_member("beta".to_string(), GosValue::Int(2));
+        pkg.add_member("Gamma".to_string(), GosValue::new_str("g".to_string()));
+        pkg.add_member("_".to_string(), GosValue::Int(3));
+
+        let names: Vec<&str> = pkg.member_names().collect();
+        assert_eq!(names, vec!["Alpha", "Gamma"]);
+        let members: Vec<(&str, GosValue)> =
+            pkg.iter_members().map(|(n, v)| (n, v.clone())).collect();
+        assert_eq!(
+            members,
+            vec![
+                ("Alpha", GosValue::Int(1)),
+                ("Gamma", GosValue::new_str("g".to_string()))
+            ]
+        );
+    }
+
+    struct NoopFfi;
+
+    impl Ffi for NoopFfi {
+        fn call(
+            &self,
+            _: &str,
+            _: Vec<GosValue>,
+        ) -> std::pin::Pin<Box<dyn std::future::Future<Output = RtMultiValResult> + '_>> {
+            Box::pin(async move { Ok(vec![]) })
+        }
+    }
+
+    #[test]
+    fn test_ffi_method_metas() {
+        let objs = VMObjects::new();
+        let md = &objs.metadata;
+        let ffi = || -> Rc<RefCell<dyn Ffi>> { Rc::new(RefCell::new(NoopFfi)) };
+        let good = vec![("run".to_string(), md.default_sig)];
+        assert!(UnderlyingFfi::new(ffi(), good, &objs.metas).is_ok());
+
+        let bad = vec![
+            ("run".to_string(), md.default_sig),
+            ("count".to_string(), md.mint),
+            ("stop".to_string(), md.default_sig),
+        ];
+        let err = UnderlyingFfi::new(ffi(), bad, &objs.metas).unwrap_err();
+        assert_eq!(err, "ffi methods without signatures: count");
+    }
+}
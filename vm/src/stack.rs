@@ -1,8 +1,9 @@
 #![allow(dead_code)]
 use super::gc::GcoVec;
 use super::instruction::{Instruction, OpIndex, Opcode, ValueType};
-use super::metadata::GosMetadata;
+use super::metadata::{GosMetadata, MetaCategory, MetadataType};
 use super::value::*;
+use super::vm_util;
 use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::fmt::{self, Display};
@@ -55,13 +56,13 @@ macro_rules! store_to_with_op {
         $op:expr,
         $t:expr) => {{
         if $t.copyable() {
-            let a = $from.get_c($li);
+            let a = $to.get_c($li);
             let b = $from.get_c($ri);
             *$to.get_c_mut($li) = GosValue64::binary_op(a, b, $t, $op);
         } else {
-            let a = $from.get_rc($li);
+            let a = $to.get_rc($li);
             let b = $from.get_rc($ri);
-            *$to.get_rc_mut($li) = GosValue::add_str(a, b);
+            *$to.get_rc_mut($li) = GosValue::binary_op(a, b, $t, $op);
         }
     }};
 }
@@ -257,17 +258,20 @@ impl Stack {
         ret
     }
 
+    /// returns None if it's a nil interface
     #[inline]
-    pub fn pop_interface(&mut self) -> Rc<RefCell<InterfaceObj>> {
+    pub fn pop_interface(&mut self) -> Option<Rc<RefCell<InterfaceObj>>> {
         self.cursor -= 1;
         let mut ret = GosValue::new_nil();
         std::mem::swap(self.get_rc_mut(self.cursor), &mut ret);
         match ret {
-            GosValue::Interface(i) => i,
+            GosValue::Interface(i) => Some(i),
             GosValue::Named(n) => match &n.0 {
-                GosValue::Interface(i) => i.clone(),
+                GosValue::Interface(i) => Some(i.clone()),
+                GosValue::Nil(_) => None,
                 _ => unreachable!(),
             },
+            GosValue::Nil(_) => None,
             _ => unreachable!(),
         }
     }
@@ -413,7 +417,7 @@ impl Stack {
                 let v = GosValue64::binary_op(&a, b, t, op);
                 v.get_v128(t)
             } else {
-                GosValue::add_str(target, self.get_rc(ri))
+                GosValue::binary_op(target, self.get_rc(ri), t, op)
             }
         };
         *target = val;
@@ -443,13 +447,31 @@ impl Stack {
         if t.copyable() {
             stack_binary_op!(self, binary_op_add, t)
         } else {
-            let a = self.get_rc(self.len() - 2);
-            let b = self.get_rc(self.len() - 1);
-            *self.get_rc_mut(self.len() - 2) = GosValue::add_str(a, b);
-            self.pop_discard();
+            self.binary_op_rc(t, Opcode::ADD)
         }
     }
 
+    #[inline]
+    fn binary_op_rc(&mut self, t: ValueType, op: Opcode) {
+        let a = self.get_rc(self.len() - 2);
+        let b = self.get_rc(self.len() - 1);
+        *self.get_rc_mut(self.len() - 2) = GosValue::binary_op(a, b, t, op);
+        self.pop_discard();
+    }
+
+    /// replaces the top `n` strings with their concatenation
+    pub fn concat(&mut self, n: usize) {
+        let begin = self.len() - n;
+        let s = {
+            let parts: Vec<&StringObj> = (begin..self.len())
+                .map(|i| self.get_rc(i).as_str().as_ref())
+                .collect();
+            StringObj::concat(&parts)
+        };
+        *self.get_rc_mut(begin) = GosValue::Str(Rc::new(s));
+        self.pop_discard_n(n - 1);
+    }
+
     #[inline]
     pub fn switch_cmp(&mut self, t: ValueType, objs: &VMObjects) -> bool {
         let b = if t.copyable() {
@@ -460,10 +482,26 @@ impl Stack {
         } else {
             let a = self.get_rc(self.len() - 2);
             let b = self.get_rc(self.len() - 1);
-            if t != ValueType::Metadata {
-                a.eq(&b)
-            } else {
-                a.as_meta().semantic_eq(b.as_meta(), &objs.metas)
+            match (t, b) {
+                (ValueType::Metadata, GosValue::Nil(_)) => {
+                    // "case nil" of a type switch only matches a nil interface
+                    a.as_meta() == &GosMetadata::Untyped
+                }
+                (ValueType::Metadata, _) => {
+                    let (a, b) = (a.as_meta(), b.as_meta());
+                    match b.get_underlying(&objs.metas) {
+                        // an interface case matches any type implementing it
+                        GosMetadata::NonPtr(k, MetaCategory::Default) => match &objs.metas[k] {
+                            MetadataType::Interface(fields) => {
+                                a != &GosMetadata::Untyped
+                                    && vm_util::iface_methods(a, fields, objs).is_some()
+                            }
+                            _ => a.semantic_eq(b, &objs.metas),
+                        },
+                        _ => a.semantic_eq(b, &objs.metas),
+                    }
+                }
+                _ => a.eq(&b),
             }
         };
         self.pop_discard();
@@ -472,17 +510,29 @@ impl Stack {
 
     #[inline]
     pub fn sub(&mut self, t: ValueType) {
-        stack_binary_op!(self, binary_op_sub, t)
+        if t.copyable() {
+            stack_binary_op!(self, binary_op_sub, t)
+        } else {
+            self.binary_op_rc(t, Opcode::SUB)
+        }
     }
 
     #[inline]
     pub fn mul(&mut self, t: ValueType) {
-        stack_binary_op!(self, binary_op_mul, t)
+        if t.copyable() {
+            stack_binary_op!(self, binary_op_mul, t)
+        } else {
+            self.binary_op_rc(t, Opcode::MUL)
+        }
     }
 
     #[inline]
     pub fn quo(&mut self, t: ValueType) {
-        stack_binary_op!(self, binary_op_quo, t)
+        if t.copyable() {
+            stack_binary_op!(self, binary_op_quo, t)
+        } else {
+            self.binary_op_rc(t, Opcode::QUO)
+        }
     }
 
     #[inline]
@@ -541,6 +591,25 @@ impl Stack {
         self.get_c_mut(self.len() - 1).unary_not(t);
     }
 
+    /// pops the operands of a comparison of copyable values and returns the
+    /// result instead of pushing it
+    #[inline]
+    pub fn pop_compare(&mut self, op: Opcode, t: ValueType) -> bool {
+        let len = self.len();
+        let (a, b) = (self.get_c(len - 2), self.get_c(len - 1));
+        let re = match op {
+            Opcode::EQL => GosValue64::compare_eql(a, b, t),
+            Opcode::NEQ => GosValue64::compare_neq(a, b, t),
+            Opcode::LSS => GosValue64::compare_lss(a, b, t),
+            Opcode::GTR => GosValue64::compare_gtr(a, b, t),
+            Opcode::LEQ => GosValue64::compare_leq(a, b, t),
+            Opcode::GEQ => GosValue64::compare_geq(a, b, t),
+            _ => unreachable!(),
+        };
+        self.pop_discard_n(2);
+        re
+    }
+
     #[inline]
     pub fn compare_eql(&mut self, t: ValueType) {
         if t.copyable() {
@@ -641,8 +710,12 @@ impl Stack {
 
 /// store iterators for Opcode::RANGE
 pub struct RangeStack {
-    maps: Vec<GosHashMapIter<'static>>,
-    slices: Vec<SliceEnumIter<'static>>,
+    // the map, a snapshot of its keys and the cursor, so that entries can be
+    // added or deleted in the loop body. the entries deleted before they are
+    // reached are skipped, the ones added are not produced
+    maps: Vec<(MapObj, Vec<GosValue>, usize)>,
+    // a copy of the slice header and the cursor
+    slices: Vec<(SliceObj, usize)>,
     strings: Vec<StringEnumIter<'static>>,
 }
 
@@ -658,15 +731,13 @@ impl RangeStack {
     pub fn range_init(&mut self, target: &GosValue) {
         match target {
             GosValue::Map(m) => {
-                let map = m.0.borrow_data();
-                let iter = unsafe { mem::transmute(map.iter()) };
-                self.maps.push(iter);
-            }
-            GosValue::Slice(sl) => {
-                let slice = sl.0.borrow();
-                let iter = unsafe { mem::transmute(slice.iter().enumerate()) };
-                self.slices.push(iter);
+                let keys =
+                    m.0.map
+                        .as_ref()
+                        .map_or(vec![], |x| x.borrow().keys().cloned().collect());
+                self.maps.push((m.0.clone(), keys, 0));
             }
+            GosValue::Slice(sl) => self.slices.push((sl.0.clone(), 0)),
             GosValue::Str(s) => {
                 let iter = unsafe { mem::transmute(s.iter().enumerate()) };
                 self.strings.push(iter);
@@ -677,28 +748,34 @@ impl RangeStack {
 
     pub fn range_body(&mut self, typ: ValueType, stack: &mut Stack) -> bool {
         match typ {
-            ValueType::Map => match self.maps.last_mut().unwrap().next() {
-                Some((k, v)) => {
-                    stack.push(k.clone());
-                    stack.push(v.clone().into_inner());
-                    false
-                }
-                None => {
-                    self.maps.pop();
-                    true
+            ValueType::Map => {
+                let (map, keys, cursor) = self.maps.last_mut().unwrap();
+                while *cursor < keys.len() {
+                    let k = &keys[*cursor];
+                    *cursor += 1;
+                    if let Some(v) = map.try_get(k) {
+                        stack.push(k.clone());
+                        stack.push(v);
+                        return false;
+                    }
                 }
-            },
-            ValueType::Slice => match self.slices.last_mut().unwrap().next() {
-                Some((k, v)) => {
-                    stack.push_int(k as isize);
-                    stack.push(v.clone().into_inner());
+                self.maps.pop();
+                true
+            }
+            ValueType::Slice => {
+                let (slice, cursor) = self.slices.last_mut().unwrap();
+                // the header is copied at init, so appending to the ranged slice
+                // in the loop body doesn't change the number of iterations
+                if *cursor < slice.len() {
+                    stack.push_int(*cursor as isize);
+                    stack.push(slice.get(*cursor).unwrap());
+                    *cursor += 1;
                     false
-                }
-                None => {
+                } else {
                     self.slices.pop();
                     true
                 }
-            },
+            }
             ValueType::Str => match self.strings.last_mut().unwrap().next() {
                 Some((k, v)) => {
                     stack.push_int(k as isize);
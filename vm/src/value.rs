@@ -1,4 +1,5 @@
 //#![allow(dead_code)]
+use super::complex;
 use super::gc::{GcWeak, GcoVec};
 use super::instruction::{Opcode, ValueType};
 use super::metadata::*;
@@ -93,6 +94,24 @@ macro_rules! binary_op_int_float {
     };
 }
 
+macro_rules! binary_op_int_wrap {
+    ($t:ident, $a:ident, $b:ident, $op:tt) => {
+        match $t {
+            ValueType::Int => union_op_wrap!($a, $b, int, $op),
+            ValueType::Int8 => union_op_wrap!($a, $b, int8, $op),
+            ValueType::Int16 => union_op_wrap!($a, $b, int16, $op),
+            ValueType::Int32 => union_op_wrap!($a, $b, int32, $op),
+            ValueType::Int64 => union_op_wrap!($a, $b, int64, $op),
+            ValueType::Uint => union_op_wrap!($a, $b, uint, $op),
+            ValueType::Uint8 => union_op_wrap!($a, $b, uint8, $op),
+            ValueType::Uint16 => union_op_wrap!($a, $b, uint16, $op),
+            ValueType::Uint32 => union_op_wrap!($a, $b, uint32, $op),
+            ValueType::Uint64 => union_op_wrap!($a, $b, uint64, $op),
+            _ => unreachable!(),
+        }
+    };
+}
+
 macro_rules! binary_op_int_no_wrap {
     ($t:ident, $a:ident, $b:ident, $op:tt) => {
         match $t {
@@ -170,6 +189,9 @@ macro_rules! shift_int {
     };
 }
 
+/// Floats are truncated toward zero. Out of range floats, which Go leaves
+/// implementation-defined, saturate to the bounds of the target type and NaN
+/// becomes zero, as Rust's `as` does.
 macro_rules! convert_to_int {
     ($val:expr, $vt:expr, $d_type:tt, $typ:tt) => {{
         unsafe {
@@ -334,14 +356,20 @@ impl GosValue {
     }
 
     #[inline]
-    pub fn slice_with_array(arr: &GosValue, begin: isize, end: isize, gcobjs: &GcoVec) -> GosValue {
+    pub fn slice_with_array(
+        arr: &GosValue,
+        begin: isize,
+        end: Option<isize>,
+        max: Option<isize>,
+        gcobjs: &GcoVec,
+    ) -> RtValueResult {
         let s = Rc::new((
-            SliceObj::with_array(&arr.as_array().0, begin, end),
+            SliceObj::with_array(&arr.as_array().0, begin, end, max)?,
             Cell::new(0),
         ));
         let v = GosValue::Slice(s);
         gcobjs.add(&v);
-        v
+        Ok(v)
     }
 
     #[inline]
@@ -436,6 +464,21 @@ impl GosValue {
         unwrap_gos_val!(Uint8, self)
     }
 
+    #[inline]
+    pub fn as_uint16(&self) -> &u16 {
+        unwrap_gos_val!(Uint16, self)
+    }
+
+    #[inline]
+    pub fn as_uint32(&self) -> &u32 {
+        unwrap_gos_val!(Uint32, self)
+    }
+
+    #[inline]
+    pub fn as_uint64(&self) -> &u64 {
+        unwrap_gos_val!(Uint64, self)
+    }
+
     #[inline]
     pub fn as_int32(&self) -> &i32 {
         unwrap_gos_val!(Int32, self)
@@ -532,6 +575,14 @@ impl GosValue {
         }
     }
 
+    pub fn try_get_slice(&self) -> Option<&Rc<(SliceObj, RCount)>> {
+        match &self {
+            GosValue::Slice(_) => Some(self.as_slice()),
+            GosValue::Named(n) => Some(n.0.as_slice()),
+            _ => None,
+        }
+    }
+
     pub fn try_get_map(&self) -> Option<&Rc<(MapObj, RCount)>> {
         match &self {
             GosValue::Map(_) => Some(self.as_map()),
@@ -559,11 +610,59 @@ impl GosValue {
         }
     }
 
+    /// deep_eq compares two values the way reflect.DeepEqual does: slices, arrays,
+    /// maps and structs are equal when their elements are, the values behind
+    /// interfaces are compared deeply, and everything else is compared with ==
+    pub fn deep_eq(&self, other: &GosValue, metas: &MetadataObjs) -> bool {
+        match (self, other) {
+            // the metadata of identical types are not always the same, nils of the
+            // same kind are considered equal
+            (GosValue::Nil(x), GosValue::Nil(y)) => x.kind(metas) == y.kind(metas),
+            (GosValue::Nil(m), v) | (v, GosValue::Nil(m)) => match v {
+                GosValue::Slice(s) => s.0.is_nil() && m.kind(metas) == MetaKind::Slice,
+                GosValue::Map(mo) => mo.0.is_nil() && m.kind(metas) == MetaKind::Map,
+                _ => false,
+            },
+            (GosValue::Slice(x), GosValue::Slice(y)) => {
+                if x.0.is_nil() || y.0.is_nil() {
+                    return x.0.is_nil() && y.0.is_nil();
+                }
+                let (a, b) = (x.0.get_vec(), y.0.get_vec());
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(v, w)| v.deep_eq(w, metas))
+            }
+            (GosValue::Array(x), GosValue::Array(y)) => {
+                let (a, b) = (x.0.borrow_data(), y.0.borrow_data());
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|(v, w)| v.borrow().deep_eq(&w.borrow(), metas))
+            }
+            (GosValue::Map(x), GosValue::Map(y)) => x.0.structural_eq(&y.0, metas),
+            (GosValue::Struct(x), GosValue::Struct(y)) => {
+                let (a, b) = (x.0.borrow(), y.0.borrow());
+                a.fields.len() == b.fields.len()
+                    && a.fields
+                        .iter()
+                        .zip(b.fields.iter())
+                        .all(|(v, w)| v.deep_eq(w, metas))
+            }
+            (GosValue::Interface(x), GosValue::Interface(y)) => {
+                let (a, b) = (x.borrow(), y.borrow());
+                match (a.underlying_value(), b.underlying_value()) {
+                    (Some(v), Some(w)) => v.deep_eq(w, metas),
+                    _ => *a == *b,
+                }
+            }
+            (GosValue::Named(x), GosValue::Named(y)) => x.1 == y.1 && x.0.deep_eq(&y.0, metas),
+            _ => self == other,
+        }
+    }
+
     #[inline]
     pub fn equals_nil(&self) -> bool {
         match &self {
             GosValue::Nil(_) => true,
-            GosValue::Named(n) => n.0.is_nil(),
+            GosValue::Named(n) => n.0.equals_nil(),
             GosValue::Slice(s) => s.0.is_nil(),
             GosValue::Map(m) => m.0.is_nil(),
             GosValue::Interface(iface) => iface.borrow().is_nil(),
@@ -571,6 +670,28 @@ impl GosValue {
         }
     }
 
+    /// slices, maps and funcs, and the structs and arrays containing them
+    /// can only be compared to nil
+    pub fn is_comparable(&self) -> bool {
+        match &self {
+            GosValue::Slice(_) | GosValue::Map(_) | GosValue::Closure(_) => false,
+            GosValue::Named(n) => n.0.is_comparable(),
+            GosValue::Struct(s) => s.0.borrow().fields.iter().all(|x| x.is_comparable()),
+            GosValue::Array(a) => a.0.borrow_data().iter().all(|x| x.borrow().is_comparable()),
+            _ => true,
+        }
+    }
+
+    /// values of named basic types are only wrapped up with their metadata
+    /// while being held by interfaces
+    #[inline]
+    pub fn unwrap_named_basic(self) -> GosValue {
+        match self {
+            GosValue::Named(n) if n.0.get_type().is_basic() => n.0,
+            _ => self,
+        }
+    }
+
     #[inline]
     pub fn get_type(&self) -> ValueType {
         match self {
@@ -606,8 +727,13 @@ impl GosValue {
         }
     }
 
+    /// nils are only identical when they are of the same type, so that the typed
+    /// nils of different types are not merged into one constant
     pub fn identical(&self, other: &GosValue) -> bool {
-        self.get_type() == other.get_type() && self == other
+        match (self, other) {
+            (GosValue::Nil(x), GosValue::Nil(y)) => x == y,
+            _ => self.get_type() == other.get_type() && self == other,
+        }
     }
 
     pub fn get_meta(&self, objs: &VMObjects, stack: &Stack) -> GosMetadata {
@@ -686,9 +812,13 @@ impl GosValue {
         }
     }
 
+    /// returns a copy of the value as made by an assignment.
+    /// values of empty struct types have nothing to copy or modify, so all of
+    /// them share the zero value in their metadata
     #[inline]
     pub fn copy_semantic(&self, gcos: &GcoVec) -> GosValue {
         match self {
+            GosValue::Struct(s) if s.0.borrow().fields.is_empty() => self.clone(),
             GosValue::Slice(s) => {
                 let rc = Rc::new((SliceObj::clone(&s.0), Cell::new(0)));
                 gcos.add_weak(GcWeak::Slice(Rc::downgrade(&rc)));
@@ -699,8 +829,13 @@ impl GosValue {
                 gcos.add_weak(GcWeak::Map(Rc::downgrade(&rc)));
                 GosValue::Map(rc)
             }
+            GosValue::Array(arr) => {
+                let rc = Rc::new((arr.0.copy_semantic(gcos), Cell::new(0)));
+                gcos.add_weak(GcWeak::Array(Rc::downgrade(&rc)));
+                GosValue::Array(rc)
+            }
             GosValue::Struct(s) => {
-                let rc = Rc::new((RefCell::clone(&s.0), Cell::new(0)));
+                let rc = Rc::new((RefCell::new(s.0.borrow().copy_semantic(gcos)), Cell::new(0)));
                 gcos.add_weak(GcWeak::Struct(Rc::downgrade(&rc)));
                 GosValue::Struct(rc)
             }
@@ -726,6 +861,8 @@ impl GosValue {
         }
     }
 
+    /// deep_clone duplicates the containers but not what pointers point to,
+    /// just like assignment in Go, so cycles through pointers are not followed
     #[inline]
     pub fn deep_clone(&self, gcos: &GcoVec) -> GosValue {
         match self {
@@ -749,7 +886,6 @@ impl GosValue {
                 gcos.add_weak(GcWeak::Struct(Rc::downgrade(&rc)));
                 GosValue::Struct(rc)
             }
-            GosValue::Pointer(p) => GosValue::Pointer(Box::new(p.deep_clone(gcos))),
             GosValue::Named(v) => GosValue::Named(Box::new((v.0.deep_clone(gcos), v.1))),
             _ => self.clone(),
         }
@@ -757,9 +893,17 @@ impl GosValue {
 
     #[inline]
     pub fn add_str(a: &GosValue, b: &GosValue) -> GosValue {
-        let mut s = a.as_str().as_str().to_string();
-        s.push_str(b.as_str().as_str());
-        GosValue::new_str(s)
+        GosValue::Str(Rc::new(StringObj::concat(&[a.as_str(), b.as_str()])))
+    }
+
+    /// binary operations on the values that are not copyable, which are
+    /// string concatenation and complex128 arithmetic
+    #[inline]
+    pub fn binary_op(a: &GosValue, b: &GosValue, t: ValueType, op: Opcode) -> GosValue {
+        match t {
+            ValueType::Str => GosValue::add_str(a, b),
+            _ => complex::binary_op(a, b, op),
+        }
     }
 
     /// for gc
@@ -886,7 +1030,7 @@ impl PartialEq for GosValue {
             (Self::Interface(x), Self::Interface(y)) => InterfaceObj::eq(&x.borrow(), &y.borrow()),
             (Self::Struct(x), Self::Struct(y)) => StructObj::eq(&x.0.borrow(), &y.0.borrow()),
             (Self::Channel(x), Self::Channel(y)) => Rc::ptr_eq(x, y),
-            (Self::Named(x), Self::Named(y)) => x.0 == y.0,
+            (Self::Named(x), Self::Named(y)) => x.1 == y.1 && x.0 == y.0,
             (Self::Nil(_), nil) | (nil, Self::Nil(_)) => nil.equals_nil(),
             (Self::Interface(iface), val) | (val, Self::Interface(iface)) => {
                 match iface.borrow().underlying_value() {
@@ -908,6 +1052,11 @@ impl PartialOrd for GosValue {
 
 impl Hash for GosValue {
     fn hash<H: Hasher>(&self, state: &mut H) {
+        // interfaces hash as their dynamic values, which are kept apart by type
+        if let GosValue::Interface(i) = &self {
+            return i.borrow().hash(state);
+        }
+        std::mem::discriminant(self).hash(state);
         match &self {
             GosValue::Bool(b) => b.hash(state),
             GosValue::Int(i) => i.hash(state),
@@ -935,13 +1084,13 @@ impl Hash for GosValue {
             GosValue::Struct(s) => {
                 s.0.borrow().hash(state);
             }
-            GosValue::Interface(i) => {
-                i.borrow().hash(state);
-            }
             GosValue::Pointer(p) => {
                 PointerObj::hash(&p, state);
             }
-            GosValue::Named(n) => n.0.hash(state),
+            GosValue::Named(n) => {
+                n.1.hash(state);
+                n.0.hash(state);
+            }
             _ => unreachable!(),
         }
     }
@@ -990,8 +1139,8 @@ impl Display for GosValue {
             GosValue::Uint64(i) => write!(f, "{}", i),
             GosValue::Float32(fl) => write!(f, "{}", fl),
             GosValue::Float64(fl) => write!(f, "{}", fl),
-            GosValue::Complex64(r, i) => write!(f, "({}, {})", r, i),
-            GosValue::Complex128(b) => write!(f, "({}, {})", b.0, b.1),
+            GosValue::Complex64(r, i) => complex::fmt(f, r.0, i.0),
+            GosValue::Complex128(b) => complex::fmt(f, b.0 .0, b.1 .0),
             GosValue::Str(s) => f.write_str(s.as_ref().as_str()),
             GosValue::Array(a) => write!(f, "{}", a.0),
             GosValue::Pointer(p) => p.fmt(f),
@@ -1338,27 +1487,50 @@ impl GosValue64 {
 
     #[inline]
     pub fn binary_op_add(a: &GosValue64, b: &GosValue64, t: ValueType) -> GosValue64 {
-        unsafe { binary_op_int_float!(t, a, b, +) }
+        match t {
+            ValueType::Complex64 => GosValue64::binary_op_complex64(a, b, complex::complex_add),
+            _ => unsafe { binary_op_int_float!(t, a, b, +) },
+        }
     }
 
     #[inline]
     pub fn binary_op_sub(a: &GosValue64, b: &GosValue64, t: ValueType) -> GosValue64 {
-        unsafe { binary_op_int_float!(t, a, b, -) }
+        match t {
+            ValueType::Complex64 => GosValue64::binary_op_complex64(a, b, complex::complex_sub),
+            _ => unsafe { binary_op_int_float!(t, a, b, -) },
+        }
     }
 
     #[inline]
     pub fn binary_op_mul(a: &GosValue64, b: &GosValue64, t: ValueType) -> GosValue64 {
-        unsafe { binary_op_int_float!(t, a, b, *) }
+        match t {
+            ValueType::Complex64 => GosValue64::binary_op_complex64(a, b, complex::complex_mul),
+            _ => unsafe { binary_op_int_float!(t, a, b, *) },
+        }
     }
 
     #[inline]
     pub fn binary_op_quo(a: &GosValue64, b: &GosValue64, t: ValueType) -> GosValue64 {
-        unsafe { binary_op_int_float!(t, a, b, /) }
+        match t {
+            ValueType::Complex64 => GosValue64::binary_op_complex64(a, b, complex::complex_div),
+            _ => unsafe { binary_op_int_float!(t, a, b, /) },
+        }
+    }
+
+    #[inline]
+    fn binary_op_complex64(
+        a: &GosValue64,
+        b: &GosValue64,
+        f: fn(&GosValue, &GosValue) -> GosValue,
+    ) -> GosValue64 {
+        let t = ValueType::Complex64;
+        GosValue64::from_v128(&f(&a.get_v128(t), &b.get_v128(t))).unwrap()
     }
 
     #[inline]
     pub fn binary_op_rem(a: &GosValue64, b: &GosValue64, t: ValueType) -> GosValue64 {
-        unsafe { binary_op_int_no_wrap!(t, a, b, %) }
+        // truncated remainder like Go, and MinInt % -1 is 0 instead of an overflow
+        unsafe { binary_op_int_wrap!(t, a, b, %) }
     }
 
     #[inline]
@@ -1459,12 +1631,18 @@ impl GosValue64 {
 
     #[inline]
     pub fn compare_eql(a: &GosValue64, b: &GosValue64, t: ValueType) -> bool {
-        unsafe { cmp_bool_int_float!(t, a, b, ==) }
+        match t {
+            ValueType::Complex64 => unsafe { a.data.complex64 == b.data.complex64 },
+            _ => unsafe { cmp_bool_int_float!(t, a, b, ==) },
+        }
     }
 
     #[inline]
     pub fn compare_neq(a: &GosValue64, b: &GosValue64, t: ValueType) -> bool {
-        unsafe { cmp_bool_int_float!(t, a, b, !=) }
+        match t {
+            ValueType::Complex64 => !GosValue64::compare_eql(a, b, t),
+            _ => unsafe { cmp_bool_int_float!(t, a, b, !=) },
+        }
     }
 
     #[inline]
@@ -1490,7 +1668,9 @@ impl GosValue64 {
 
 #[cfg(test)]
 mod test {
+    use super::super::complex;
     use super::super::value::*;
+    use std::any::Any;
     use std::collections::HashMap;
     use std::mem;
 
@@ -1528,4 +1708,270 @@ mod test {
         dbg!(h[&0]);
         dbg!(h2[&0]);
     }
+
+    fn hash_of(v: &GosValue) -> u64 {
+        let mut h = std::collections::hash_map::DefaultHasher::new();
+        v.hash(&mut h);
+        h.finish()
+    }
+
+    #[test]
+    fn test_hash_type() {
+        let s1 = GosValue::new_str("Norway".to_string());
+        let s2 = GosValue::new_str("Norway".to_string());
+        assert_eq!(hash_of(&s1), hash_of(&s2));
+        assert_ne!(hash_of(&GosValue::Int(1)), hash_of(&GosValue::Int64(1)));
+        assert_ne!(hash_of(&GosValue::Int(1)), hash_of(&GosValue::Uint(1)));
+    }
+
+    #[test]
+    fn test_deep_clone_shares_pointees() {
+        let gcos = GcoVec::new();
+        let obj = StructObj {
+            meta: GosMetadata::Untyped,
+            fields: vec![GosValue::Int(1), GosValue::new_nil()],
+        };
+        let s = GosValue::new_struct(obj, &gcos);
+        let rc = s.as_struct().clone();
+        // the struct points to itself
+        let p = GosValue::new_pointer(PointerObj::Struct(rc.clone(), GosMetadata::Untyped));
+        rc.0.borrow_mut().fields[1] = p;
+
+        let cloned = s.deep_clone(&gcos);
+        let crc = cloned.as_struct();
+        assert!(!Rc::ptr_eq(&rc, crc));
+        crc.0.borrow_mut().fields[0] = GosValue::Int(2);
+        assert_eq!(rc.0.borrow().fields[0], GosValue::Int(1));
+        match &crc.0.borrow().fields[1] {
+            GosValue::Pointer(p) => match p.as_ref() {
+                PointerObj::Struct(target, _) => assert!(Rc::ptr_eq(&rc, target)),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+        // break the cycle
+        rc.0.borrow_mut().fields[1] = GosValue::new_nil();
+    }
+
+    #[test]
+    fn test_empty_struct_shared() {
+        let mut objs = VMObjects::new();
+        let mut gcos = GcoVec::new();
+        let empty =
+            GosMetadata::new_struct(Fields::new(vec![], HashMap::new()), &mut objs, &mut gcos);
+        let a = empty.zero_val(&objs.metas, &gcos);
+        let b = a.copy_semantic(&gcos);
+        assert!(Rc::ptr_eq(a.as_struct(), b.as_struct()));
+        assert!(Rc::ptr_eq(
+            a.as_struct(),
+            empty.zero_val(&objs.metas, &gcos).as_struct()
+        ));
+
+        let fields = Fields::new(vec![objs.metadata.mint], HashMap::new());
+        let one = GosMetadata::new_struct(fields, &mut objs, &mut gcos);
+        let c = one.zero_val(&objs.metas, &gcos);
+        assert!(!Rc::ptr_eq(
+            c.as_struct(),
+            c.copy_semantic(&gcos).as_struct()
+        ));
+    }
+
+    #[test]
+    fn test_deep_eq() {
+        let mut objs = VMObjects::new();
+        let gcv = GcoVec::new();
+        let (mint, mstr) = (objs.metadata.mint, objs.metadata.mstr);
+        let metas = &mut objs.metas;
+        let mslice = GosMetadata::new_slice(mint, metas);
+        let mmap = GosMetadata::new_map(mstr, mslice, metas);
+        // another metadata of the same type, as if it was from somewhere else
+        let mmap2 = GosMetadata::new_map(mstr, mslice, metas);
+
+        let ints = |v: &[isize]| {
+            let vals = v.iter().map(|x| GosValue::Int(*x)).collect();
+            GosValue::slice_with_val(vals, mslice, &gcv)
+        };
+        let new_map = |meta, entries: &[(&str, &[isize])]| {
+            let m = GosValue::new_map(meta, GosValue::new_slice_nil(mslice, &gcv), &gcv);
+            for (k, v) in entries.iter() {
+                m.as_map()
+                    .0
+                    .insert(GosValue::new_str(k.to_string()), ints(v));
+            }
+            m
+        };
+
+        let a = new_map(mmap, &[("x", &[1, 2]), ("y", &[])]);
+        let b = new_map(mmap2, &[("y", &[]), ("x", &[1, 2])]);
+        assert!(a.as_map().0.structural_eq(&b.as_map().0, metas));
+        assert!(a.deep_eq(&b, metas));
+        assert!(a.deep_eq(&a, metas));
+
+        let c = new_map(mmap, &[("x", &[1, 3]), ("y", &[])]);
+        let d = new_map(mmap, &[("x", &[1, 2]), ("z", &[])]);
+        let e = new_map(mmap, &[("x", &[1, 2])]);
+        assert!(!a.deep_eq(&c, metas));
+        assert!(!a.deep_eq(&d, metas));
+        assert!(!a.deep_eq(&e, metas));
+        assert!(!e.deep_eq(&a, metas));
+
+        // a nil map or slice only equals another nil one
+        let nil_map = GosValue::new_map_nil(mmap, GosValue::new_slice_nil(mslice, &gcv), &gcv);
+        let empty_map = new_map(mmap, &[]);
+        assert!(nil_map.deep_eq(&nil_map.copy_semantic(&gcv), metas));
+        assert!(nil_map.deep_eq(&GosValue::Nil(mmap2), metas));
+        assert!(!nil_map.deep_eq(&empty_map, metas));
+        assert!(!nil_map.deep_eq(&GosValue::Nil(mslice), metas));
+        let with_nil = new_map(mmap, &[]);
+        with_nil.as_map().0.insert(
+            GosValue::new_str("y".to_string()),
+            GosValue::new_slice_nil(mslice, &gcv),
+        );
+        assert!(!with_nil.deep_eq(&new_map(mmap, &[("y", &[])]), metas));
+
+        // the values behind interfaces are compared deeply
+        let md = &objs.metadata;
+        let ia = GosValue::new_empty_iface(md, a.clone());
+        let ib = GosValue::new_empty_iface(md, b.clone());
+        let ic = GosValue::new_empty_iface(md, c.clone());
+        assert!(ia.deep_eq(&ib, &objs.metas));
+        assert!(!ia.deep_eq(&ic, &objs.metas));
+        assert!(GosValue::Int(1).deep_eq(&GosValue::Int(1), &objs.metas));
+        assert!(!GosValue::Int(1).deep_eq(&GosValue::Int64(1), &objs.metas));
+    }
+
+    /// an amount of money, equal to the same amount in another object
+    struct Money(i64);
+
+    impl UserData for Money {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn eq(&self, other: &dyn UserData) -> bool {
+            other
+                .as_any()
+                .downcast_ref::<Money>()
+                .map_or(false, |m| m.0 == self.0)
+        }
+
+        fn hash(&self, state: &mut dyn Hasher) {
+            state.write_i64(self.0);
+        }
+
+        fn display(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "${}", self.0)
+        }
+    }
+
+    struct Opaque;
+
+    impl UserData for Opaque {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn test_user_data_eq() {
+        let objs = VMObjects::new();
+        let money = |n| GosValue::new_pointer(PointerObj::UserData(Rc::new(Money(n))));
+        let (five, other_five, six) = (money(5), money(5), money(6));
+        assert!(five == other_five);
+        assert!(five != six);
+        assert_eq!(format!("{}", six), "$6");
+
+        // also when they are in interfaces
+        let iface = |v: &GosValue| GosValue::new_empty_iface(&objs.metadata, v.clone());
+        assert!(iface(&five) == iface(&other_five));
+        assert!(iface(&five) != iface(&six));
+
+        // without the hook only the same object is equal
+        let opaque = GosValue::new_pointer(PointerObj::UserData(Rc::new(Opaque)));
+        assert!(opaque == opaque.clone());
+        assert!(opaque != GosValue::new_pointer(PointerObj::UserData(Rc::new(Opaque))));
+        assert!(opaque != five && five != opaque);
+
+        // equal ones hash the same, other objects by their addresses
+        let hash = |v: &GosValue| {
+            let mut h = std::collections::hash_map::DefaultHasher::new();
+            v.hash(&mut h);
+            h.finish()
+        };
+        let other_opaque = GosValue::new_pointer(PointerObj::UserData(Rc::new(Opaque)));
+        assert_eq!(hash(&five), hash(&other_five));
+        assert_ne!(hash(&five), hash(&six));
+        assert_eq!(hash(&opaque), hash(&opaque.clone()));
+        assert_ne!(hash(&opaque), hash(&other_opaque));
+        let mut m = HashMap::new();
+        m.insert(five.clone(), 1);
+        m.insert(other_five.clone(), 2);
+        m.insert(opaque.clone(), 3);
+        m.insert(other_opaque, 4);
+        assert_eq!(m.len(), 3);
+        assert_eq!(m.get(&five), Some(&2));
+        assert_eq!(m.get(&opaque), Some(&3));
+    }
+
+    fn c128(r: f64, i: f64) -> GosValue {
+        GosValue::Complex128(Box::new((r.into(), i.into())))
+    }
+
+    fn c64(r: f32, i: f32) -> GosValue {
+        GosValue::Complex64(r.into(), i.into())
+    }
+
+    #[test]
+    fn test_complex_eq() {
+        assert!(c128(1.0, 2.0) == c128(1.0, 2.0));
+        assert!(c128(1.0, 2.0) != c128(1.0, 3.0));
+        assert!(c128(0.0, 1.0) == c128(-0.0, 1.0));
+        assert!(c64(1.0, 2.0) == c64(1.0, 2.0));
+
+        // like floats, the components compare as ordered floats, so NaN equals NaN
+        // and a map has a single NaN key
+        let nan128 = c128(1.0, f64::NAN);
+        assert!(nan128 == nan128.clone());
+        assert!(nan128 != c128(f64::NAN, 1.0));
+        let t = ValueType::Complex64;
+        let nan64 = GosValue64::from_v128(&c64(f32::NAN, 0.0)).unwrap();
+        assert!(GosValue64::compare_eql(&nan64, &nan64, t));
+        assert!(!GosValue64::compare_neq(&nan64, &nan64, t));
+        let one64 = GosValue64::from_v128(&c64(1.0, 0.0)).unwrap();
+        assert!(GosValue64::compare_eql(&one64, &one64, t));
+        assert!(GosValue64::compare_neq(&one64, &nan64, t));
+
+        let mut m = HashMap::new();
+        m.insert(c128(1.0, 2.0), 1);
+        m.insert(c128(1.0, 2.0), 2);
+        m.insert(nan128.clone(), 3);
+        m.insert(nan128.clone(), 4);
+        assert_eq!(m.len(), 2);
+        assert_eq!(m.get(&c128(1.0, 2.0)), Some(&2));
+        assert_eq!(m.get(&nan128), Some(&4));
+    }
+
+    #[test]
+    fn test_complex_arith() {
+        let (a, b) = (c128(1.0, 2.0), c128(3.0, 4.0));
+        assert_eq!(complex::complex_mul(&a, &b).to_string(), "(-5+10i)");
+        assert_eq!(complex::complex_div(&a, &b).to_string(), "(0.44+0.08i)");
+        assert_eq!(complex::complex_add(&a, &b).to_string(), "(4+6i)");
+        assert_eq!(complex::conj(&a).to_string(), "(1-2i)");
+        assert_eq!(complex::abs(&b).to_string(), "5");
+
+        // complex64 keeps its kind and goes through the c lane of the stack
+        let t = ValueType::Complex64;
+        let (a, b) = (c64(1.0, 2.0), c64(3.0, 4.0));
+        assert_eq!(complex::complex_mul(&a, &b), c64(-5.0, 10.0));
+        let a64 = GosValue64::from_v128(&a).unwrap();
+        let b64 = GosValue64::from_v128(&b).unwrap();
+        let q = GosValue64::binary_op(&a64, &b64, t, Opcode::QUO);
+        assert_eq!(q.get_v128(t).to_string(), "(0.44+0.08i)");
+
+        // dividing by zero gives infinities like Go instead of panicking
+        let z = complex::complex_div(&a, &c64(0.0, 0.0));
+        assert_eq!(z.to_string(), "(+Inf+Infi)");
+        assert_eq!(c128(f64::NAN, -0.0).to_string(), "(NaN-0i)");
+    }
 }
@@ -4,26 +4,37 @@ use super::ffi::FfiFactory;
 use super::gc::{gc, GcoVec};
 use super::instruction::*;
 use super::metadata::*;
-use super::objects::{u64_to_key, ClosureObj, GosHashMap};
+use super::objects::{u64_to_key, ClosureObj, GosHashMap, GosVec};
 use super::stack::{RangeStack, Stack};
 use super::value::*;
 use super::vm_util;
 use async_executor::LocalExecutor;
-use futures_lite::future;
+use futures_lite::{future, FutureExt};
 use goscript_parser::FileSet;
+use std::any::Any;
 use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
 use std::pin::Pin;
 use std::ptr;
 use std::rc::Rc;
 use std::str;
 
+/// An interface conversion: the interface, the methods of the source type in the
+/// order of the interface methods, and the source type itself
+pub type IfaceBinding = (
+    GosMetadata,
+    Option<Rc<Vec<FunctionKey>>>,
+    Option<GosMetadata>,
+);
+
 #[derive(Debug)]
 pub struct ByteCode {
     pub objects: Pin<Box<VMObjects>>,
     pub packages: Vec<PackageKey>,
-    pub ifaces: Vec<(GosMetadata, Option<Rc<Vec<FunctionKey>>>)>,
+    pub ifaces: Vec<IfaceBinding>,
     pub entry: FunctionKey,
 }
 
@@ -83,6 +94,31 @@ impl CallFrame {
         }
     }
 
+    fn find_referred_by(&self, index: OpIndex) -> Option<UpValue> {
+        self.referred_by
+            .as_ref()?
+            .get(&index)?
+            .weaks
+            .iter()
+            .find_map(|w| w.upgrade())
+    }
+
+    /// closes the upvalues pointing to the local var at `index` and forgets them,
+    /// the closures created so far keep its current value and the ones created
+    /// afterwards refer to it anew
+    fn close_referred_by(&mut self, index: OpIndex, stack: &Stack) {
+        let referrers = match self.referred_by.as_mut().and_then(|m| m.remove(&index)) {
+            Some(r) => r,
+            None => return,
+        };
+        let val = stack.get_with_type(Stack::offset(self.stack_base, index), referrers.typ);
+        for weak in referrers.weaks.iter() {
+            if let Some(uv) = weak.upgrade() {
+                uv.close(val.clone());
+            }
+        }
+    }
+
     #[inline]
     fn func(&self) -> FunctionKey {
         self.closure.0.borrow().func.unwrap()
@@ -134,13 +170,104 @@ enum Result {
 struct PanicData {
     msg: GosValue,
     call_stack: Vec<(FunctionKey, usize)>,
+    // the frames below this height are being unwound, the ones above it are
+    // deferred calls and what they call, which run normally
+    unwind_height: usize,
+    // the panic going on when this one was raised by a deferred call
+    previous: Option<Box<PanicData>>,
 }
 
 impl PanicData {
-    fn new(m: GosValue) -> PanicData {
+    fn new(m: GosValue, height: usize, previous: Option<PanicData>) -> PanicData {
         PanicData {
             msg: m,
             call_stack: vec![],
+            unwind_height: height,
+            previous: previous.map(Box::new),
+        }
+    }
+
+    /// Recovers this panic. The previous one goes on if this one is recovered
+    /// before unwinding the deferred call that raised it, otherwise it's aborted.
+    fn recover(self) -> (GosValue, Option<PanicData>) {
+        let unwind_height = self.unwind_height;
+        let previous = self
+            .previous
+            .filter(|p| p.unwind_height < unwind_height)
+            .map(|p| *p);
+        (self.msg, previous)
+    }
+
+    /// The values of the panics this one replaced, oldest first
+    fn aborted(&self) -> Vec<GosValue> {
+        let mut v = vec![];
+        let mut p = &self.previous;
+        while let Some(data) = p {
+            v.push(data.msg.clone());
+            p = &data.previous;
+        }
+        v.reverse();
+        v
+    }
+}
+
+/// What is passed to the panic hook when a goroutine ends with an unrecovered panic
+pub struct PanicInfo {
+    /// the value passed to panic(), as an interface
+    pub value: GosValue,
+    /// the values of the panics that were going on when deferred calls
+    /// raised this one, oldest first
+    pub aborted: Vec<GosValue>,
+    /// the value formatted the way the default output prints it
+    pub message: String,
+    /// source positions of the call stack, innermost first
+    pub call_stack: Vec<String>,
+    /// the package name, the function name and the source position of each
+    /// frame of the call stack, innermost first
+    pub backtrace: Vec<(String, String, String)>,
+    suppress: Cell<bool>,
+}
+
+impl PanicInfo {
+    /// Keeps the VM from printing the panic message and call stack
+    pub fn suppress_default_output(&self) {
+        self.suppress.set(true);
+    }
+
+    pub fn is_default_output_suppressed(&self) -> bool {
+        self.suppress.get()
+    }
+}
+
+pub type PanicHook<'a> = dyn Fn(&PanicInfo) + 'a;
+
+pub type Output = Rc<RefCell<dyn Write>>;
+
+/// Where the output of the program goes, the standard output and error of the
+/// process are used for the ones that are None
+#[derive(Clone, Default)]
+pub struct Outputs {
+    pub stdout: Option<Output>,
+    pub stderr: Option<Output>,
+}
+
+impl Outputs {
+    pub fn print_out(&self, s: &str) {
+        match &self.stdout {
+            // like print!, which doesn't report errors either
+            Some(w) => {
+                let _ = w.borrow_mut().write_all(s.as_bytes());
+            }
+            None => print!("{}", s),
+        }
+    }
+
+    pub fn print_err(&self, s: &str) {
+        match &self.stderr {
+            Some(w) => {
+                let _ = w.borrow_mut().write_all(s.as_bytes());
+            }
+            None => eprint!("{}", s),
         }
     }
 }
@@ -152,6 +279,11 @@ struct Context<'a> {
     gcv: &'a GcoVec,
     ffi_factory: &'a FfiFactory,
     fs: Option<&'a FileSet>,
+    panic_hook: Option<&'a PanicHook<'a>>,
+    panic_nil_error: bool,
+    outputs: &'a Outputs,
+    // the Rust panic of a fiber, which the executor would swallow
+    crash: Rc<RefCell<Option<Box<dyn Any + Send>>>>,
 }
 
 impl<'a> Context<'a> {
@@ -161,6 +293,9 @@ impl<'a> Context<'a> {
         gcv: &'a GcoVec,
         ffi_factory: &'a FfiFactory,
         fs: Option<&'a FileSet>,
+        panic_hook: Option<&'a PanicHook<'a>>,
+        panic_nil_error: bool,
+        outputs: &'a Outputs,
     ) -> Context<'a> {
         Context {
             exec: exec,
@@ -168,6 +303,10 @@ impl<'a> Context<'a> {
             gcv: gcv,
             ffi_factory: ffi_factory,
             fs: fs,
+            panic_hook: panic_hook,
+            panic_nil_error: panic_nil_error,
+            outputs: outputs,
+            crash: Rc::new(RefCell::new(None)),
         }
     }
 
@@ -178,11 +317,14 @@ impl<'a> Context<'a> {
 
     fn spawn_fiber(&self, stack: Stack, first_frame: CallFrame) {
         let mut f = Fiber::new(self.clone(), stack, first_frame);
+        let crash = self.crash.clone();
         self.exec
             .spawn(async move {
                 // let parent fiber go first
                 future::yield_now().await;
-                f.main_loop().await;
+                if let Err(e) = AssertUnwindSafe(f.main_loop()).catch_unwind().await {
+                    crash.borrow_mut().get_or_insert(e);
+                }
             })
             .detach();
     }
@@ -287,6 +429,7 @@ impl<'a> Fiber<'a> {
                         );
                         frame = self.frames.last_mut().unwrap();
                     }
+                    Opcode::CLOSE_UPVALUE => frame.close_referred_by(inst.imm(), &stack),
                     Opcode::LOAD_INDEX => {
                         let ind = stack.pop_with_type(inst.t1());
                         let mut val = &stack.pop_with_type(inst.t0());
@@ -297,7 +440,7 @@ impl<'a> Fiber<'a> {
                             match vm_util::load_index(val, &ind) {
                                 Ok(v) => stack.push(v),
                                 Err(e) => {
-                                    go_panic_str!(panic, &objs.metadata, e, frame, code);
+                                    go_panic_str!(panic, &objs.metadata, e, frame, frame_height, code);
                                 }
                             }
                         } else {
@@ -311,7 +454,7 @@ impl<'a> Fiber<'a> {
                             match vm_util::load_index_int(val, index) {
                                 Ok(v) => stack.push(v),
                                 Err(e) => {
-                                    go_panic_str!(panic, metadata, e, frame, code);
+                                    go_panic_str!(panic, metadata, e, frame, frame_height, code);
                                 }
                             }
                         } else {
@@ -343,7 +486,7 @@ impl<'a> Fiber<'a> {
                             inst.t0(),
                             gcv,
                         ) {
-                            go_panic_str!(panic, metadata, e, frame, code);
+                            go_panic_str!(panic, metadata, e, frame, frame_height, code);
                         }
                     }
                     Opcode::LOAD_FIELD => {
@@ -354,6 +497,11 @@ impl<'a> Fiber<'a> {
                     Opcode::LOAD_STRUCT_FIELD => {
                         let ind = inst.imm();
                         let mut target = stack.pop_with_type(inst.t0());
+                        if target.is_nil() {
+                            let msg = vm_util::NIL_DEREF_MSG.to_string();
+                            go_panic_str!(panic, metadata, msg, frame, frame_height, code);
+                            continue;
+                        }
                         if let GosValue::Pointer(_) = &target {
                             target = deref_value!(target, self, stack, self.frames, objs);
                             frame = self.frames.last_mut().unwrap();
@@ -364,8 +512,7 @@ impl<'a> Fiber<'a> {
                             }
                             GosValue::Struct(sval) => sval.0.borrow().fields[ind as usize].clone(),
                             _ => {
-                                dbg!(&target);
-                                unreachable!()
+                                internal_error!("not a struct", inst, frame, objs, ctx.fs);
                             }
                         };
 
@@ -388,7 +535,9 @@ impl<'a> Fiber<'a> {
                         let val = match &val {
                             GosValue::Named(n) => n.0.clone(),
                             GosValue::Interface(_) => val,
-                            _ => unreachable!(),
+                            _ => {
+                                internal_error!("not an interface", inst, frame, objs, ctx.fs);
+                            }
                         };
                         let borrowed = val.as_interface().borrow();
                         let cls = match borrowed.underlying() {
@@ -397,7 +546,7 @@ impl<'a> Fiber<'a> {
                                 let cls = ClosureObj::new_gos(
                                     func,
                                     &objs.functions,
-                                    Some(val.copy_semantic(gcv)),
+                                    Some(val.copy_semantic(gcv).unwrap_named_basic()),
                                 );
                                 GosValue::Closure(Rc::new((RefCell::new(cls), Cell::new(0))))
                             }
@@ -415,7 +564,7 @@ impl<'a> Fiber<'a> {
                             }
                             IfaceUnderlying::None => {
                                 let msg = "access nil interface".to_string();
-                                go_panic_str!(panic, metadata, msg, frame, code);
+                                go_panic_str!(panic, metadata, msg, frame, frame_height, code);
                                 continue;
                             }
                         };
@@ -427,6 +576,11 @@ impl<'a> Fiber<'a> {
                         let s_index = Stack::offset(stack.len(), index);
                         let key = stack.get_with_type(s_index + 1, inst.t2());
                         let target = stack.get_with_type(s_index, inst.t1());
+                        if inst.t1() == ValueType::Pointer && target.is_nil() {
+                            let msg = vm_util::NIL_DEREF_MSG.to_string();
+                            go_panic_str!(panic, metadata, msg, frame, frame_height, code);
+                            continue;
+                        }
                         match target {
                             GosValue::Pointer(_) => {
                                 let unboxed = deref_value!(target, self, stack, self.frames, objs);
@@ -457,6 +611,11 @@ impl<'a> Fiber<'a> {
                         let index = inst.t2_as_index();
                         let s_index = Stack::offset(stack.len(), index);
                         let mut target = stack.get_with_type(s_index, inst.t1());
+                        if target.is_nil() {
+                            let msg = vm_util::NIL_DEREF_MSG.to_string();
+                            go_panic_str!(panic, metadata, msg, frame, frame_height, code);
+                            continue;
+                        }
                         if let GosValue::Pointer(_) = &target {
                             target = deref_value!(target, self, stack, self.frames, objs);
                             frame = self.frames.last_mut().unwrap();
@@ -472,8 +631,7 @@ impl<'a> Fiber<'a> {
                                 stack.store_val(field, rhs_index, inst.t0(), gcv);
                             }
                             _ => {
-                                dbg!(&target);
-                                unreachable!()
+                                internal_error!("not a struct", inst, frame, objs, ctx.fs);
                             }
                         }
                     }
@@ -491,7 +649,13 @@ impl<'a> Fiber<'a> {
                     Opcode::STORE_DEREF => {
                         let (rhs_index, index) = inst.imm824();
                         let s_index = Stack::offset(stack.len(), index);
-                        match stack.get_with_type(s_index, ValueType::Pointer) {
+                        let target = stack.get_with_type(s_index, ValueType::Pointer);
+                        if target.is_nil() {
+                            let msg = vm_util::NIL_DEREF_MSG.to_string();
+                            go_panic_str!(panic, metadata, msg, frame, frame_height, code);
+                            continue;
+                        }
+                        match target {
                             GosValue::Pointer(b) => {
                                 let r: &PointerObj = &b;
                                 match r {
@@ -521,7 +685,7 @@ impl<'a> Fiber<'a> {
                                     PointerObj::Slice(r, _) => {
                                         let rhs_s_index = Stack::offset(stack.len(), rhs_index);
                                         let val = stack.get_with_type(rhs_s_index, inst.t0());
-                                        r.0.set_from(&val.as_slice().0);
+                                        r.0.set_from(&val.try_get_slice().unwrap().0);
                                     }
                                     PointerObj::Map(r, _) => {
                                         let rhs_s_index = Stack::offset(stack.len(), rhs_index);
@@ -546,61 +710,77 @@ impl<'a> Fiber<'a> {
                                             &mut objs.packages[*p].member_mut(*index);
                                         stack.store_val(target, rhs_index, inst.t0(), gcv);
                                     }
-                                    // todo: report error instead of crash
-                                    PointerObj::UserData(_) => unreachable!(),
-                                    PointerObj::Released => unreachable!(),
+                                    PointerObj::UserData(_) | PointerObj::Released => {
+                                        internal_error!(
+                                            "storing through an invalid pointer",
+                                            inst,
+                                            frame,
+                                            objs,
+                                            ctx.fs
+                                        );
+                                    }
                                 };
                             }
-                            _ => unreachable!(),
+                            _ => {
+                                internal_error!("not a pointer", inst, frame, objs, ctx.fs);
+                            }
                         }
                     }
                     Opcode::CAST => {
                         let (target, mapping) = inst.imm824();
                         let rhs_s_index = Stack::offset(stack.len(), target);
                         match inst.t0() {
+                            // an untyped nil becomes a nil slice or map
+                            _ if inst.t1() == ValueType::Nil => {
+                                let zero = consts[mapping as usize].copy_semantic(gcv);
+                                stack.set(rhs_s_index, zero);
+                            }
                             ValueType::Interface => {
                                 let iface = ifaces[mapping as usize].clone();
-                                let under = stack.get_with_type(rhs_s_index, inst.t1());
-                                let val = match &objs.metas[iface.0.as_non_ptr()] {
-                                    MetadataType::Named(_, md) => GosValue::Named(Box::new((
-                                        GosValue::new_iface(
-                                            *md,
-                                            IfaceUnderlying::Gos(under, iface.1),
-                                        ),
-                                        iface.0,
-                                    ))),
-                                    MetadataType::Interface(_) => GosValue::new_iface(
-                                        iface.0,
-                                        IfaceUnderlying::Gos(under, iface.1),
-                                    ),
-                                    _ => unreachable!(),
-                                };
+                                let mut under = stack.get_with_type(rhs_s_index, inst.t1());
+                                // a nil from an untyped nil literal gets the dynamic type
+                                if let (GosValue::Nil(_), Some(src)) = (&under, iface.2) {
+                                    under = GosValue::Nil(src);
+                                }
+                                let val =
+                                    vm_util::new_iface_value(iface.0, under, iface.1, &objs.metas);
                                 stack.set(rhs_s_index, val);
                             }
+                            ValueType::Named => {
+                                // named basic values carry their metadata into interfaces
+                                let meta = *consts[mapping as usize].as_meta();
+                                let under = stack.get_with_type(rhs_s_index, inst.t1());
+                                stack.set(rhs_s_index, GosValue::Named(Box::new((under, meta))));
+                            }
                             ValueType::Str => {
                                 let result = match inst.t1() {
                                     ValueType::Slice => {
                                         let slice = stack.get_rc(rhs_s_index).as_slice();
-                                        match inst.t2() {
-                                            ValueType::Int32 => slice
-                                                .0
-                                                .borrow_data()
-                                                .iter()
-                                                .map(|x| {
-                                                    vm_util::char_from_i32(*(x.borrow().as_int32()))
-                                                })
-                                                .collect(),
-                                            ValueType::Uint8 => {
-                                                let buf: Vec<u8> = slice
-                                                    .0
-                                                    .borrow_data()
+                                        // the elements are copied out, the string never
+                                        // shares the slice's backing array
+                                        if slice.0.is_nil() {
+                                            String::new()
+                                        } else {
+                                            let data = slice.0.borrow();
+                                            match inst.t2() {
+                                                ValueType::Int32 => data
                                                     .iter()
-                                                    .map(|x| *(x.borrow().as_uint8()))
-                                                    .collect();
-                                                // todo: error handling
-                                                str::from_utf8(&buf).unwrap().to_string()
+                                                    .map(|x| {
+                                                        vm_util::char_from_i32(
+                                                            *(x.borrow().as_int32()),
+                                                        )
+                                                    })
+                                                    .collect(),
+                                                ValueType::Uint8 => {
+                                                    let buf: Vec<u8> = data
+                                                        .iter()
+                                                        .map(|x| *(x.borrow().as_uint8()))
+                                                        .collect();
+                                                    // todo: error handling
+                                                    str::from_utf8(&buf).unwrap().to_string()
+                                                }
+                                                _ => unreachable!(),
                                             }
-                                            _ => unreachable!(),
                                         }
                                     }
                                     _ => {
@@ -664,12 +844,64 @@ impl<'a> Fiber<'a> {
                     Opcode::OR => stack.or(inst.t0()),
                     Opcode::XOR => stack.xor(inst.t0()),
                     Opcode::AND_NOT => stack.and_not(inst.t0()),
+                    Opcode::CONCAT => stack.concat(inst.imm() as usize),
                     Opcode::SHL => stack.shl(inst.t0(), inst.t1()),
                     Opcode::SHR => stack.shr(inst.t0(), inst.t1()),
                     Opcode::UNARY_ADD => {}
                     Opcode::UNARY_SUB => stack.unary_negate(inst.t0()),
                     Opcode::UNARY_XOR => stack.unary_xor(inst.t0()),
                     Opcode::NOT => stack.logical_not(inst.t0()),
+                    Opcode::EQL | Opcode::NEQ if inst.t0() == ValueType::Interface => {
+                        let len = stack.len();
+                        let a = stack.get_with_type(len - 2, ValueType::Interface);
+                        let b = stack.get_with_type(len - 1, ValueType::Interface);
+                        match vm_util::check_iface_comparable(&a, &b, objs, stack) {
+                            Ok(()) => {
+                                let eq = vm_util::iface_eq(&a, &b, objs);
+                                stack.pop_discard_n(2);
+                                stack.push_bool(eq == (inst_op == Opcode::EQL));
+                            }
+                            Err(e) => {
+                                go_panic_str!(panic, metadata, e, frame, frame_height, code);
+                            }
+                        }
+                    }
+                    Opcode::EQL | Opcode::NEQ
+                        if matches!(
+                            inst.t0(),
+                            ValueType::Array | ValueType::Struct | ValueType::Named
+                        ) =>
+                    {
+                        let len = stack.len();
+                        let a = stack.get_with_type(len - 2, inst.t0());
+                        let b = stack.get_with_type(len - 1, inst.t0());
+                        let eq = vm_util::compare_eql(&a, &b, &objs.metas);
+                        stack.pop_discard_n(2);
+                        stack.push_bool(eq == (inst_op == Opcode::EQL));
+                    }
+                    Opcode::EQL
+                    | Opcode::NEQ
+                    | Opcode::LSS
+                    | Opcode::GTR
+                    | Opcode::LEQ
+                    | Opcode::GEQ
+                        if inst.t0().copyable() =>
+                    {
+                        let re = stack.pop_compare(inst_op, inst.t0());
+                        // the conditions of if and for are followed by a jump,
+                        // which takes the result here without it going through
+                        // the stack
+                        let next = code[frame.pc];
+                        match next.op() {
+                            Opcode::JUMP_IF | Opcode::JUMP_IF_NOT => {
+                                frame.pc += 1;
+                                if re == (next.op() == Opcode::JUMP_IF) {
+                                    frame.pc = Stack::offset(frame.pc, next.imm());
+                                }
+                            }
+                            _ => stack.push_bool(re),
+                        }
+                    }
                     Opcode::EQL => stack.compare_eql(inst.t0()),
                     Opcode::LSS => stack.compare_lss(inst.t0()),
                     Opcode::GTR => stack.compare_gtr(inst.t0()),
@@ -683,7 +915,7 @@ impl<'a> Fiber<'a> {
                         let re = chan.as_channel().send(&val).await;
                         restore_stack_ref!(self, stack, stack_mut_ref);
                         if let Err(e) = re {
-                            go_panic_str!(panic, metadata, e, frame, code);
+                            go_panic_str!(panic, metadata, e, frame, frame_height, code);
                         }
                     }
                     Opcode::RECV => {
@@ -717,18 +949,42 @@ impl<'a> Fiber<'a> {
                     Opcode::REF_SLICE_MEMBER => {
                         let index = stack.pop_int();
                         let typ = inst.t0();
-                        let mut slice = stack.pop_with_type(typ);
-                        // create a slice if it's an array
-                        if typ == ValueType::Array {
-                            slice = GosValue::slice_with_array(&slice, 0, -1, gcv);
+                        let mut target = stack.pop_with_type(typ);
+                        // &p[i] with p a pointer to an array
+                        if typ == ValueType::Pointer {
+                            if target.is_nil() {
+                                let msg = vm_util::NIL_DEREF_MSG.to_string();
+                                go_panic_str!(panic, metadata, msg, frame, frame_height, code);
+                                continue;
+                            }
+                            target = deref_value!(target, self, stack, self.frames, objs);
+                        }
+                        if let GosValue::Named(n) = target {
+                            target = n.0;
+                        }
+                        // create a slice if it's an array, it shares the elements
+                        if let GosValue::Array(_) = target {
+                            target =
+                                GosValue::slice_with_array(&target, 0, None, None, gcv).unwrap();
+                        }
+                        let slice = target.as_slice();
+                        if index < 0 || index as usize >= slice.0.len() {
+                            let msg = vm_util::index_out_of_range(index as usize, slice.0.len());
+                            go_panic_str!(panic, metadata, msg, frame, frame_height, code);
+                            continue;
                         }
                         stack.push(GosValue::new_pointer(PointerObj::SliceMember(
-                            slice.as_slice().clone(),
+                            slice.clone(),
                             index.try_into().unwrap(),
                         )));
                     }
                     Opcode::REF_STRUCT_FIELD => {
                         let mut struct_ = stack.pop_with_type(inst.t0());
+                        if struct_.is_nil() {
+                            let msg = vm_util::NIL_DEREF_MSG.to_string();
+                            go_panic_str!(panic, metadata, msg, frame, frame_height, code);
+                            continue;
+                        }
                         // todo: do this check in codegen
                         if inst.t0() == ValueType::Pointer {
                             struct_ = deref_value!(struct_, self, stack, self.frames, objs);
@@ -736,7 +992,9 @@ impl<'a> Fiber<'a> {
                         let struct_ = match &struct_ {
                             GosValue::Named(n) => n.0.clone(),
                             GosValue::Struct(_) => struct_,
-                            _ => unreachable!(),
+                            _ => {
+                                internal_error!("not a struct", inst, frame, objs, ctx.fs);
+                            }
                         };
                         stack.push(GosValue::new_pointer(PointerObj::StructField(
                             struct_.as_struct().clone(),
@@ -758,12 +1016,22 @@ impl<'a> Fiber<'a> {
                     }
                     Opcode::DEREF => {
                         let boxed = stack.pop_with_type(inst.t0());
+                        if boxed.is_nil() {
+                            let msg = vm_util::NIL_DEREF_MSG.to_string();
+                            go_panic_str!(panic, metadata, msg, frame, frame_height, code);
+                            continue;
+                        }
                         let val = deref_value!(boxed, self, stack, self.frames, objs);
                         stack.push(val);
                         frame = self.frames.last_mut().unwrap();
                     }
                     Opcode::PRE_CALL => {
                         let val = stack.pop_with_type(ValueType::Closure);
+                        if val.is_nil() {
+                            let msg = vm_util::NIL_DEREF_MSG.to_string();
+                            go_panic_str!(panic, metadata, msg, frame, frame_height, code);
+                            continue;
+                        }
                         let cls_rc = val.as_closure();
                         let cls: &ClosureObj = &*cls_rc.0.borrow();
                         let next_frame = CallFrame::with_closure(cls_rc.clone(), stack.len());
@@ -839,11 +1107,16 @@ impl<'a> Fiber<'a> {
                                     ValueType::FlagA => {
                                         // goroutine
                                         nframe.stack_base = 0;
-                                        let nstack = Stack::move_from(stack, nfunc.param_count());
+                                        // the arguments are copied now, the callee may
+                                        // share the values it does not modify
+                                        let (c, rc) = stack.pop_n(nfunc.param_count());
+                                        let rc = rc.iter().map(|x| x.copy_semantic(gcv)).collect();
+                                        let nstack = Stack::with_data(c, rc);
                                         self.context.spawn_fiber(nstack, nframe);
                                     }
                                     ValueType::FlagB => {
                                         let (c, rc) = stack.pop_n(nfunc.param_count());
+                                        let rc = rc.iter().map(|x| x.copy_semantic(gcv)).collect();
                                         let deferred = DeferredCall {
                                             frame: nframe,
                                             stack_c: c,
@@ -856,10 +1129,8 @@ impl<'a> Fiber<'a> {
                             }
                             None => {
                                 let call = cls.ffi.as_ref().unwrap();
-                                let ptypes = &objs.metas[call.meta.as_non_ptr()]
-                                    .as_signature()
-                                    .params_type;
-                                let params = stack.pop_with_type_n(ptypes);
+                                let sig = objs.metas[call.meta.as_non_ptr()].as_signature();
+                                let params = stack.pop_with_type_n(&sig.params_type);
                                 // release stack so that code in ffi can yield
                                 drop(stack_mut_ref);
                                 let ffi_ref = call.ffi.borrow();
@@ -867,9 +1138,27 @@ impl<'a> Fiber<'a> {
                                 let returns = fut.await;
                                 restore_stack_ref!(self, stack, stack_mut_ref);
                                 match returns {
-                                    Ok(result) => stack.append(result),
+                                    Ok(result) if result.len() == sig.results.len() => {
+                                        stack.append(result)
+                                    }
+                                    Ok(result) => {
+                                        let msg = format!(
+                                            "ffi {} returned {} values, {} expected",
+                                            call.func_name,
+                                            result.len(),
+                                            sig.results.len()
+                                        );
+                                        go_panic_str!(
+                                            panic,
+                                            metadata,
+                                            msg,
+                                            frame,
+                                            frame_height,
+                                            code
+                                        );
+                                    }
                                     Err(e) => {
-                                        go_panic_str!(panic, &objs.metadata, e, frame, code);
+                                        go_panic_str!(panic, &objs.metadata, e, frame, frame_height, code);
                                     }
                                 }
                             }
@@ -886,15 +1175,9 @@ impl<'a> Fiber<'a> {
                                 stack.truncate(stack_base + frame.ret_count(objs));
                             }
                             // init_package func
+                            // the vars have been stored by INIT_PKG
                             ValueType::FlagA => {
-                                let index = inst.imm() as usize;
-                                let pkey = pkgs[index];
-                                let pkg = &objs.packages[pkey];
-                                let count = pkg.var_count();
-                                // remove garbage first
-                                debug_assert!(stack.len() == stack_base + count);
-                                // the var values left on the stack are for pkg members
-                                stack.init_pkg_vars(pkg, count);
+                                stack.truncate(stack_base);
                             }
                             // func with deferred calls
                             ValueType::FlagB => {
@@ -951,8 +1234,13 @@ impl<'a> Fiber<'a> {
                         code = func.code();
 
                         if let Some(p) = &mut panic {
-                            p.call_stack.push((frame.func(), frame.pc - 1));
-                            frame.pc = code.len() - 1;
+                            // returning from a deferred call doesn't unwind the frame
+                            // that runs it, it's already in the call stack
+                            if frame_height < p.unwind_height {
+                                p.call_stack.push((frame.func(), frame.pc - 1));
+                                p.unwind_height = frame_height;
+                                frame.pc = code.len() - 1;
+                            }
                         }
                     }
 
@@ -1045,7 +1333,7 @@ impl<'a> Fiber<'a> {
                                 frame.pc = Stack::offset(frame.pc, (blocks - 1) + block_offset);
                             }
                             Err(e) => {
-                                go_panic_str!(panic, &objs.metadata, e, frame, code);
+                                go_panic_str!(panic, &objs.metadata, e, frame, frame_height, code);
                             }
                         }
                     }
@@ -1064,60 +1352,142 @@ impl<'a> Fiber<'a> {
                     }
 
                     Opcode::TYPE_ASSERT => {
-                        let val = match stack.pop_interface().borrow().underlying() {
-                            IfaceUnderlying::Gos(v, _) => v.copy_semantic(gcv),
-                            _ => GosValue::new_nil(),
-                        };
-                        let meta = GosValue::Metadata(val.get_meta(objs, stack));
-                        stack.push(val);
-                        let ok = &consts[inst.imm() as usize] == &meta;
                         let do_try = inst.t2_as_index() > 0;
-                        if !do_try {
-                            if !ok {
-                                // todo go_panic
-                                unimplemented!()
-                            }
+                        // the static type of the interface, for the panic message
+                        let iface_meta = if do_try {
+                            None
                         } else {
-                            stack.push_bool(ok);
+                            let top = stack.len() - 1;
+                            Some(match stack.get_with_type(top, ValueType::Interface) {
+                                GosValue::Interface(i) => i.borrow().meta,
+                                GosValue::Named(n) => n.1,
+                                GosValue::Nil(m) => m,
+                                _ => unreachable!(),
+                            })
+                        };
+                        let val = match stack.pop_interface() {
+                            Some(iface) => match iface.borrow().underlying() {
+                                IfaceUnderlying::Gos(v, _) => v.copy_semantic(gcv),
+                                _ => GosValue::new_nil(),
+                            },
+                            None => GosValue::new_nil(),
+                        };
+                        let meta = val.get_meta(objs, stack);
+                        let want = *consts[inst.imm() as usize].as_meta();
+                        let iface_fields = match want.get_underlying(&objs.metas) {
+                            GosMetadata::NonPtr(k, MetaCategory::Default) => match &objs.metas[k] {
+                                MetadataType::Interface(fields) => Some(fields),
+                                _ => None,
+                            },
+                            _ => None,
+                        };
+                        let ok = match iface_fields {
+                            // a nil interface has no dynamic type to implement any interface
+                            Some(_) if meta == GosMetadata::Untyped => {
+                                stack.push(want.zero_val(&objs.metas, gcv));
+                                false
+                            }
+                            // asserting to an interface type checks the method set at runtime
+                            Some(fields) => match vm_util::iface_methods(&meta, fields, objs) {
+                                Some(funcs) => {
+                                    let v = vm_util::new_iface_value(
+                                        want,
+                                        val,
+                                        Some(funcs),
+                                        &objs.metas,
+                                    );
+                                    stack.push(v);
+                                    true
+                                }
+                                None => {
+                                    stack.push(want.zero_val(&objs.metas, gcv));
+                                    false
+                                }
+                            },
+                            None => {
+                                let ok = want.semantic_eq(&meta, &objs.metas);
+                                if ok {
+                                    stack.push(val.unwrap_named_basic());
+                                } else {
+                                    stack.push(want.zero_val(&objs.metas, gcv));
+                                }
+                                ok
+                            }
+                        };
+                        match iface_meta {
+                            Some(iface_meta) => {
+                                if !ok {
+                                    let msg =
+                                        vm_util::type_assert_msg(&iface_meta, &meta, &want, objs);
+                                    go_panic_str!(panic, metadata, msg, frame, frame_height, code);
+                                }
+                            }
+                            None => stack.push_bool(ok),
                         }
                     }
                     Opcode::TYPE => {
-                        let val = match stack.pop_interface().borrow().underlying() {
-                            IfaceUnderlying::Gos(v, _) => v.copy_semantic(gcv),
-                            _ => GosValue::new_nil(),
+                        let val = match stack.pop_interface() {
+                            Some(iface) => match iface.borrow().underlying() {
+                                IfaceUnderlying::Gos(v, _) => v.copy_semantic(gcv),
+                                _ => GosValue::new_nil(),
+                            },
+                            None => GosValue::new_nil(),
                         };
                         stack.push(GosValue::Metadata(val.get_meta(objs, stack)));
-                        if inst.t2_as_index() > 0 {
-                            let index = inst.imm();
-                            let s_index = Stack::offset(stack_base, index);
-                            stack.set(s_index, val);
-                        }
                     }
                     Opcode::IMPORT => {
                         let pkey = pkgs[inst.imm() as usize];
                         stack.push(GosValue::Bool(!objs.packages[pkey].inited()));
                     }
+                    Opcode::INIT_PKG => {
+                        let pkey = pkgs[inst.imm() as usize];
+                        let pkg = &objs.packages[pkey];
+                        let count = pkg.var_count();
+                        debug_assert!(stack.len() == stack_base + count);
+                        // the var values left on the stack are for pkg members
+                        stack.init_pkg_vars(pkg, count);
+                    }
                     Opcode::SLICE | Opcode::SLICE_FULL => {
                         let max = if inst_op == Opcode::SLICE_FULL {
-                            stack.pop_int()
+                            Some(stack.pop_int())
                         } else {
-                            -1
+                            None
+                        };
+                        // FlagA means the high index is omitted
+                        let end = if inst.t1() == ValueType::FlagA {
+                            None
+                        } else {
+                            Some(stack.pop_int())
                         };
-                        let end = stack.pop_int();
                         let begin = stack.pop_int();
                         let target = stack.pop_with_type(inst.t0());
+                        // slicing a named slice gives a value of the same type
+                        let (target, named) = match target {
+                            GosValue::Named(n) => (n.0, Some(n.1)),
+                            _ => (target, None),
+                        };
                         let result = match &target {
-                            GosValue::Slice(sl) => GosValue::Slice(Rc::new((
-                                sl.0.slice(begin, end, max),
-                                Cell::new(0),
-                            ))),
-                            GosValue::Str(s) => GosValue::Str(Rc::new(s.slice(begin, end))),
+                            GosValue::Slice(sl) => sl.0.slice(begin, end, max).map(|x| {
+                                let v = GosValue::Slice(Rc::new((x, Cell::new(0))));
+                                match named {
+                                    Some(meta) => GosValue::Named(Box::new((v, meta))),
+                                    None => v,
+                                }
+                            }),
+                            GosValue::Str(s) => {
+                                s.slice(begin, end).map(|x| GosValue::Str(Rc::new(x)))
+                            }
                             GosValue::Array(_) => {
-                                GosValue::slice_with_array(&target, begin, end, gcv)
+                                GosValue::slice_with_array(&target, begin, end, max, gcv)
                             }
                             _ => unreachable!(),
                         };
-                        stack.push(result);
+                        match result {
+                            Ok(v) => stack.push(v),
+                            Err(e) => {
+                                go_panic_str!(panic, &objs.metadata, e, frame, frame_height, code);
+                            }
+                        }
                     }
                     Opcode::LITERAL => {
                         let index = inst.imm();
@@ -1129,21 +1499,30 @@ impl<'a> Fiber<'a> {
                                 if let Some(uvs) = &mut val.uvs {
                                     drop(frame);
                                     for (_, uv) in uvs.iter_mut() {
-                                        let r: &mut UpValueState = &mut uv.inner.borrow_mut();
-                                        if let UpValueState::Open(d) = r {
+                                        let mut shared = None;
+                                        if let UpValueState::Open(d) = &mut *uv.inner.borrow_mut() {
                                             // get frame index, and add_referred_by
                                             for i in 1..frame_height {
                                                 let index = frame_height - i;
                                                 if self.frames[index].func() == d.func {
                                                     let upframe = &mut self.frames[index];
-                                                    d.stack = Rc::downgrade(&self.stack);
-                                                    d.stack_base = upframe.stack_base as OpIndex;
-                                                    upframe.add_referred_by(d.index, d.typ, uv);
+                                                    // share the UpValue if the var is already
+                                                    // referred to, so that pointers to it are equal
+                                                    shared = upframe.find_referred_by(d.index);
+                                                    if shared.is_none() {
+                                                        d.stack = Rc::downgrade(&self.stack);
+                                                        d.stack_base =
+                                                            upframe.stack_base as OpIndex;
+                                                        upframe.add_referred_by(d.index, d.typ, uv);
+                                                    }
                                                     // if not found, the upvalue is already closed, nothing to be done
                                                     break;
                                                 }
                                             }
                                         }
+                                        if let Some(shared) = shared {
+                                            *uv = shared;
+                                        }
                                         //dbg!(&desc, &upframe);
                                     }
                                     frame = self.frames.last_mut().unwrap();
@@ -1155,7 +1534,7 @@ impl<'a> Fiber<'a> {
                                 let (key, mc) = umd.unwrap_non_ptr();
                                 let count = stack.pop_int32();
                                 let val = match &objs.metas[key] {
-                                    MetadataType::SliceOrArray(asm, _) => {
+                                    MetadataType::SliceOrArray(asm, size) => {
                                         let elem_type = asm.get_value_type(&objs.metas);
                                         let zero_val = asm.zero_val(&objs.metas, gcv);
                                         let mut val = vec![];
@@ -1173,7 +1552,7 @@ impl<'a> Fiber<'a> {
                                                 val.push(elem);
                                             } else if gap > 0 {
                                                 for _ in 0..gap {
-                                                    val.push(zero_val.clone());
+                                                    val.push(zero_val.copy_semantic(gcv));
                                                 }
                                                 val.push(elem);
                                             } else {
@@ -1185,6 +1564,10 @@ impl<'a> Fiber<'a> {
                                                 GosValue::slice_with_val(val, *md, gcv)
                                             }
                                             MetaCategory::Array => {
+                                                // the elements not in the literal are zero
+                                                while val.len() < *size {
+                                                    val.push(zero_val.copy_semantic(gcv));
+                                                }
                                                 GosValue::array_with_val(val, *md, gcv)
                                             }
                                             _ => unreachable!(),
@@ -1279,36 +1662,126 @@ impl<'a> Fiber<'a> {
                         stack.pop_discard();
                         stack.push(val);
                     }
+                    // the lengths of arrays are constants, they never get here
                     Opcode::LEN => {
-                        let l = match &stack.pop_with_type(inst.t0()) {
+                        let target = stack.pop_with_type(inst.t0());
+                        let target = match target {
+                            GosValue::Named(n) => n.0,
+                            _ => target,
+                        };
+                        let l = match &target {
                             GosValue::Slice(slice) => slice.0.len(),
                             GosValue::Map(map) => map.0.len(),
                             GosValue::Str(sval) => sval.len(),
                             GosValue::Channel(chan) => chan.len(),
+                            // nil channels
+                            GosValue::Nil(_) => 0,
                             _ => unreachable!(),
                         };
                         stack.push(GosValue::Int(l as isize));
                     }
                     Opcode::CAP => {
-                        let l = match &stack.pop_with_type(inst.t0()) {
+                        let target = stack.pop_with_type(inst.t0());
+                        let target = match target {
+                            GosValue::Named(n) => n.0,
+                            _ => target,
+                        };
+                        let l = match &target {
                             GosValue::Slice(slice) => slice.0.cap(),
                             GosValue::Channel(chan) => chan.cap(),
+                            GosValue::Nil(_) => 0,
                             _ => unreachable!(),
                         };
                         stack.push(GosValue::Int(l as isize));
                     }
                     Opcode::APPEND => {
                         let index = Stack::offset(stack.len(), inst.imm() - 2);
-                        let a = stack.get_with_type(index, ValueType::Slice);
+                        // the result is of the same named type as the first argument
+                        let (a, named) = match stack.get_with_type(index, ValueType::Slice) {
+                            GosValue::Named(n) => (n.0, Some(n.1)),
+                            a => (a, None),
+                        };
                         let vala = a.as_slice();
                         if inst.t1() != ValueType::Zero {
                             stack.pack_variadic(index + 1, vala.0.meta, inst.t1(), gcv);
                         }
-                        let b = stack.pop_with_type(ValueType::Slice);
+                        let b = match stack.pop_with_type(ValueType::Slice) {
+                            GosValue::Named(n) => n.0,
+                            b => b,
+                        };
                         let valb = b.as_slice();
-                        vala.0
-                            .borrow_data_mut()
-                            .append(&mut valb.0.borrow_data().clone());
+                        // append returns a new slice header, the original one is untouched
+                        let mut result = vala.0.clone();
+                        if !valb.0.is_nil() {
+                            let mut vals: GosVec = valb.0.borrow().iter().cloned().collect();
+                            result.append(&mut vals, gcv);
+                        }
+                        let result = GosValue::Slice(Rc::new((result, Cell::new(0))));
+                        let result = match named {
+                            Some(meta) => GosValue::Named(Box::new((result, meta))),
+                            None => result,
+                        };
+                        stack.set(index, result);
+                    }
+                    Opcode::COPY => {
+                        let unwrap = |v: GosValue| match v {
+                            GosValue::Named(n) => n.0,
+                            _ => v,
+                        };
+                        let src = unwrap(stack.pop_with_type(inst.t1()));
+                        let dst = unwrap(stack.pop_with_type(inst.t0()));
+                        let count = match (&dst, &src) {
+                            (GosValue::Slice(d), GosValue::Slice(s)) => d.0.copy_from(&s.0, gcv),
+                            (GosValue::Slice(d), GosValue::Str(s)) => {
+                                let count = d.0.len().min(s.len());
+                                for (i, b) in s.as_str().bytes().take(count).enumerate() {
+                                    d.0.set(i, GosValue::Uint8(b));
+                                }
+                                count
+                            }
+                            // either one is nil
+                            _ => 0,
+                        };
+                        stack.push(GosValue::Int(count as isize));
+                    }
+                    Opcode::CLEAR => {
+                        let target = stack.pop_with_type(inst.t0());
+                        let target = match target {
+                            GosValue::Named(n) => n.0,
+                            _ => target,
+                        };
+                        match &target {
+                            GosValue::Map(m) => m.0.clear(),
+                            GosValue::Slice(s) => {
+                                let meta = s.0.meta.get_underlying(&objs.metas);
+                                let elem = match &objs.metas[meta.as_non_ptr()] {
+                                    MetadataType::SliceOrArray(m, _) => *m,
+                                    _ => unreachable!(),
+                                };
+                                s.0.clear(&zero_val!(elem, objs, gcv), gcv);
+                            }
+                            GosValue::Nil(_) => {}
+                            _ => {
+                                internal_error!("not a map or slice", inst, frame, objs, ctx.fs);
+                            }
+                        }
+                    }
+                    Opcode::DELETE => {
+                        let key = stack.pop_with_type(inst.t1());
+                        let target = stack.pop_with_type(inst.t0());
+                        let target = match target {
+                            GosValue::Named(n) => n.0,
+                            _ => target,
+                        };
+                        match &target {
+                            GosValue::Map(m) => {
+                                m.0.delete(&key);
+                            }
+                            GosValue::Nil(_) => {}
+                            _ => {
+                                internal_error!("not a map", inst, frame, objs, ctx.fs);
+                            }
+                        }
                     }
                     Opcode::CLOSE => {
                         let chan = stack.pop_with_type(ValueType::Channel);
@@ -1316,17 +1789,58 @@ impl<'a> Fiber<'a> {
                     }
                     Opcode::PANIC => {
                         let val = stack.pop_rc();
-                        go_panic!(panic, val, frame, code);
+                        // since Go 1.21 panic(nil) panics with a *runtime.PanicNilError,
+                        // so that recover() can tell it from no panic at all
+                        let is_nil = match &val {
+                            GosValue::Nil(_) => true,
+                            GosValue::Named(n) => n.0.is_nil(),
+                            GosValue::Interface(i) => i.borrow().is_nil(),
+                            _ => false,
+                        };
+                        if ctx.panic_nil_error && is_nil {
+                            let msg = vm_util::PANIC_NIL_MSG.to_string();
+                            go_panic_str!(panic, metadata, msg, frame, frame_height, code);
+                        } else {
+                            go_panic!(panic, val, frame, frame_height, code);
+                        }
                     }
                     Opcode::RECOVER => {
-                        let p = panic.take();
-                        let val = p.map_or(GosValue::new_nil(), |x| x.msg);
+                        // only a deferred call run by the panicking frame can recover
+                        let recoverable = panic
+                            .as_ref()
+                            .map_or(false, |p| frame_height == p.unwind_height + 1);
+                        let val = if recoverable {
+                            let (msg, previous) = panic.take().unwrap().recover();
+                            panic = previous;
+                            msg
+                        } else {
+                            GosValue::new_nil()
+                        };
                         stack.push(val);
                     }
+                    Opcode::PRINT | Opcode::PRINTLN => {
+                        let meta = stack.pop_with_type(ValueType::Metadata);
+                        let ptypes = &objs.metas[meta.as_meta().as_non_ptr()]
+                            .as_signature()
+                            .params_type;
+                        let params = stack.pop_with_type_n(ptypes);
+                        let mut s = String::new();
+                        for (i, p) in params.iter().enumerate() {
+                            if i > 0 && inst_op == Opcode::PRINTLN {
+                                s.push(' ');
+                            }
+                            s.push_str(&p.to_string());
+                        }
+                        if inst_op == Opcode::PRINTLN {
+                            s.push('\n');
+                        }
+                        // like in Go, print and println write to standard error
+                        ctx.outputs.print_err(&s);
+                    }
                     Opcode::ASSERT => {
                         if !stack.pop_bool() {
                             let msg = "Opcode::ASSERT: not true!".to_string();
-                            go_panic_str!(panic, metadata, msg, frame, code);
+                            go_panic_str!(panic, metadata, msg, frame, frame_height, code);
                         }
                     }
                     Opcode::FFI => {
@@ -1340,19 +1854,18 @@ impl<'a> Fiber<'a> {
                             .as_signature()
                             .params_type[2..];
                         let params = stack.pop_with_type_n(ptypes);
-                        let v = match self.context.ffi_factory.create_by_name(name_str, params) {
-                            Ok(v) => {
-                                let meta = itype.as_meta().get_underlying(&objs.metas).clone();
-                                let info = objs.metas[meta.as_non_ptr()]
-                                    .as_interface()
-                                    .iface_methods_info();
-                                GosValue::new_iface(
-                                    meta,
-                                    IfaceUnderlying::Ffi(UnderlyingFfi::new(v, info)),
-                                )
-                            }
+                        let ffi = self.context.ffi_factory.create_by_name(name_str, params);
+                        let v = match ffi.and_then(|v| {
+                            let meta = itype.as_meta().get_underlying(&objs.metas).clone();
+                            let info = objs.metas[meta.as_non_ptr()]
+                                .as_interface()
+                                .iface_methods_info();
+                            let underlying = UnderlyingFfi::new(v, info, &objs.metas)?;
+                            Ok(GosValue::new_iface(meta, IfaceUnderlying::Ffi(underlying)))
+                        }) {
+                            Ok(v) => v,
                             Err(e) => {
-                                go_panic_str!(panic, metadata, e, frame, code);
+                                go_panic_str!(panic, metadata, e, frame, frame_height, code);
                                 continue;
                             }
                         };
@@ -1360,8 +1873,7 @@ impl<'a> Fiber<'a> {
                         stack.push(v);
                     }
                     _ => {
-                        dbg!(inst_op);
-                        unimplemented!();
+                        internal_error!("unsupported opcode", inst, frame, objs, ctx.fs);
                     }
                 };
                 //dbg!(inst_op, stack.len());
@@ -1369,15 +1881,51 @@ impl<'a> Fiber<'a> {
             match result {
                 Result::End => {
                     if let Some(p) = panic {
-                        println!("panic: {}", p.msg);
-                        if let Some(files) = self.context.fs {
-                            for (fkey, pc) in p.call_stack.iter() {
+                        let position = |fkey: FunctionKey, pc: usize| {
+                            let func = &objs.functions[fkey];
+                            match self.context.fs.and_then(|fs| func.position(pc, fs)) {
+                                Some(pos) => pos.to_string(),
+                                None => "<no debug info available>".to_string(),
+                            }
+                        };
+                        let call_stack = match self.context.fs {
+                            Some(_) => p
+                                .call_stack
+                                .iter()
+                                .map(|(fkey, pc)| position(*fkey, *pc))
+                                .collect(),
+                            None => vec![],
+                        };
+                        // the entry function, which is in no package, is left out
+                        let backtrace = p
+                            .call_stack
+                            .iter()
+                            .filter_map(|(fkey, pc)| {
                                 let func = &objs.functions[*fkey];
-                                if let Some(p) = func.pos()[*pc] {
-                                    println!("{}", files.position(p));
-                                } else {
-                                    println!("<no debug info available>");
-                                }
+                                let pkg = objs.packages.get(func.package)?.name().to_string();
+                                Some((pkg, func.name.clone(), position(*fkey, *pc)))
+                            })
+                            .collect();
+                        let info = PanicInfo {
+                            value: p.msg.clone(),
+                            aborted: p.aborted(),
+                            message: p.msg.to_string(),
+                            call_stack: call_stack,
+                            backtrace: backtrace,
+                            suppress: Cell::new(false),
+                        };
+                        if let Some(hook) = self.context.panic_hook {
+                            hook(&info);
+                        }
+                        if !info.is_default_output_suppressed() {
+                            let outputs = self.context.outputs;
+                            // like Go, the aborted panics come first
+                            for v in info.aborted.iter() {
+                                outputs.print_err(&format!("panic: {}\n\t", v));
+                            }
+                            outputs.print_err(&format!("panic: {}\n", info.message));
+                            for pos in info.call_stack.iter() {
+                                outputs.print_err(&format!("{}\n", pos));
                             }
                         }
 
@@ -1394,6 +1942,9 @@ impl<'a> Fiber<'a> {
                 }
                 Result::Continue => {
                     drop(stack_mut_ref);
+                    if gcv.should_collect() {
+                        gc(gcv);
+                    }
                     future::yield_now().await;
                     restore_stack_ref!(self, stack, stack_mut_ref);
                 }
@@ -1401,30 +1952,60 @@ impl<'a> Fiber<'a> {
         } //loop
 
         stack.clear_rc_garbage();
-        gc(gcv);
+        if gcv.config().auto {
+            gc(gcv);
+        }
     }
 }
 
 pub struct GosVM<'a> {
     code: ByteCode,
-    gcv: GcoVec,
+    gcv: &'a GcoVec,
     ffi: &'a FfiFactory,
     fs: Option<&'a FileSet>,
+    panic_hook: Option<&'a PanicHook<'a>>,
+    panic_nil_error: bool,
+    outputs: Outputs,
 }
 
 impl<'a> GosVM<'a> {
-    pub fn new(bc: ByteCode, ffi: &'a FfiFactory, fs: Option<&'a FileSet>) -> GosVM<'a> {
+    /// `panic_nil_error` selects the Go 1.21 behavior of `panic(nil)`, which
+    /// panics with a runtime error instead of nil. The objects are tracked in
+    /// `gcv`, so that the cycles left by a run can be collected after it.
+    pub fn new(
+        bc: ByteCode,
+        ffi: &'a FfiFactory,
+        fs: Option<&'a FileSet>,
+        panic_hook: Option<&'a PanicHook<'a>>,
+        panic_nil_error: bool,
+        outputs: Outputs,
+        gcv: &'a GcoVec,
+    ) -> GosVM<'a> {
         GosVM {
             code: bc,
-            gcv: GcoVec::new(),
+            gcv: gcv,
             ffi: ffi,
             fs: fs,
+            panic_hook: panic_hook,
+            panic_nil_error: panic_nil_error,
+            outputs: outputs,
         }
     }
 
+    /// Runs the program to the end, a Rust panic in the VM or in an extension
+    /// is raised again here instead of being lost in the executor
     pub fn run(&self) {
         let exec = Rc::new(LocalExecutor::new());
-        let ctx = Context::new(exec.clone(), &self.code, &self.gcv, self.ffi, self.fs);
+        let ctx = Context::new(
+            exec.clone(),
+            &self.code,
+            self.gcv,
+            self.ffi,
+            self.fs,
+            self.panic_hook,
+            self.panic_nil_error,
+            &self.outputs,
+        );
         let entry = ctx.new_entry_frame(self.code.entry);
         ctx.spawn_fiber(Stack::new(), entry);
 
@@ -1433,10 +2014,48 @@ impl<'a> GosVM<'a> {
                 if !exec.try_tick() {
                     break;
                 }
+                // a Rust panic in any fiber stops the VM and goes on to the caller
+                if let Some(e) = ctx.crash.borrow_mut().take() {
+                    panic::resume_unwind(e);
+                }
             }
         });
     }
 }
 
 #[cfg(test)]
-mod test {}
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_internal_error() {
+        // bytecode the compiler never generates: loading a field from an int
+        let mut objs = VMObjects::new();
+        let gcv = GcoVec::new();
+        let meta = objs.metadata.default_sig;
+        let f = GosValue::new_function(null_key!(), meta, &mut objs, &gcv, FuncFlag::Default);
+        let fkey = *f.as_function();
+        let func = &mut objs.functions[fkey];
+        func.emit_code_with_type_imm(Opcode::PUSH_IMM, ValueType::Int, 1, None);
+        func.emit_code_with_type_imm(Opcode::LOAD_STRUCT_FIELD, ValueType::Int, 0, None);
+        func.emit_code(Opcode::RETURN, None);
+        let bc = ByteCode {
+            objects: Box::pin(objs),
+            packages: vec![],
+            ifaces: vec![],
+            entry: fkey,
+        };
+
+        // it is not a Go panic, so the hook is not called
+        let hooked = Cell::new(false);
+        let hook = |_: &PanicInfo| hooked.set(true);
+        let ffi = FfiFactory::new();
+        let vm = GosVM::new(bc, &ffi, None, Some(&hook), true, Outputs::default(), &gcv);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| vm.run()));
+
+        let err = result.unwrap_err();
+        let expected = "internal error: not a struct (opcode: LOAD_STRUCT_FIELD, position: ";
+        assert!(err.downcast_ref::<String>().unwrap().starts_with(expected));
+        assert!(!hooked.get());
+    }
+}
@@ -2,9 +2,24 @@
 //use super::opcode::OpIndex;
 use super::gc::GcoVec;
 use super::instruction::*;
-use super::objects::MetadataObjs;
+use super::metadata::{Fields, GosMetadata, MetaCategory, MetadataType};
+use super::objects::{FunctionKey, IfaceUnderlying, MetadataObjs};
 use super::stack::Stack;
 use super::value::{GosValue, RtEmptyResult, RtValueResult, VMObjects};
+use goscript_parser::FileSet;
+use std::rc::Rc;
+use std::result;
+
+pub const NIL_DEREF_MSG: &str = "runtime error: invalid memory address or nil pointer dereference";
+pub const PANIC_NIL_MSG: &str = "panic called with nil argument (see issue 25448)";
+
+/// The message of indexing out of bounds, as Go's runtime writes it
+pub fn index_out_of_range(index: usize, len: usize) -> String {
+    format!(
+        "runtime error: index out of range [{}] with length {}",
+        index as isize, len
+    )
+}
 
 // restore stack_ref after drop to allow code in block call yield
 macro_rules! restore_stack_ref {
@@ -15,8 +30,8 @@ macro_rules! restore_stack_ref {
 }
 
 macro_rules! go_panic {
-    ($panic:ident, $msg:expr, $frame:ident, $code:ident) => {
-        let mut data = PanicData::new($msg);
+    ($panic:ident, $msg:expr, $frame:ident, $frame_height:ident, $code:ident) => {
+        let mut data = PanicData::new($msg, $frame_height, $panic.take());
         data.call_stack.push(($frame.func(), $frame.pc - 1));
         $panic = Some(data);
         $frame.pc = $code.len() - 1;
@@ -24,16 +39,34 @@ macro_rules! go_panic {
 }
 
 macro_rules! go_panic_str {
-    ($panic:ident, $mdata:expr, $msg:expr, $frame:ident, $code:ident) => {
+    ($panic:ident, $mdata:expr, $msg:expr, $frame:ident, $frame_height:ident, $code:ident) => {
         let str_val = GosValue::new_str($msg);
         let iface = GosValue::new_empty_iface($mdata, str_val);
-        let mut data = PanicData::new(iface);
+        let mut data = PanicData::new(iface, $frame_height, $panic.take());
         data.call_stack.push(($frame.func(), $frame.pc - 1));
         $panic = Some(data);
         $frame.pc = $code.len() - 1;
     };
 }
 
+/// For the states well-formed bytecode never gets into, i.e. bugs in the compiler
+/// or the VM rather than in the Go program.
+/// The program can't recover from them: the VM panics in Rust with the context,
+/// which `GosVM::run` passes on to the host.
+macro_rules! internal_error {
+    ($desc:expr, $inst:ident, $frame:ident, $objs:expr, $fs:expr $(,)?) => {{
+        let msg = vm_util::internal_error_msg(
+            $desc,
+            $inst.op(),
+            $frame.func(),
+            $frame.pc - 1,
+            $objs,
+            $fs,
+        );
+        panic!("{}", msg)
+    }};
+}
+
 macro_rules! read_imm_key {
     ($code:ident, $frame:ident, $objs:ident) => {{
         let inst = $code[$frame.pc];
@@ -166,6 +199,26 @@ macro_rules! unwrap_recv_val {
     };
 }
 
+pub fn internal_error_msg(
+    desc: &str,
+    op: Opcode,
+    func: FunctionKey,
+    pc: usize,
+    objs: &VMObjects,
+    fs: Option<&FileSet>,
+) -> String {
+    let pos = match (objs.functions[func].pos().get(pc), fs) {
+        (Some(Some(p)), Some(files)) => files.position(*p).to_string(),
+        _ => "<no debug info available>".to_string(),
+    };
+    format!(
+        "internal error: {} (opcode: {}, position: {})",
+        desc,
+        op.property().0,
+        pos
+    )
+}
+
 #[inline]
 pub fn char_from_u32(u: u32) -> char {
     unsafe { char::from_u32_unchecked(u) }
@@ -176,6 +229,100 @@ pub fn char_from_i32(i: i32) -> char {
     unsafe { char::from_u32_unchecked(i as u32) }
 }
 
+/// Creates a value of the interface type `iface`, which could be a named type.
+#[inline]
+pub fn new_iface_value(
+    iface: GosMetadata,
+    under: GosValue,
+    funcs: Option<Rc<Vec<FunctionKey>>>,
+    metas: &MetadataObjs,
+) -> GosValue {
+    match &metas[iface.as_non_ptr()] {
+        MetadataType::Named(_, md) => GosValue::Named(Box::new((
+            GosValue::new_iface(*md, IfaceUnderlying::Gos(under, funcs)),
+            iface,
+        ))),
+        MetadataType::Interface(_) => GosValue::new_iface(iface, IfaceUnderlying::Gos(under, funcs)),
+        _ => unreachable!(),
+    }
+}
+
+/// Looks up the methods of the type `meta` in the order of the interface methods
+/// `fields`, returns None if the type does not implement the interface.
+pub fn iface_methods(
+    meta: &GosMetadata,
+    fields: &Fields,
+    objs: &VMObjects,
+) -> Option<Rc<Vec<FunctionKey>>> {
+    lookup_iface_methods(meta, fields, objs).ok()
+}
+
+/// Like iface_methods, but fails with the name of the first interface method
+/// the type does not have.
+fn lookup_iface_methods(
+    meta: &GosMetadata,
+    fields: &Fields,
+    objs: &VMObjects,
+) -> result::Result<Rc<Vec<FunctionKey>>, String> {
+    let (mtype, is_ptr) = match meta {
+        GosMetadata::NonPtr(k, MetaCategory::Default) => (Some(&objs.metas[*k]), false),
+        GosMetadata::Ptr1(k, MetaCategory::Default) => (Some(&objs.metas[*k]), true),
+        _ => (None, false),
+    };
+    let methods = match mtype {
+        Some(MetadataType::Named(m, _)) => Some(m),
+        _ => None,
+    };
+    let mut funcs = Vec::with_capacity(fields.fields.len());
+    for (name, sig) in fields.iface_methods_info().iter() {
+        let func = (|| {
+            let index = *methods?.mapping.get(name)?;
+            let desc = methods?.members[index as usize].borrow();
+            if desc.pointer_recv && !is_ptr {
+                return None;
+            }
+            let func = desc.func?;
+            let fsig = objs.metas[objs.functions[func].meta.as_non_ptr()].as_signature();
+            let isig = objs.metas[sig.as_non_ptr()].as_signature();
+            if !fsig.semantic_eq_no_recv(isig, &objs.metas) {
+                return None;
+            }
+            Some(func)
+        })();
+        funcs.push(func.ok_or_else(|| name.clone())?);
+    }
+    Ok(Rc::new(funcs))
+}
+
+/// The message of the panic when the single-result form of the type assertion
+/// `x.(T)` fails, `iface` is the static type of x and `meta` its dynamic type,
+/// which is untyped when x is nil.
+pub fn type_assert_msg(
+    iface: &GosMetadata,
+    meta: &GosMetadata,
+    want: &GosMetadata,
+    objs: &VMObjects,
+) -> String {
+    let metas = &objs.metas;
+    let (si, sw) = (iface.type_string(metas), want.type_string(metas));
+    if *meta == GosMetadata::Untyped {
+        return format!("interface conversion: {} is nil, not {}", si, sw);
+    }
+    let sv = meta.type_string(metas);
+    match &metas[want.get_underlying(metas).as_non_ptr()] {
+        MetadataType::Interface(fields) => {
+            let missing = lookup_iface_methods(meta, fields, objs).err();
+            format!(
+                "interface conversion: {} is not {}: missing method {}",
+                sv,
+                sw,
+                missing.unwrap_or_default()
+            )
+        }
+        _ => format!("interface conversion: {} is {}, not {}", si, sv, sw),
+    }
+}
+
 #[inline(always)]
 pub fn load_index(val: &GosValue, ind: &GosValue) -> RtValueResult {
     match val {
@@ -185,20 +332,20 @@ pub fn load_index(val: &GosValue, ind: &GosValue) -> RtValueResult {
             slice
                 .0
                 .get(index)
-                .map_or_else(|| Err(format!("index {} out of range", index)), |x| Ok(x))
+                .map_or_else(|| Err(index_out_of_range(index, slice.0.len())), |x| Ok(x))
         }
         GosValue::Str(s) => {
             let index = ind.as_index();
             s.get_byte(index).map_or_else(
-                || Err(format!("index {} out of range", index)),
-                |x| Ok(GosValue::Int((*x).into())),
+                || Err(index_out_of_range(index, s.len())),
+                |x| Ok(GosValue::Uint8(*x)),
             )
         }
         GosValue::Array(arr) => {
             let index = ind.as_index();
             arr.0
                 .get(index)
-                .map_or_else(|| Err(format!("index {} out of range", index)), |x| Ok(x))
+                .map_or_else(|| Err(index_out_of_range(index, arr.0.len())), |x| Ok(x))
         }
         _ => unreachable!(),
     }
@@ -210,19 +357,19 @@ pub fn load_index_int(val: &GosValue, i: usize) -> RtValueResult {
         GosValue::Slice(slice) => slice
             .0
             .get(i)
-            .map_or_else(|| Err(format!("index {} out of range", i)), |x| Ok(x)),
+            .map_or_else(|| Err(index_out_of_range(i, slice.0.len())), |x| Ok(x)),
         GosValue::Map(map) => {
             let ind = GosValue::Int(i as isize);
             Ok(map.0.get(&ind).clone())
         }
         GosValue::Str(s) => s.get_byte(i).map_or_else(
-            || Err(format!("index {} out of range", i)),
-            |x| Ok(GosValue::Int((*x).into())),
+            || Err(index_out_of_range(i, s.len())),
+            |x| Ok(GosValue::Uint8(*x)),
         ),
         GosValue::Array(arr) => arr
             .0
             .get(i)
-            .map_or_else(|| Err(format!("index {} out of range", i)), |x| Ok(x)),
+            .map_or_else(|| Err(index_out_of_range(i, arr.0.len())), |x| Ok(x)),
         GosValue::Named(n) => load_index_int(&n.0, i),
         _ => {
             dbg!(val);
@@ -270,6 +417,7 @@ pub fn store_index(
             let target_cell = borrowed.get(&key).unwrap();
             stack.store_val(&mut target_cell.borrow_mut(), r_index, t, gcos);
         }
+        GosValue::Named(n) => store_index(stack, &n.0, key, r_index, t, gcos),
         _ => unreachable!(),
     }
 }
@@ -311,6 +459,7 @@ pub fn store_index_int(
                 Ok(())
             }
         }
+        GosValue::Named(n) => store_index_int(stack, &n.0, i, r_index, t, gcos),
         GosValue::Nil(_) => err,
         _ => {
             dbg!(target);
@@ -350,10 +499,83 @@ pub fn store_field(
 
 #[inline]
 pub fn push_index_comma_ok(stack: &mut Stack, map: &GosValue, index: &GosValue) {
-    let (v, b) = match map.as_map().0.try_get(index) {
+    let map = &map.as_map().0;
+    let (v, b) = match map.try_get(index) {
         Some(v) => (v, true),
-        None => (GosValue::new_nil(), false),
+        // a missing key reads as the zero value, like the single-valued form
+        None => (map.get(index), false),
     };
     stack.push(v);
     stack.push_bool(b);
 }
+
+/// comparing two interfaces holding values of the same uncomparable type panics,
+/// while values of different types are simply not equal
+pub fn check_iface_comparable(
+    a: &GosValue,
+    b: &GosValue,
+    objs: &VMObjects,
+    stack: &Stack,
+) -> RtEmptyResult {
+    if let (GosValue::Interface(_), GosValue::Interface(_)) = (a, b) {
+        if let (Some(x), Some(y)) = (a.iface_underlying(), b.iface_underlying()) {
+            if x.is_comparable() {
+                return Ok(());
+            }
+            let (mx, my) = (x.get_meta(objs, stack), y.get_meta(objs, stack));
+            let (sx, sy) = (mx.type_string(&objs.metas), my.type_string(&objs.metas));
+            // the metadata of unnamed types are not unique, compare them by structure
+            let same = match (&x, &y) {
+                (GosValue::Named(_), _) | (_, GosValue::Named(_)) => mx == my,
+                _ => sx == sy,
+            };
+            if same {
+                return Err(format!("runtime error: comparing uncomparable type {}", sx));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `==` for values of the same type that are not copyable. An array or a struct
+/// is equal to itself without comparing the elements, unless they may be floats,
+/// as a NaN is unequal to itself.
+#[inline]
+pub fn compare_eql(a: &GosValue, b: &GosValue, metas: &MetadataObjs) -> bool {
+    let (same, meta) = match (a, b) {
+        (GosValue::Array(x), GosValue::Array(y)) => (Rc::ptr_eq(&x.0.vec, &y.0.vec), x.0.meta),
+        (GosValue::Struct(x), GosValue::Struct(y)) => (Rc::ptr_eq(x, y), x.0.borrow().meta),
+        (GosValue::Named(x), GosValue::Named(y)) => {
+            return x.1 == y.1 && compare_eql(&x.0, &y.0, metas)
+        }
+        _ => return a == b,
+    };
+    (same && !meta.may_hold_float(metas)) || a == b
+}
+
+/// like `a == b`, except that nil pointers and arrays held by interfaces are only
+/// equal when they have the same dynamic type
+pub fn iface_eq(a: &GosValue, b: &GosValue, objs: &VMObjects) -> bool {
+    if let (GosValue::Interface(_), GosValue::Interface(_)) = (a, b) {
+        if let (Some(x), Some(y)) = (a.iface_underlying(), b.iface_underlying()) {
+            if let (GosValue::Array(ax), GosValue::Array(ay)) = (&x, &y) {
+                return ax.0.equals(&ay.0, &objs.metas);
+            }
+            if let (GosValue::Nil(mx), GosValue::Nil(my)) = (&x, &y) {
+                // named types have unique metadata, the unnamed ones are compared
+                // by structure
+                let named = |m: &GosMetadata| match m {
+                    GosMetadata::NonPtr(k, _) | GosMetadata::Ptr1(k, _) => {
+                        matches!(objs.metas[*k], MetadataType::Named(_, _))
+                    }
+                    _ => false,
+                };
+                return mx == my
+                    || (!named(mx)
+                        && !named(my)
+                        && mx.type_string(&objs.metas) == my.type_string(&objs.metas));
+            }
+        }
+    }
+    a == b
+}